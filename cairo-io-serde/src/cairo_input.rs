@@ -1,7 +1,7 @@
 use cainome_cairo_serde::ByteArray;
 use cairo_vm::Felt252;
-use serde_json::Value;
-use std::str::FromStr;
+use serde_json::{Map, Value};
+use std::{path::Path, str::FromStr};
 
 use crate::{
     schema::{Schema, SchemaType},
@@ -9,16 +9,81 @@ use crate::{
     FuncArg, FuncArgs,
 };
 
+/// Format of the program arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgsFormat {
+    Json,
+    Yaml,
+    /// One column per `Span<T>`/`Array<T>` field of the input schema, with a header row.
+    Csv,
+}
+
+impl FromStr for ArgsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ArgsFormat::Json),
+            "yaml" | "yml" => Ok(ArgsFormat::Yaml),
+            "csv" => Ok(ArgsFormat::Csv),
+            _ => Err(format!("{s} is not a valid args format")),
+        }
+    }
+}
+
+impl ArgsFormat {
+    /// Returns the format matching the extension of `path`, if known.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+    }
+
+    /// Guesses the format of `content`.
+    pub fn detect(content: &str) -> Self {
+        let trimmed = content.trim_start();
+        if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            return ArgsFormat::Json;
+        }
+        let header = trimmed.lines().next().unwrap_or_default();
+        if header.contains(',') && !header.contains(':') {
+            ArgsFormat::Csv
+        } else {
+            ArgsFormat::Yaml
+        }
+    }
+}
+
+/// Converts arguments in any supported format into the JSON value expected by the input schema.
+pub fn parse_args_value(content: &str, format: ArgsFormat, schema: &Schema) -> Result<Value, String> {
+    match format {
+        ArgsFormat::Json => {
+            serde_json::from_str(content).map_err(|e| format!("Failed to parse JSON: {}", e))
+        }
+        ArgsFormat::Yaml => {
+            serde_yaml::from_str(content).map_err(|e| format!("Failed to parse YAML: {}", e))
+        }
+        ArgsFormat::Csv => parse_csv(content, schema),
+    }
+}
+
+/// Parses arguments in any supported format into the program arguments.
+pub fn process_args(content: &str, format: ArgsFormat, schema: &Schema) -> Result<FuncArgs, String> {
+    let value = parse_args_value(content, format, schema)?;
+    process_value_args(&value, schema)
+}
+
 pub fn process_json_args(json_str: &str, schema: &Schema) -> Result<FuncArgs, String> {
-    let json: Value =
-        serde_json::from_str(json_str).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    process_args(json_str, ArgsFormat::Json, schema)
+}
 
+pub fn process_value_args(json: &Value, schema: &Schema) -> Result<FuncArgs, String> {
     if json.as_object().map_or(false, |obj| obj.is_empty()) {
         // Return default (empty) FuncArgs if JSON is empty
         return Ok(FuncArgs::default());
     }
 
-    let parsed = parse_schema(&json, &schema.cairo_input, schema)?;
+    let parsed = parse_schema(json, &schema.cairo_input, schema)?;
 
     Ok(FuncArgs(vec![FuncArg::Array(parsed)]))
 }
@@ -111,6 +176,90 @@ fn parse_value(value: &Value, ty: &SchemaType, schema: &Schema) -> Result<Vec<Fe
     }
 }
 
+// Builds the input object from CSV columns, each column being a `Span<T>` field of the input schema
+fn parse_csv(content: &str, schema: &Schema) -> Result<Value, String> {
+    let schema_def = schema
+        .schemas
+        .get(&schema.cairo_input)
+        .ok_or_else(|| format!("Schema {} not found in schema", schema.cairo_input))?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Failed to parse CSV: {}", e))?
+        .clone();
+
+    let mut columns = Vec::new();
+    for header in headers.iter() {
+        let field = schema_def
+            .fields
+            .iter()
+            .find(|field| field.name == header)
+            .ok_or_else(|| format!("CSV column {} is not a field of {}", header, schema.cairo_input))?;
+        match &field.ty {
+            SchemaType::Array { item_type } | SchemaType::Span { item_type } => {
+                match item_type.as_ref() {
+                    SchemaType::Primitive { name } => columns.push((header, name, Vec::new(), None)),
+                    _ => return Err(format!("CSV column {} must hold primitive values", header)),
+                }
+            }
+            _ => return Err(format!("CSV column {} must map to a Span or Array field", header)),
+        }
+    }
+
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Failed to parse CSV: {}", e))?;
+        let line = record.position().map_or(0, |position| position.line());
+        for ((header, ty, values, blank_line), cell) in columns.iter_mut().zip(record.iter()) {
+            // Columns may have different lengths, shorter ones are padded with empty cells
+            // after their last value
+            if cell.is_empty() {
+                blank_line.get_or_insert(line);
+                continue;
+            }
+            if let Some(blank_line) = blank_line {
+                return Err(format!(
+                    "Empty cell in column {} on line {}, followed by a value on line {}",
+                    header, blank_line, line
+                ));
+            }
+            values.push(parse_csv_cell(cell, ty.as_str()).map_err(|e| format!("{} in column {}", e, header))?);
+        }
+    }
+
+    let object = columns
+        .into_iter()
+        .map(|(header, _, values, _)| (header.to_string(), Value::Array(values)))
+        .collect::<Map<_, _>>();
+    Ok(Value::Object(object))
+}
+
+// Converts a CSV cell into the JSON value expected for the primitive type
+fn parse_csv_cell(cell: &str, ty: &str) -> Result<Value, String> {
+    match ty {
+        "u64" | "u32" | "u16" | "u8" => cell
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| format!("Expected unsigned integer for {}, got {}", ty, cell)),
+        "i64" | "i32" | "i16" | "i8" => cell
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| format!("Expected signed integer for {}, got {}", ty, cell)),
+        "F64" => cell
+            .parse::<f64>()
+            .map(Value::from)
+            .map_err(|_| format!("Expected float for {}, got {}", ty, cell)),
+        "bool" => cell
+            .parse::<bool>()
+            .map(Value::from)
+            .map_err(|_| format!("Expected boolean value, got {}", cell)),
+        "felt252" | "ByteArray" => Ok(Value::from(cell)),
+        _ => Err(format!("Unknown primitive type: {}", ty)),
+    }
+}
+
 fn parse_byte_array(string: &str) -> Result<Vec<Felt252>, String> {
     let byte_array =
         ByteArray::from_string(string).map_err(|e| format!("Error parsing ByteArray: {}", e))?;
@@ -611,4 +760,200 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Expected unsigned integer"));
     }
+
+    #[test]
+    fn test_function_schema() {
+        let input_schema = r#"
+        schemas:
+            Input:
+                fields:
+                    - request:
+                        type: Primitive
+                        name: u32
+            QuoteInput:
+                fields:
+                    - amount:
+                        type: Primitive
+                        name: u64
+                    - pair:
+                        type: Primitive
+                        name: felt252
+        cairo_input: Input
+        cairo_output: null
+        functions:
+            ::quote:
+                cairo_input: QuoteInput
+                cairo_output: null
+        "#;
+
+        let schema_file = create_temp_file_with_content(input_schema);
+        let schema = parse_schema_file(&schema_file.path().to_path_buf()).unwrap();
+
+        // Functions with their own section use it
+        let json = json!({"amount": 7, "pair": "0x1"});
+        let result = process_json_args(&json.to_string(), &schema.for_function("::quote")).unwrap();
+        assert_eq!(
+            result.0[0],
+            FuncArg::Array(vec![Felt252::from(7), Felt252::from(1)])
+        );
+
+        // Other functions fall back to the top-level input
+        let json = json!({"request": 42});
+        let result = process_json_args(&json.to_string(), &schema.for_function("::main")).unwrap();
+        assert_eq!(result.0[0], FuncArg::Array(vec![Felt252::from(42)]));
+    }
+
+    #[test]
+    fn test_yaml_args() {
+        let input_schema = r#"
+        schemas:
+            Input:
+                fields:
+                    - n:
+                        type: Primitive
+                        name: u32
+                    - m:
+                        type: Span
+                        item_type:
+                            type: Primitive
+                            name: i32
+        cairo_input: Input
+        cairo_output: null
+        "#;
+
+        let schema_file = create_temp_file_with_content(input_schema);
+        let input_schema = parse_schema_file(&schema_file.path().to_path_buf()).unwrap();
+
+        let yaml = "n: 42\nm: [1, -2]\n";
+        assert_eq!(ArgsFormat::detect(yaml), ArgsFormat::Yaml);
+
+        let result = process_args(yaml, ArgsFormat::Yaml, &input_schema).unwrap();
+        let expected = process_json_args(r#"{"n": 42, "m": [1, -2]}"#, &input_schema).unwrap();
+        assert_eq!(result.0, expected.0);
+    }
+
+    #[test]
+    fn test_csv_args() {
+        let input_schema = r#"
+        schemas:
+            Input:
+                fields:
+                    - prices:
+                        type: Span
+                        item_type:
+                            type: Primitive
+                            name: F64
+                    - volumes:
+                        type: Array
+                        item_type:
+                            type: Primitive
+                            name: u64
+        cairo_input: Input
+        cairo_output: null
+        "#;
+
+        let schema_file = create_temp_file_with_content(input_schema);
+        let input_schema = parse_schema_file(&schema_file.path().to_path_buf()).unwrap();
+
+        let csv = "prices,volumes\n0.5,10\n1.5,20\n2.0,\n";
+        assert_eq!(ArgsFormat::detect(csv), ArgsFormat::Csv);
+
+        let result = process_args(csv, ArgsFormat::Csv, &input_schema).unwrap();
+        let expected = process_json_args(
+            r#"{"prices": [0.5, 1.5, 2.0], "volumes": [10, 20]}"#,
+            &input_schema,
+        )
+        .unwrap();
+        assert_eq!(result.0, expected.0);
+    }
+
+    #[test]
+    fn test_csv_args_different_lengths() {
+        let input_schema = r#"
+        schemas:
+            Input:
+                fields:
+                    - a:
+                        type: Span
+                        item_type:
+                            type: Primitive
+                            name: u64
+                    - b:
+                        type: Span
+                        item_type:
+                            type: Primitive
+                            name: u64
+        cairo_input: Input
+        cairo_output: null
+        "#;
+
+        let schema_file = create_temp_file_with_content(input_schema);
+        let input_schema = parse_schema_file(&schema_file.path().to_path_buf()).unwrap();
+
+        let csv = "a,b\n1,\n2,\n3,\n";
+        let result = process_args(csv, ArgsFormat::Csv, &input_schema).unwrap();
+        let expected = process_json_args(r#"{"a": [1, 2, 3], "b": []}"#, &input_schema).unwrap();
+        assert_eq!(result.0, expected.0);
+
+        let csv = "a,b\n1,4\n,5\n,6\n";
+        let result = process_args(csv, ArgsFormat::Csv, &input_schema).unwrap();
+        let expected = process_json_args(r#"{"a": [1], "b": [4, 5, 6]}"#, &input_schema).unwrap();
+        assert_eq!(result.0, expected.0);
+    }
+
+    #[test]
+    fn test_csv_args_interior_blank() {
+        let input_schema = r#"
+        schemas:
+            Input:
+                fields:
+                    - a:
+                        type: Span
+                        item_type:
+                            type: Primitive
+                            name: u64
+                    - b:
+                        type: Span
+                        item_type:
+                            type: Primitive
+                            name: u64
+        cairo_input: Input
+        cairo_output: null
+        "#;
+
+        let schema_file = create_temp_file_with_content(input_schema);
+        let input_schema = parse_schema_file(&schema_file.path().to_path_buf()).unwrap();
+
+        let csv = "a,b\n1,4\n,5\n3,6\n";
+        let result = process_args(csv, ArgsFormat::Csv, &input_schema);
+        assert_eq!(
+            result.unwrap_err(),
+            "Empty cell in column a on line 3, followed by a value on line 4"
+        );
+    }
+
+    #[test]
+    fn test_csv_args_invalid_column() {
+        let input_schema = r#"
+        schemas:
+            Input:
+                fields:
+                    - n:
+                        type: Primitive
+                        name: u32
+        cairo_input: Input
+        cairo_output: null
+        "#;
+
+        let schema_file = create_temp_file_with_content(input_schema);
+        let input_schema = parse_schema_file(&schema_file.path().to_path_buf()).unwrap();
+
+        let result = process_args("n\n1\n", ArgsFormat::Csv, &input_schema);
+        assert!(result
+            .unwrap_err()
+            .contains("must map to a Span or Array field"));
+
+        let result = process_args("unknown\n1\n", ArgsFormat::Csv, &input_schema);
+        assert!(result.unwrap_err().contains("is not a field of Input"));
+    }
 }
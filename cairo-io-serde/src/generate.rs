@@ -0,0 +1,285 @@
+use rand::Rng;
+use serde_json::{json, Map, Value};
+
+use crate::schema::{Schema, SchemaType};
+
+/// Bounds of the randomly generated inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct GenerateConfig {
+    /// Maximum length of the generated `Array<T>` and `Span<T>` values.
+    pub max_array_len: usize,
+    /// Maximum length in bytes of the generated `ByteArray` values.
+    pub max_byte_array_len: usize,
+}
+
+impl Default for GenerateConfig {
+    fn default() -> Self {
+        Self {
+            max_array_len: 8,
+            max_byte_array_len: 64,
+        }
+    }
+}
+
+/// Generates a random JSON input valid against the input schema.
+pub fn random_input<R: Rng>(
+    schema: &Schema,
+    config: &GenerateConfig,
+    rng: &mut R,
+) -> Result<Value, String> {
+    random_struct(&schema.cairo_input, schema, config, rng)
+}
+
+fn random_struct<R: Rng>(
+    name: &str,
+    schema: &Schema,
+    config: &GenerateConfig,
+    rng: &mut R,
+) -> Result<Value, String> {
+    let schema_def = schema
+        .schemas
+        .get(name)
+        .ok_or_else(|| format!("Schema {} not found in schema", name))?;
+
+    let mut object = Map::new();
+    for field in &schema_def.fields {
+        object.insert(
+            field.name.clone(),
+            random_value(&field.ty, schema, config, rng)?,
+        );
+    }
+    Ok(Value::Object(object))
+}
+
+fn random_value<R: Rng>(
+    ty: &SchemaType,
+    schema: &Schema,
+    config: &GenerateConfig,
+    rng: &mut R,
+) -> Result<Value, String> {
+    match ty {
+        SchemaType::Primitive { name } => match name.as_str() {
+            "u64" => Ok(json!(random_unsigned(rng, u64::MAX))),
+            "u32" => Ok(json!(random_unsigned(rng, u32::MAX.into()))),
+            "u16" => Ok(json!(random_unsigned(rng, u16::MAX.into()))),
+            "u8" => Ok(json!(random_unsigned(rng, u8::MAX.into()))),
+            "i64" => Ok(json!(random_signed(rng, i64::MIN, i64::MAX))),
+            "i32" => Ok(json!(random_signed(rng, i32::MIN.into(), i32::MAX.into()))),
+            "i16" => Ok(json!(random_signed(rng, i16::MIN.into(), i16::MAX.into()))),
+            "i8" => Ok(json!(random_signed(rng, i8::MIN.into(), i8::MAX.into()))),
+            // Fixed point numbers with 32 fractional bits, stored in an i64
+            "F64" => Ok(json!(rng.gen_range(-1e6..1e6))),
+            "felt252" => Ok(json!(random_felt252(rng))),
+            "ByteArray" => Ok(json!(random_string(rng, config.max_byte_array_len))),
+            "bool" => Ok(json!(rng.gen::<bool>())),
+            _ => Err(format!("Unknown primitive type: {}", name)),
+        },
+        SchemaType::Array { item_type } | SchemaType::Span { item_type } => {
+            let len = rng.gen_range(0..=config.max_array_len);
+            (0..len)
+                .map(|_| random_value(item_type, schema, config, rng))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array)
+        }
+        SchemaType::Struct { name } => random_struct(name, schema, config, rng),
+    }
+}
+
+/// Returns a random integer in `0..=max`, favoring the bounds of the range.
+pub fn random_unsigned<R: Rng>(rng: &mut R, max: u64) -> u64 {
+    if rng.gen_ratio(1, 4) {
+        [0, 1, max][rng.gen_range(0..3)]
+    } else {
+        rng.gen_range(0..=max)
+    }
+}
+
+/// Returns a random integer in `min..=max`, favoring the bounds of the range and zero.
+pub fn random_signed<R: Rng>(rng: &mut R, min: i64, max: i64) -> i64 {
+    if rng.gen_ratio(1, 4) {
+        [min, -1, 0, 1, max][rng.gen_range(0..5)]
+    } else {
+        rng.gen_range(min..=max)
+    }
+}
+
+/// Returns a random felt252 of up to 31 bytes, as a hex string.
+pub fn random_felt252<R: Rng>(rng: &mut R) -> String {
+    let len = rng.gen_range(0..=31);
+    let bytes = (0..len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+    if bytes.is_empty() {
+        "0x0".to_string()
+    } else {
+        format!("0x{}", hex::encode(bytes))
+    }
+}
+
+/// Returns a random printable ASCII string of up to `max_len` bytes.
+pub fn random_string<R: Rng>(rng: &mut R, max_len: usize) -> String {
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| rng.gen_range(' '..='~')).collect()
+}
+
+/// Returns simpler variants of an input, each still valid against the input schema.
+///
+/// Variants are ordered from the most to the least aggressive simplification.
+pub fn shrink_input(value: &Value, schema: &Schema) -> Vec<Value> {
+    shrink_struct(value, &schema.cairo_input, schema)
+}
+
+fn shrink_struct(value: &Value, name: &str, schema: &Schema) -> Vec<Value> {
+    let (Some(schema_def), Some(object)) = (schema.schemas.get(name), value.as_object()) else {
+        return vec![];
+    };
+
+    let mut variants = Vec::new();
+    for field in &schema_def.fields {
+        let Some(field_value) = object.get(&field.name) else {
+            continue;
+        };
+        for shrunk in shrink_value(field_value, &field.ty, schema) {
+            let mut object = object.clone();
+            object.insert(field.name.clone(), shrunk);
+            variants.push(Value::Object(object));
+        }
+    }
+    variants
+}
+
+fn shrink_value(value: &Value, ty: &SchemaType, schema: &Schema) -> Vec<Value> {
+    let mut variants = match (ty, value) {
+        (SchemaType::Primitive { name }, value) => match (name.as_str(), value) {
+            (_, Value::Number(n)) if n.is_u64() => {
+                let n = n.as_u64().unwrap_or_default();
+                vec![json!(0), json!(n / 2), json!(n - n.min(1))]
+            }
+            (_, Value::Number(n)) if n.is_i64() => {
+                let n = n.as_i64().unwrap_or_default();
+                vec![json!(0), json!(n / 2), json!(n - n.signum())]
+            }
+            (_, Value::Number(n)) => {
+                let n = n.as_f64().unwrap_or_default();
+                vec![json!(0.0), json!(n.trunc())]
+            }
+            ("felt252", Value::String(s)) => {
+                let mut variants = vec![json!("0x0")];
+                if s.starts_with("0x") && s.len() > 4 {
+                    variants.push(json!(s[..s.len() - 2]));
+                }
+                variants
+            }
+            (_, Value::String(s)) => {
+                let len = s.chars().count();
+                vec![
+                    json!(""),
+                    json!(s.chars().take(len / 2).collect::<String>()),
+                    json!(s.chars().take(len.saturating_sub(1)).collect::<String>()),
+                ]
+            }
+            (_, Value::Bool(true)) => vec![json!(false)],
+            _ => vec![],
+        },
+        (SchemaType::Array { item_type } | SchemaType::Span { item_type }, Value::Array(items)) => {
+            let mut variants = vec![json!([]), json!(items[..items.len() / 2])];
+            for i in 0..items.len() {
+                let mut items = items.clone();
+                items.remove(i);
+                variants.push(Value::Array(items));
+            }
+            for (i, item) in items.iter().enumerate() {
+                for shrunk in shrink_value(item, item_type, schema) {
+                    let mut items = items.clone();
+                    items[i] = shrunk;
+                    variants.push(Value::Array(items));
+                }
+            }
+            variants
+        }
+        (SchemaType::Struct { name }, value) => shrink_struct(value, name, schema),
+        _ => vec![],
+    };
+    variants.retain(|variant| variant != value);
+    variants.dedup();
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::cairo_input::process_value_args;
+
+    fn test_schema() -> Schema {
+        serde_yaml::from_str(
+            r#"
+            schemas:
+                Input:
+                    fields:
+                        - amount:
+                            type: Primitive
+                            name: u8
+                        - delta:
+                            type: Primitive
+                            name: i16
+                        - name:
+                            type: Primitive
+                            name: ByteArray
+                        - ids:
+                            type: Span
+                            item_type:
+                                type: Primitive
+                                name: felt252
+            cairo_input: Input
+            cairo_output: null
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_random_input() {
+        let schema = test_schema();
+        let config = GenerateConfig {
+            max_array_len: 3,
+            max_byte_array_len: 10,
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for _ in 0..100 {
+            let input = random_input(&schema, &config, &mut rng).unwrap();
+            assert!(input["amount"].as_u64().unwrap() <= u8::MAX.into());
+            let delta = input["delta"].as_i64().unwrap();
+            assert!((i16::MIN.into()..=i16::MAX.into()).contains(&delta));
+            assert!(input["name"].as_str().unwrap().len() <= 10);
+            assert!(input["ids"].as_array().unwrap().len() <= 3);
+            process_value_args(&input, &schema).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_shrink_input() {
+        let schema = test_schema();
+        let input = json!({"amount": 7, "delta": -3, "name": "ab", "ids": ["0x1234"]});
+
+        let variants = shrink_input(&input, &schema);
+        assert!(
+            variants.contains(&json!({"amount": 0, "delta": -3, "name": "ab", "ids": ["0x1234"]}))
+        );
+        assert!(
+            variants.contains(&json!({"amount": 7, "delta": -2, "name": "ab", "ids": ["0x1234"]}))
+        );
+        assert!(
+            variants.contains(&json!({"amount": 7, "delta": -3, "name": "a", "ids": ["0x1234"]}))
+        );
+        assert!(variants.contains(&json!({"amount": 7, "delta": -3, "name": "ab", "ids": []})));
+        assert!(
+            variants.contains(&json!({"amount": 7, "delta": -3, "name": "ab", "ids": ["0x12"]}))
+        );
+        for variant in variants {
+            assert_ne!(variant, input);
+            process_value_args(&variant, &schema).unwrap();
+        }
+    }
+}
@@ -2,6 +2,7 @@ use cairo_vm::Felt252;
 
 pub mod cairo_input;
 pub mod cairo_output;
+pub mod generate;
 pub mod schema;
 pub(crate) mod utils;
 
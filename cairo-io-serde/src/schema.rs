@@ -62,11 +62,43 @@ impl<'de> Visitor<'de> for NamedSchemaTypeVisitor {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Input and output schemas of a single entry function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FunctionSchema {
+    pub(crate) cairo_input: String,
+    pub(crate) cairo_output: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Schema {
     pub(crate) schemas: HashMap<String, SchemaDef>,
     pub(crate) cairo_input: String,
     pub(crate) cairo_output: String,
+    /// Per-function overrides of `cairo_input`/`cairo_output`, keyed by function path (e.g. `::quote`).
+    #[serde(default)]
+    pub(crate) functions: HashMap<String, FunctionSchema>,
+}
+
+impl Schema {
+    /// Returns the schema to use when running `function`.
+    /// Falls back to the top-level `cairo_input`/`cairo_output` if the function has no own section.
+    pub fn for_function(&self, function: &str) -> Schema {
+        let key = function.trim_start_matches("::");
+        let section = self
+            .functions
+            .iter()
+            .find(|(name, _)| name.trim_start_matches("::") == key);
+
+        match section {
+            Some((_, section)) => Schema {
+                schemas: self.schemas.clone(),
+                cairo_input: section.cairo_input.clone(),
+                cairo_output: section.cairo_output.clone(),
+                functions: HashMap::new(),
+            },
+            None => self.clone(),
+        }
+    }
 }
 
 pub fn parse_schema_file(path: &PathBuf) -> Result<Schema, String> {
@@ -0,0 +1,347 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use cairo_io_serde::cairo_input::process_json_args;
+use cairo_io_serde::schema::Schema;
+use cairo_io_serde::FuncArgs;
+use cairo_lang_sierra::program::Program;
+use cairo_oracle_hint_processor::cairo_run::PreparedProgram;
+use cairo_oracle_hint_processor::mock::OracleMock;
+use cairo_oracle_hint_processor::{run_1, Error, RunOptions};
+use cairo_proto_serde::configuration::Configuration;
+use cairo_vm::types::layout_name::LayoutName;
+use colored::Colorize;
+use itertools::Itertools;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::snapshot::{Snapshot, SnapshotOutcome, Snapshots};
+use crate::{
+    as_short_string, failure_message, RunResultValue, TestCaseResult, TestCaseStatus,
+    TestRunConfig, TestsSummary,
+};
+
+/// Input-driven test cases of an agent entry point, read from a JSON or YAML file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCases {
+    /// The function the cases run.
+    #[serde(default = "default_function")]
+    pub function: String,
+    pub cases: Vec<TestCase>,
+}
+
+fn default_function() -> String {
+    "::main".to_string()
+}
+
+/// A run of the entry point with the output or panic it must produce.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    /// Arguments of the function, as passed to `agent-run --args`.
+    #[serde(default)]
+    pub input: Value,
+    /// Decoded output the run must return.
+    #[serde(default)]
+    pub expected: Option<Value>,
+    /// Panic data the run must panic with, felts holding short strings decoded.
+    #[serde(default)]
+    pub expected_panic: Option<Vec<String>>,
+    /// Oracle mocks serving the calls of the case instead of the oracle servers.
+    #[serde(default)]
+    pub mocks: Option<Vec<OracleMock>>,
+}
+
+impl TestCases {
+    /// Reads test cases from a JSON or YAML file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read test cases file: {}", path.display()))?;
+        // YAML being a superset of JSON, both are read the same way
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("failed to parse test cases file: {}", path.display()))
+    }
+}
+
+/// Runs input-driven test cases against a compiled agent.
+pub struct CasesRunner {
+    pub cases: TestCases,
+    pub sierra_program: Program,
+    pub schema: Schema,
+    pub config: TestRunConfig,
+    /// Snapshots the cases are compared to, if snapshot testing.
+    pub snapshots: Option<Snapshots>,
+}
+
+impl CasesRunner {
+    /// Configure a new test cases runner
+    ///
+    /// # Arguments
+    ///
+    /// * `cases` - The test cases to run
+    /// * `sierra_program` - The agent program, as built by `scarb build`
+    /// * `schema` - The input and output schema of the cases function
+    /// * `config` - Test run configuration
+    pub fn new(
+        cases: TestCases,
+        sierra_program: Program,
+        schema: Schema,
+        config: TestRunConfig,
+    ) -> Self {
+        Self {
+            cases,
+            sierra_program,
+            schema,
+            config,
+            snapshots: None,
+        }
+    }
+
+    /// Compares the output and oracle calls of each case to its stored snapshot.
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    /// Runs the cases, returning their summary whether they passed or not.
+    pub fn run(self, configuration: &Configuration, layout: &LayoutName) -> Result<TestsSummary> {
+        let quiet = self.config.quiet;
+        // Checked on every case, not to find out once the filter is changed
+        if let Some(snapshots) = &self.snapshots {
+            snapshots.check_names(self.cases.cases.iter().map(|case| case.name.as_str()))?;
+        }
+        let (cases, filtered_out): (Vec<_>, Vec<_>) = self
+            .cases
+            .cases
+            .iter()
+            .partition(|case| case.name.contains(&self.config.filter));
+        if !quiet {
+            println!("running {} cases", cases.len());
+        }
+
+        let program = PreparedProgram::new(
+            self.sierra_program.clone(),
+            self.config.casm_cache_dir.as_deref(),
+        )?;
+        let results = cases
+            .par_iter()
+            .map(|case| self.run_case(case, &program, configuration, layout))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut summary = TestsSummary {
+            filtered_out: filtered_out.len(),
+            ..Default::default()
+        };
+        for (result, failure) in results {
+            if !quiet {
+                let status = match failure {
+                    None => "ok".bright_green(),
+                    Some(_) => "fail".bright_red(),
+                };
+                println!("case {} ... {status}", result.name);
+            }
+            match failure {
+                None => summary.passed.push(result.name.clone()),
+                Some(failure) => {
+                    summary.failed.push(result.name.clone());
+                    summary.failed_run_results.push(failure);
+                }
+            }
+            summary.tests.push(result);
+        }
+
+        if quiet {
+            return Ok(summary);
+        }
+        if summary.is_success() {
+            println!(
+                "test result: {}. {} passed; {} failed; {} filtered out;",
+                "ok".bright_green(),
+                summary.passed.len(),
+                summary.failed.len(),
+                summary.filtered_out
+            );
+        } else {
+            println!("failures:");
+            for test in &summary.tests {
+                if let Some(message) = &test.message {
+                    println!("   {} - {message}", test.name);
+                }
+            }
+            println!();
+            println!(
+                "test result: {}. {} passed; {} failed",
+                "FAILED".bright_red(),
+                summary.passed.len(),
+                summary.failed.len()
+            );
+        }
+        Ok(summary)
+    }
+
+    /// Runs a case, returning its result and why it failed if it did.
+    fn run_case(
+        &self,
+        case: &TestCase,
+        program: &PreparedProgram,
+        configuration: &Configuration,
+        layout: &LayoutName,
+    ) -> Result<(TestCaseResult, Option<RunResultValue>)> {
+        let args = match &case.input {
+            Value::Null => FuncArgs::default(),
+            input => process_json_args(&input.to_string(), &self.schema)
+                .map_err(|e| anyhow::anyhow!("invalid input of case {}: {e}", case.name))?,
+        };
+        let mocks = case.mocks.clone().or_else(|| {
+            self.config
+                .mocks
+                .as_ref()
+                .map(|mocks| mocks.for_test(&case.name))
+        });
+
+        let started_at = Instant::now();
+        let r = run_1(
+            configuration,
+            program,
+            &self.cases.function,
+            &args,
+            &self.schema,
+            &RunOptions {
+                layout: *layout,
+                limits: self.config.limits,
+                mocks: mocks.as_deref(),
+                ..Default::default()
+            },
+        );
+        let mut result = TestCaseResult {
+            name: case.name.clone(),
+            duration: started_at.elapsed(),
+            ..Default::default()
+        };
+
+        let mut snapshot = None;
+        let mut failure = match r {
+            Ok((output, _, report)) => {
+                result.steps = Some(report.n_steps);
+                result.oracle_calls = Some(report.oracle_calls);
+                let output = match output {
+                    Some(output) => serde_json::from_str(&output)
+                        .with_context(|| format!("case {} returned invalid JSON", case.name))?,
+                    None => Value::Null,
+                };
+                snapshot = Some(Snapshot::new(
+                    Some(output.clone()),
+                    vec![],
+                    &report.oracle_transcript,
+                ));
+                match (&case.expected, &case.expected_panic) {
+                    (_, Some(_)) => Some(format!("expected panic but returned {output}")),
+                    (Some(expected), None) => {
+                        let diffs = json_diff(expected, &output);
+                        (!diffs.is_empty())
+                            .then(|| format!("output mismatch:\n     {}", diffs.join("\n     ")))
+                    }
+                    (None, None) => None,
+                }
+                .map(RunResultValue::Error)
+            }
+            Err(Error::RunPanic { panic_data, report }) => {
+                if let Some(report) = &report {
+                    result.steps = Some(report.n_steps);
+                    result.oracle_calls = Some(report.oracle_calls.clone());
+                }
+                result.panic_data = panic_data
+                    .iter()
+                    .map(|felt| as_short_string(felt).unwrap_or_else(|| felt.to_string()))
+                    .collect();
+                snapshot = Some(Snapshot::new(
+                    None,
+                    result.panic_data.clone(),
+                    report
+                        .as_ref()
+                        .map_or(&[][..], |report| report.oracle_transcript.as_slice()),
+                ));
+                match &case.expected_panic {
+                    Some(expected) if *expected == result.panic_data => None,
+                    _ => Some(RunResultValue::Panic(panic_data)),
+                }
+            }
+            Err(e) => Some(RunResultValue::Error(e.to_string())),
+        };
+
+        if let (None, Some(snapshots), Some(snapshot)) = (&failure, &self.snapshots, &snapshot) {
+            match snapshots.check(&case.name, snapshot)? {
+                SnapshotOutcome::Matched => {}
+                SnapshotOutcome::Created(path) | SnapshotOutcome::Updated(path) => {
+                    if !self.config.quiet {
+                        println!(
+                            "case {} ... snapshot written to {}",
+                            case.name,
+                            path.display()
+                        );
+                    }
+                }
+                SnapshotOutcome::Mismatch(diffs) => {
+                    failure = Some(RunResultValue::Error(format!(
+                        "snapshot mismatch, run with --update-snapshots to accept it:\n     {}",
+                        diffs.join("\n     ")
+                    )));
+                }
+            }
+        }
+
+        if let Some(failure) = &failure {
+            result.status = TestCaseStatus::Failed;
+            result.message = Some(match failure {
+                RunResultValue::Error(message) => message.clone(),
+                failure => failure_message(failure),
+            });
+        } else {
+            result.status = TestCaseStatus::Passed;
+        }
+        Ok((result, failure))
+    }
+}
+
+/// Lists the differences between the expected and the actual JSON values, one per line.
+pub fn json_diff(expected: &Value, actual: &Value) -> Vec<String> {
+    let mut diffs = Vec::new();
+    diff_at("$", expected, actual, &mut diffs);
+    diffs
+}
+
+fn diff_at(path: &str, expected: &Value, actual: &Value, diffs: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for key in expected.keys().chain(actual.keys()).unique() {
+                let path = format!("{path}.{key}");
+                match (expected.get(key), actual.get(key)) {
+                    (Some(expected), Some(actual)) => diff_at(&path, expected, actual, diffs),
+                    (Some(expected), None) => {
+                        diffs.push(format!("{path}: missing, expected {expected}"))
+                    }
+                    (None, Some(actual)) => diffs.push(format!("{path}: unexpected {actual}")),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for i in 0..expected.len().max(actual.len()) {
+                let path = format!("{path}[{i}]");
+                match (expected.get(i), actual.get(i)) {
+                    (Some(expected), Some(actual)) => diff_at(&path, expected, actual, diffs),
+                    (Some(expected), None) => {
+                        diffs.push(format!("{path}: missing, expected {expected}"))
+                    }
+                    (None, Some(actual)) => diffs.push(format!("{path}: unexpected {actual}")),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if expected != actual => diffs.push(format!("{path}: expected {expected}, got {actual}")),
+        _ => {}
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+
+use cairo_lang_sierra::debug_info::DebugInfo;
+use cairo_oracle_hint_processor::coverage::Coverage;
+
+/// Key of the debug info annotations holding the source location of each statement.
+const COVERAGE_ANNOTATIONS: &str = "github.com/software-mansion/cairo-coverage";
+
+/// Cairo source lines each Sierra statement was compiled from.
+pub struct StatementsLines(pub(crate) HashMap<usize, Vec<(String, usize)>>);
+
+impl StatementsLines {
+    /// Reads the statements code locations, added to the debug info by Scarb when
+    /// `unstable-add-statements-code-locations-debug-info` is enabled.
+    pub fn from_debug_info(debug_info: Option<&DebugInfo>) -> Option<Self> {
+        let locations = debug_info?
+            .annotations
+            .get(COVERAGE_ANNOTATIONS)?
+            .get("statements_code_locations")?
+            .as_object()?;
+
+        let mut lines = HashMap::new();
+        for (statement, statement_locations) in locations {
+            let Ok(statement) = statement.parse::<usize>() else {
+                continue;
+            };
+            // Each location is a `[file, span]` pair, with 0-based lines
+            let statement_lines = statement_locations
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|location| {
+                    let file = location.get(0)?.as_str()?;
+                    let line = location.get(1)?.get("start")?.get("line")?.as_u64()?;
+                    Some((file.to_string(), line as usize + 1))
+                })
+                .collect();
+            lines.insert(statement, statement_lines);
+        }
+        Some(Self(lines))
+    }
+}
+
+/// Coverage of a single source line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineHits {
+    /// Number of tests executing the line.
+    pub hits: usize,
+    /// Whether the line calls an oracle.
+    pub oracle_call: bool,
+}
+
+/// Line coverage of the Cairo sources.
+#[derive(Debug, Default)]
+pub struct LineCoverage {
+    pub files: BTreeMap<String, BTreeMap<usize, LineHits>>,
+}
+
+impl LineCoverage {
+    /// Maps the statements executed by each test back to source lines.
+    pub fn new(lines: &StatementsLines, tests_coverage: &[Coverage]) -> Self {
+        let mut files = BTreeMap::<String, BTreeMap<usize, LineHits>>::new();
+        for (file, line) in lines.0.values().flatten() {
+            files.entry(file.clone()).or_default().entry(*line).or_default();
+        }
+
+        let statements_lines = |statements: &BTreeSet<usize>| {
+            statements
+                .iter()
+                .filter_map(|statement| lines.0.get(statement))
+                .flatten()
+                .collect::<BTreeSet<_>>()
+        };
+        for coverage in tests_coverage {
+            // Lines count once per test, however many statements they were compiled to
+            for (file, line) in statements_lines(&coverage.executed_statements) {
+                if let Some(hits) = files.get_mut(file).and_then(|f| f.get_mut(line)) {
+                    hits.hits += 1;
+                }
+            }
+            for (file, line) in statements_lines(&coverage.oracle_statements) {
+                if let Some(hits) = files.get_mut(file).and_then(|f| f.get_mut(line)) {
+                    hits.oracle_call = true;
+                }
+            }
+        }
+
+        Self { files }
+    }
+
+    /// Keeps only the files matching `predicate`, e.g. to drop the corelib.
+    pub fn retain_files(&mut self, predicate: impl Fn(&str) -> bool) {
+        self.files.retain(|file, _| predicate(file));
+    }
+
+    /// Returns the coverage in the LCOV format.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::from("TN:\n");
+        for (file, lines) in &self.files {
+            let _ = writeln!(lcov, "SF:{file}");
+            for (line, hits) in lines {
+                let _ = writeln!(lcov, "DA:{line},{}", hits.hits);
+            }
+            let _ = writeln!(lcov, "LF:{}", lines.len());
+            let _ = writeln!(lcov, "LH:{}", lines.values().filter(|l| l.hits > 0).count());
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+
+    /// Returns a summary of the covered lines per file, then of the oracle call sites.
+    pub fn summary(&self) -> String {
+        let percent = |hit: usize, total: usize| 100.0 * hit as f64 / total.max(1) as f64;
+        let (mut total, mut hit) = (0, 0);
+        let mut files_summary = String::new();
+        let mut oracle_sites = Vec::new();
+        for (file, lines) in &self.files {
+            let file_hit = lines.values().filter(|l| l.hits > 0).count();
+            let _ = writeln!(
+                files_summary,
+                "  {file}: {:.2}% ({file_hit}/{} lines)",
+                percent(file_hit, lines.len()),
+                lines.len()
+            );
+            total += lines.len();
+            hit += file_hit;
+            oracle_sites.extend(
+                lines
+                    .iter()
+                    .filter(|(_, l)| l.oracle_call)
+                    .map(|(line, l)| (file, line, l.hits)),
+            );
+        }
+
+        let mut summary = format!(
+            "coverage: {:.2}% ({hit}/{total} lines)\n{files_summary}",
+            percent(hit, total)
+        );
+        let oracle_hit = oracle_sites.iter().filter(|(_, _, hits)| *hits > 0).count();
+        let _ = write!(
+            summary,
+            "oracle call sites: {oracle_hit}/{} executed",
+            oracle_sites.len()
+        );
+        for (file, line, hits) in oracle_sites {
+            let _ = write!(summary, "\n  {file}:{line} - {hits} tests");
+        }
+        summary
+    }
+}
@@ -1,5 +1,7 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
 use cairo_io_serde::schema::Schema;
@@ -16,14 +18,25 @@ use cairo_lang_test_plugin::{
     compile_test_prepared_db, test_plugin_suite, TestCompilation, TestCompilationMetadata,
     TestConfig, TestsCompilationConfig,
 };
-use cairo_oracle_hint_processor::{run_1, Error};
+use cairo_oracle_hint_processor::cairo_run::{Instrumentation, PreparedProgram, RunLimits};
+use cairo_oracle_hint_processor::coverage::Coverage;
+use cairo_oracle_hint_processor::mock::OracleMocks;
+use cairo_oracle_hint_processor::report::OracleCallsReport;
+use cairo_oracle_hint_processor::{run_1, Error, RunOptions};
 use cairo_proto_serde::configuration::Configuration;
 use cairo_vm::types::layout_name::LayoutName;
 use cairo_vm::Felt252 as VMFelt;
 use colored::Colorize;
 use itertools::Itertools;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde::{Serialize, Serializer};
 
+use crate::coverage::{LineCoverage, StatementsLines};
+
+pub mod cases;
+pub mod coverage;
+pub mod report;
+pub mod snapshot;
 #[cfg(test)]
 mod test;
 
@@ -53,7 +66,7 @@ impl TestRunner {
         &self,
         configuration: &Configuration,
         layout: &LayoutName,
-    ) -> Result<Option<TestsSummary>> {
+    ) -> Result<TestsSummary> {
         let runner = CompiledTestRunner::new(self.compiler.build()?, self.config.clone());
         runner.run(configuration, layout)
     }
@@ -74,75 +87,79 @@ impl CompiledTestRunner {
         Self { compiled, config }
     }
 
-    /// Execute preconfigured test execution.
-    pub fn run(
-        self,
-        configuration: &Configuration,
-        layout: &LayoutName,
-    ) -> Result<Option<TestsSummary>> {
+    /// Execute preconfigured test execution, returning the summary of the tests whether they
+    /// passed or not.
+    pub fn run(self, configuration: &Configuration, layout: &LayoutName) -> Result<TestsSummary> {
         let (compiled, filtered_out) = filter_test_cases(
             self.compiled,
             self.config.include_ignored,
             self.config.ignored,
-            self.config.filter,
+            self.config.filter.clone(),
         );
-        let TestsSummary {
-            passed,
-            failed,
-            ignored,
-            failed_run_results,
-        } = run_tests(
+        let statements_lines = self.config.coverage.as_ref().and_then(|_| {
+            let lines =
+                StatementsLines::from_debug_info(compiled.sierra_program.debug_info.as_ref());
+            if lines.is_none() {
+                eprintln!(
+                    "warning: the tests were compiled without statements code locations, \
+                     set `unstable-add-statements-code-locations-debug-info = true` \
+                     in the `[profile.dev.cairo]` section of Scarb.toml to report coverage"
+                );
+            }
+            lines
+        });
+        let mut summary = run_tests(
             compiled.metadata.named_tests,
             compiled.sierra_program.program,
             configuration,
             layout,
+            &self.config,
+            statements_lines.is_some(),
         )?;
+        summary.filtered_out = filtered_out;
+        let quiet = self.config.quiet;
+
+        if let (Some(lcov_path), Some(statements_lines)) = (&self.config.coverage, statements_lines)
+        {
+            let mut line_coverage = LineCoverage::new(&statements_lines, &summary.coverage);
+            if let Some(source_root) = &self.config.source_root {
+                line_coverage.retain_files(|file| Path::new(file).starts_with(source_root));
+            }
+            if let Some(parent) = lcov_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(lcov_path, line_coverage.to_lcov())?;
+            if !quiet {
+                println!("{}", line_coverage.summary());
+            }
+        }
+        if quiet {
+            return Ok(summary);
+        }
 
-        if failed.is_empty() {
+        if summary.failed.is_empty() {
             println!(
                 "test result: {}. {} passed; {} failed; {} ignored; {filtered_out} filtered out;",
                 "ok".bright_green(),
-                passed.len(),
-                failed.len(),
-                ignored.len()
+                summary.passed.len(),
+                summary.failed.len(),
+                summary.ignored.len()
             );
-            Ok(None)
         } else {
             println!("failures:");
-            for (failure, run_result) in failed.iter().zip_eq(failed_run_results) {
-                print!("   {failure} - ");
-                match run_result {
-                    RunResultValue::Success(_) => {
-                        println!("expected panic but finished successfully.");
-                    }
-                    RunResultValue::Panic(panic_data) => {
-                        if !panic_data.is_empty() {
-                            let panic_data_string_list = panic_data
-                                .iter()
-                                .map(|m| {
-                                    // Try to parse to utf8 string
-                                    let msg = String::from_utf8(m.to_bytes_be().to_vec());
-                                    if let Ok(msg) = msg {
-                                        format!("{} ('{}')", m, msg)
-                                    } else {
-                                        m.to_string()
-                                    }
-                                })
-                                .join(", ");
-                            println!("Run panicked with: [{}]", panic_data_string_list);
-                        }
-                    }
-                }
+            for (failure, run_result) in summary.failed.iter().zip_eq(&summary.failed_run_results) {
+                println!("   {failure} - {}", failure_message(run_result));
             }
             println!();
-            bail!(
+            println!(
                 "test result: {}. {} passed; {} failed; {} ignored",
                 "FAILED".bright_red(),
-                passed.len(),
-                failed.len(),
-                ignored.len()
+                summary.passed.len(),
+                summary.failed.len(),
+                summary.ignored.len()
             );
         }
+        Ok(summary)
     }
 }
 
@@ -152,6 +169,20 @@ pub struct TestRunConfig {
     pub filter: String,
     pub include_ignored: bool,
     pub ignored: bool,
+    /// Resource budget of each test.
+    pub limits: RunLimits,
+    /// Directory where the profile of each test is written, if profiling.
+    pub profile_dir: Option<PathBuf>,
+    /// File where the LCOV line coverage of the tests is written, if collecting coverage.
+    pub coverage: Option<PathBuf>,
+    /// Only source files under this directory are reported in the coverage.
+    pub source_root: Option<PathBuf>,
+    /// Oracle mocks serving the cheatcodes of the tests, the oracle servers are queried if not set.
+    pub mocks: Option<OracleMocks>,
+    /// Do not print the progress and results, e.g. when they are reported in another format.
+    pub quiet: bool,
+    /// Directory where the compiled programs are cached between runs, if caching.
+    pub casm_cache_dir: Option<PathBuf>,
 }
 
 /// The test cases compiler.
@@ -268,6 +299,37 @@ pub enum RunResultValue {
     Success(Vec<VMFelt>),
     /// Run panicked, returning the carried error data.
     Panic(Vec<VMFelt>),
+    /// Run failed before completing, e.g. on an oracle call without matching mock.
+    Error(String),
+}
+
+/// Returns a felt as the Cairo short string it holds, if it holds one.
+pub fn as_short_string(felt: &VMFelt) -> Option<String> {
+    let bytes = felt
+        .to_bytes_be()
+        .into_iter()
+        .skip_while(|byte| *byte == 0)
+        .collect_vec();
+    let printable = bytes.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ');
+    (!bytes.is_empty() && printable).then(|| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Describes why a test failed.
+fn failure_message(run_result: &RunResultValue) -> String {
+    match run_result {
+        RunResultValue::Success(_) => "expected panic but finished successfully.".to_string(),
+        RunResultValue::Error(message) => format!("Run failed with: {message}"),
+        RunResultValue::Panic(panic_data) => {
+            let panic_data_string_list = panic_data
+                .iter()
+                .map(|felt| match as_short_string(felt) {
+                    Some(msg) => format!("{felt} ('{msg}')"),
+                    None => felt.to_string(),
+                })
+                .join(", ");
+            format!("Run panicked with: [{panic_data_string_list}]")
+        }
+    }
 }
 
 /// The status of a ran test.
@@ -281,15 +343,102 @@ struct TestResult {
     /// The status of the run.
     status: TestStatus,
     /// The gas usage of the run if relevant.
-    gas_usage: Option<i64>,
+    gas_usage: Option<usize>,
+    /// The statements executed by the run, if collecting coverage.
+    coverage: Option<Coverage>,
+    /// The wall-clock duration of the run.
+    duration: Duration,
+    /// The steps executed by the run, if it completed.
+    steps: Option<usize>,
+    /// The oracle calls made by the run, if it completed.
+    oracle_calls: Option<OracleCallsReport>,
+}
+
+/// The outcome of a test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestCaseStatus {
+    Passed,
+    Failed,
+    #[default]
+    Ignored,
+}
+
+/// The detailed result of a test, as reported to CI.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TestCaseResult {
+    pub name: String,
+    pub status: TestCaseStatus,
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    /// Why the test failed.
+    pub message: Option<String>,
+    /// The panic data, with felts holding short strings decoded.
+    pub panic_data: Vec<String>,
+    /// The gas consumed by the test, if it has an available gas.
+    pub gas_usage: Option<usize>,
+    pub steps: Option<usize>,
+    pub oracle_calls: Option<OracleCallsReport>,
+}
+
+impl TestCaseResult {
+    fn new(name: &str, result: Option<&TestResult>) -> Self {
+        let Some(result) = result else {
+            return Self {
+                name: name.to_string(),
+                ..Default::default()
+            };
+        };
+        let (status, message, panic_data) = match &result.status {
+            TestStatus::Success => (TestCaseStatus::Passed, None, vec![]),
+            TestStatus::Fail(run_result) => {
+                let panic_data = match run_result {
+                    RunResultValue::Panic(panic_data) => panic_data
+                        .iter()
+                        .map(|felt| as_short_string(felt).unwrap_or_else(|| felt.to_string()))
+                        .collect(),
+                    _ => vec![],
+                };
+                (TestCaseStatus::Failed, Some(failure_message(run_result)), panic_data)
+            }
+        };
+        Self {
+            name: name.to_string(),
+            status,
+            duration: result.duration,
+            message,
+            panic_data,
+            gas_usage: result.gas_usage,
+            steps: result.steps,
+            oracle_calls: result.oracle_calls.clone(),
+        }
+    }
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
 }
 
 /// Summary data of the ran tests.
+#[derive(Debug, Default, Serialize)]
 pub struct TestsSummary {
-    passed: Vec<String>,
-    failed: Vec<String>,
-    ignored: Vec<String>,
-    failed_run_results: Vec<RunResultValue>,
+    pub passed: Vec<String>,
+    pub failed: Vec<String>,
+    pub ignored: Vec<String>,
+    pub filtered_out: usize,
+    /// The results of all the tests, sorted by name.
+    pub tests: Vec<TestCaseResult>,
+    #[serde(skip)]
+    pub failed_run_results: Vec<RunResultValue>,
+    #[serde(skip)]
+    pub coverage: Vec<Coverage>,
+}
+
+impl TestsSummary {
+    /// Returns true if no test failed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
 }
 
 /// Runs the tests and process the results for a summary.
@@ -300,14 +449,19 @@ pub fn run_tests(
     // _contracts_info: OrderedHashMap<Felt252, ContractInfo>,
     configuration: &Configuration,
     layout: &LayoutName,
+    config: &TestRunConfig,
+    coverage: bool,
 ) -> Result<TestsSummary> {
-    println!("running {} tests", named_tests.len());
-    let wrapped_summary = Mutex::new(Ok(TestsSummary {
-        passed: vec![],
-        failed: vec![],
-        ignored: vec![],
-        failed_run_results: vec![],
-    }));
+    let profile_dir = config.profile_dir.as_deref();
+    if !config.quiet {
+        println!("running {} tests", named_tests.len());
+    }
+    if let Some(profile_dir) = profile_dir {
+        fs::create_dir_all(profile_dir)?;
+    }
+    // Compiled once, the tests all running functions of the same program
+    let program = PreparedProgram::new(sierra_program, config.casm_cache_dir.as_deref())?;
+    let wrapped_summary = Mutex::new(Ok(TestsSummary::default()));
     named_tests
         .into_par_iter()
         .map(
@@ -316,22 +470,50 @@ pub fn run_tests(
                     return Ok((name, None));
                 }
 
+                let test_mocks = config.mocks.as_ref().map(|mocks| mocks.for_test(&name));
+                let started_at = Instant::now();
                 let r = run_1(
                     configuration,
-                    layout,
-                    &None,
-                    &None,
-                    &None,
-                    &None,
-                    &None,
+                    &program,
+                    &name,
                     &FuncArgs::default(),
                     &Schema::default(),
-                    &sierra_program,
-                    &name,
-                    false,
-                    None,
-                    None
+                    &RunOptions {
+                        layout: *layout,
+                        limits: config.limits,
+                        instrumentation: Instrumentation {
+                            profile: profile_dir.is_some(),
+                            coverage,
+                        },
+                        mocks: test_mocks.as_deref(),
+                        available_gas: test.available_gas,
+                        ..Default::default()
+                    },
                 );
+                let duration = started_at.elapsed();
+
+                let report = match &r {
+                    Ok((_, _, report)) => Some(report),
+                    Err(Error::RunPanic { report, .. }) => report.as_deref(),
+                    Err(_) => None,
+                };
+                if let (Some(profile_dir), Some(report)) = (profile_dir, report) {
+                    if let Some(profile) = &report.profile {
+                        let file_name = name.replace("::", ".");
+                        fs::write(
+                            profile_dir.join(format!("{file_name}.folded")),
+                            profile.folded(),
+                        )?;
+                        fs::write(profile_dir.join(format!("{file_name}.txt")), profile.table(20))?;
+                    }
+                }
+                let coverage = report.and_then(|report| report.coverage.clone());
+                // Tests with `#[available_gas(static)]` have no gas to estimate the usage from
+                let gas_usage = test
+                    .available_gas
+                    .and(report.and_then(|report| report.gas_usage));
+                let steps = report.map(|report| report.n_steps);
+                let oracle_calls = report.map(|report| report.oracle_calls.clone());
 
                 Ok((
                     name,
@@ -343,7 +525,7 @@ pub fn run_tests(
                                     RunResultValue::Panic([VMFelt::default()].to_vec()),
                                 ),
                             },
-                            Err(Error::RunPanic(panic_data)) => match test.expectation {
+                            Err(Error::RunPanic { panic_data, .. }) => match test.expectation {
                                 TestExpectation::Success => {
                                     TestStatus::Fail(RunResultValue::Panic(panic_data))
                                 }
@@ -356,9 +538,13 @@ pub fn run_tests(
                                     }
                                 }
                             },
-                            Err(e) => panic!("Error: {:?}", e),
+                            Err(e) => TestStatus::Fail(RunResultValue::Error(e.to_string())),
                         },
-                        gas_usage: None,
+                        gas_usage,
+                        coverage,
+                        duration,
+                        steps,
+                        oracle_calls,
                     }),
                 ))
             },
@@ -368,7 +554,7 @@ pub fn run_tests(
             if wrapped_summary.is_err() {
                 return;
             }
-            let (name, status) = match r {
+            let (name, mut status) = match r {
                 Ok((name, status)) => (name, status),
                 Err(err) => {
                     *wrapped_summary = Err(err);
@@ -376,26 +562,37 @@ pub fn run_tests(
                 }
             };
             let summary = wrapped_summary.as_mut().unwrap();
+            if let Some(coverage) = status.as_mut().and_then(|r| r.coverage.take()) {
+                summary.coverage.push(coverage);
+            }
+            summary.tests.push(TestCaseResult::new(&name, status.as_ref()));
             let (res_type, status_str, gas_usage) = match status {
                 Some(TestResult {
                     status: TestStatus::Success,
                     gas_usage,
+                    ..
                 }) => (&mut summary.passed, "ok".bright_green(), gas_usage),
                 Some(TestResult {
                     status: TestStatus::Fail(run_result),
                     gas_usage,
+                    ..
                 }) => {
                     summary.failed_run_results.push(run_result);
                     (&mut summary.failed, "fail".bright_red(), gas_usage)
                 }
                 None => (&mut summary.ignored, "ignored".bright_yellow(), None),
             };
-            if let Some(gas_usage) = gas_usage {
-                println!("test {name} ... {status_str} (gas usage est.: {gas_usage})");
-            } else {
-                println!("test {name} ... {status_str}");
+            if !config.quiet {
+                match gas_usage {
+                    Some(gas_usage) => {
+                        println!("test {name} ... {status_str} (gas usage est.: {gas_usage})")
+                    }
+                    None => println!("test {name} ... {status_str}"),
+                }
             }
             res_type.push(name);
         });
-    wrapped_summary.into_inner().unwrap()
+    let mut summary = wrapped_summary.into_inner().unwrap()?;
+    summary.tests.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(summary)
 }
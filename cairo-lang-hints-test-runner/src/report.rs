@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::{TestCaseStatus, TestsSummary};
+
+/// The tests of a compiled target, as reported to CI.
+#[derive(Debug, Serialize)]
+pub struct TestSuite {
+    pub name: String,
+    #[serde(flatten)]
+    pub summary: TestsSummary,
+}
+
+/// Returns the test suites as a JSON array.
+pub fn to_json(suites: &[TestSuite]) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(suites)
+}
+
+/// Returns the test suites in the JUnit XML format.
+pub fn to_junit(suites: &[TestSuite]) -> String {
+    let count = |summary: &TestsSummary, status: TestCaseStatus| {
+        summary.tests.iter().filter(|t| t.status == status).count()
+    };
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    for suite in suites {
+        let summary = &suite.summary;
+        let time: f64 = summary.tests.iter().map(|t| t.duration.as_secs_f64()).sum();
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{time:.3}\">",
+            escape(&suite.name),
+            summary.tests.len(),
+            count(summary, TestCaseStatus::Failed),
+            count(summary, TestCaseStatus::Ignored),
+        );
+        for test in &summary.tests {
+            let (classname, name) = test.name.rsplit_once("::").unwrap_or(("", &test.name));
+            let _ = writeln!(
+                xml,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\">",
+                escape(classname),
+                escape(name),
+                test.duration.as_secs_f64()
+            );
+
+            let properties = [
+                ("steps", test.steps.map(|steps| steps.to_string())),
+                ("gas_usage", test.gas_usage.map(|gas| gas.to_string())),
+                (
+                    "oracle_calls",
+                    test.oracle_calls.as_ref().map(|calls| calls.count.to_string()),
+                ),
+            ];
+            if properties.iter().any(|(_, value)| value.is_some()) {
+                xml.push_str("      <properties>\n");
+                for (name, value) in properties {
+                    if let Some(value) = value {
+                        let _ = writeln!(
+                            xml,
+                            "        <property name=\"{name}\" value=\"{value}\"/>"
+                        );
+                    }
+                }
+                xml.push_str("      </properties>\n");
+            }
+
+            match test.status {
+                TestCaseStatus::Passed => {}
+                TestCaseStatus::Failed => {
+                    let message = test.message.as_deref().unwrap_or_default();
+                    let _ = writeln!(
+                        xml,
+                        "      <failure message=\"{}\">{}</failure>",
+                        escape(message),
+                        escape(&test.panic_data.join("\n"))
+                    );
+                }
+                TestCaseStatus::Ignored => xml.push_str("      <skipped/>\n"),
+            }
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// Escapes the characters with a special meaning in XML attributes and text.
+fn escape(text: &str) -> String {
+    text.chars().fold(String::new(), |mut escaped, c| {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+        escaped
+    })
+}
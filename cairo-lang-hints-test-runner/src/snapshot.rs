@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use cairo_oracle_hint_processor::report::OracleCall;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cases::json_diff;
+
+/// What a run of a test case produced, compared across runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The decoded output, if the run returned.
+    pub output: Option<Value>,
+    /// The panic data, felts holding short strings decoded, if the run panicked.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub panic_data: Vec<String>,
+    /// The oracle calls of the run, in order.
+    pub oracle_calls: Vec<OracleExchange>,
+}
+
+/// A decoded oracle request and its response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OracleExchange {
+    pub selector: String,
+    pub request: Value,
+    pub response: Option<Value>,
+}
+
+impl Snapshot {
+    pub fn new(
+        output: Option<Value>,
+        panic_data: Vec<String>,
+        oracle_calls: &[OracleCall],
+    ) -> Self {
+        Self {
+            output,
+            panic_data,
+            oracle_calls: oracle_calls
+                .iter()
+                .map(|call| OracleExchange {
+                    selector: call.selector.clone(),
+                    request: call.request.clone(),
+                    response: call.response.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How a snapshot compared to the stored one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotOutcome {
+    /// The snapshot is the stored one.
+    Matched,
+    /// No snapshot was stored, it was written.
+    Created(PathBuf),
+    /// The stored snapshot was replaced.
+    Updated(PathBuf),
+    /// The snapshot differs from the stored one, listing the differences.
+    Mismatch(Vec<String>),
+}
+
+/// A directory of snapshots, one JSON file per test case.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    pub dir: PathBuf,
+    /// Replace the stored snapshots that differ instead of reporting them.
+    pub update: bool,
+}
+
+impl Snapshots {
+    pub fn new(dir: PathBuf, update: bool) -> Self {
+        Self { dir, update }
+    }
+
+    /// Returns the file of a test case's snapshot.
+    pub fn path(&self, name: &str) -> PathBuf {
+        let file_name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{file_name}.json"))
+    }
+
+    /// Fails if test cases would share a snapshot file, their names only differing by the
+    /// characters replaced in the file names.
+    pub fn check_names<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let mut paths = HashMap::new();
+        for name in names {
+            if let Some(other) = paths.insert(self.path(name), name) {
+                bail!(
+                    "the cases `{other}` and `{name}` would share the snapshot {}, rename one of them",
+                    self.path(name).display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares a snapshot to the stored one, writing it if there is none or if updating.
+    pub fn check(&self, name: &str, snapshot: &Snapshot) -> Result<SnapshotOutcome> {
+        let path = self.path(name);
+        let actual = serde_json::to_value(snapshot)?;
+        if !path.exists() {
+            write_snapshot(&path, &actual)?;
+            return Ok(SnapshotOutcome::Created(path));
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read snapshot: {}", path.display()))?;
+        let stored: Value = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse snapshot: {}", path.display()))?;
+        let diffs = json_diff(&stored, &actual);
+        if diffs.is_empty() {
+            Ok(SnapshotOutcome::Matched)
+        } else if self.update {
+            write_snapshot(&path, &actual)?;
+            Ok(SnapshotOutcome::Updated(path))
+        } else {
+            Ok(SnapshotOutcome::Mismatch(diffs))
+        }
+    }
+}
+
+fn write_snapshot(path: &Path, snapshot: &Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(
+        path,
+        format!("{}\n", serde_json::to_string_pretty(snapshot)?),
+    )
+    .with_context(|| format!("failed to write snapshot: {}", path.display()))
+}
@@ -265,3 +265,171 @@ fn test_format_for_panic() {
         "Panicked with (0x9999, \"hello\", 0x776f726c64 ('world'), 0x8888)."
     );
 }
+
+#[test]
+fn test_line_coverage() {
+    use std::collections::{BTreeSet, HashMap};
+
+    use cairo_oracle_hint_processor::coverage::Coverage;
+
+    use crate::coverage::{LineCoverage, StatementsLines};
+
+    let lines = StatementsLines(HashMap::from([
+        (0, vec![("src/lib.cairo".to_string(), 1)]),
+        (1, vec![("src/lib.cairo".to_string(), 1)]),
+        (2, vec![("src/lib.cairo".to_string(), 2)]),
+        (3, vec![("src/lib.cairo".to_string(), 3)]),
+    ]));
+    let tests = [
+        Coverage {
+            executed_statements: BTreeSet::from([0, 1, 2]),
+            oracle_statements: BTreeSet::from([2]),
+        },
+        Coverage {
+            executed_statements: BTreeSet::from([0]),
+            oracle_statements: BTreeSet::from([2]),
+        },
+    ];
+
+    let coverage = LineCoverage::new(&lines, &tests);
+    assert_eq!(
+        coverage.to_lcov(),
+        "TN:\nSF:src/lib.cairo\nDA:1,2\nDA:2,1\nDA:3,0\nLF:3\nLH:2\nend_of_record\n"
+    );
+    assert!(coverage.summary().contains("oracle call sites: 1/1 executed"));
+}
+
+#[test]
+fn test_junit_report() {
+    use std::time::Duration;
+
+    use crate::report::{to_junit, TestSuite};
+    use crate::{TestCaseResult, TestCaseStatus, TestsSummary};
+
+    let suite = TestSuite {
+        name: "agent_unittest".to_string(),
+        summary: TestsSummary {
+            tests: vec![
+                TestCaseResult {
+                    name: "agent::tests::test_ok".to_string(),
+                    status: TestCaseStatus::Passed,
+                    duration: Duration::from_millis(12),
+                    steps: Some(42),
+                    gas_usage: Some(1230),
+                    ..Default::default()
+                },
+                TestCaseResult {
+                    name: "agent::tests::test_lie".to_string(),
+                    status: TestCaseStatus::Failed,
+                    message: Some("Run panicked with: [..]".to_string()),
+                    panic_data: vec!["The oracle lied!".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        },
+    };
+
+    let junit = to_junit(&[suite]);
+    assert!(junit.contains(
+        "<testsuite name=\"agent_unittest\" tests=\"2\" failures=\"1\" skipped=\"0\" time=\"0.012\">"
+    ));
+    assert!(junit.contains("<property name=\"steps\" value=\"42\"/>"));
+    assert!(junit.contains("<property name=\"gas_usage\" value=\"1230\"/>"));
+    assert!(junit.contains(
+        "<failure message=\"Run panicked with: [..]\">The oracle lied!</failure>"
+    ));
+}
+
+#[test]
+fn test_json_diff() {
+    use serde_json::json;
+
+    use crate::cases::json_diff;
+
+    let expected = json!({"price": 3000, "sources": ["a", "b"], "pair": {"base": "ETH"}});
+    let actual = json!({"price": 2999, "sources": ["a"], "pair": {"base": "ETH"}, "extra": 1});
+
+    assert_eq!(
+        json_diff(&expected, &actual),
+        vec![
+            "$.price: expected 3000, got 2999",
+            "$.sources[1]: missing, expected \"b\"",
+            "$.extra: unexpected 1",
+        ]
+    );
+    assert!(json_diff(&expected, &expected).is_empty());
+}
+
+#[test]
+fn test_snapshot_names() {
+    use std::path::PathBuf;
+
+    use crate::snapshot::Snapshots;
+
+    let snapshots = Snapshots::new(PathBuf::from("snapshots"), false);
+    assert_eq!(
+        snapshots.path("Buy BTC"),
+        PathBuf::from("snapshots/buy_btc.json")
+    );
+    assert!(snapshots.check_names(["Buy BTC", "Sell BTC"]).is_ok());
+
+    let error = snapshots
+        .check_names(["Buy BTC", "Sell BTC", "buy-btc"])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("the cases `Buy BTC` and `buy-btc` would share the snapshot"));
+}
+
+#[test]
+fn test_gas_usage() {
+    use std::path::PathBuf;
+
+    use cairo_oracle_hint_processor::cairo_run::RunLimits;
+    use cairo_proto_serde::configuration::Configuration;
+    use cairo_vm::types::layout_name::LayoutName;
+
+    use crate::{run_tests, TestCaseStatus, TestRunConfig};
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data/gas");
+    let compiled = TestCompiler::try_new(&path, false)
+        .unwrap()
+        .build()
+        .unwrap();
+    let config = TestRunConfig {
+        filter: String::new(),
+        include_ignored: false,
+        ignored: false,
+        limits: RunLimits::default(),
+        profile_dir: None,
+        coverage: None,
+        source_root: None,
+        mocks: None,
+        quiet: true,
+        casm_cache_dir: None,
+    };
+    let summary = run_tests(
+        compiled.metadata.named_tests,
+        compiled.sierra_program.program,
+        &Configuration::default(),
+        &LayoutName::all_cairo,
+        &config,
+        false,
+    )
+    .unwrap();
+
+    let [test_sum, test_out_of_gas] = &summary.tests[..] else {
+        panic!("expected two tests, got {:?}", summary.tests);
+    };
+    assert_eq!(test_sum.name, "gas::tests::test_sum");
+    assert_eq!(test_sum.status, TestCaseStatus::Passed);
+    let gas_usage = test_sum.gas_usage.unwrap();
+    assert!(gas_usage > 0 && gas_usage < 1000000, "{gas_usage}");
+    assert_eq!(
+        serde_json::to_value(test_sum).unwrap()["gas_usage"],
+        gas_usage
+    );
+
+    assert_eq!(test_out_of_gas.status, TestCaseStatus::Failed);
+}
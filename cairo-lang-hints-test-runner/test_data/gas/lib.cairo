@@ -0,0 +1,24 @@
+fn sum(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        n + sum(n - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sum;
+
+    #[test]
+    #[available_gas(1000000)]
+    fn test_sum() {
+        assert(sum(10) == 55, 'wrong sum');
+    }
+
+    #[test]
+    #[available_gas(100)]
+    fn test_sum_out_of_gas() {
+        assert(sum(100) == 5050, 'wrong sum');
+    }
+}
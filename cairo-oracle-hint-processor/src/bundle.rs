@@ -0,0 +1,430 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cairo_io_serde::schema::Schema;
+use cairo_lang_sierra::program::Program as SierraProgram;
+use cairo_vm::Felt252;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::cairo_run::{find_function, is_panic_result};
+use crate::report::OracleCall;
+use crate::Error;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const TRACE_FILE: &str = "trace.bin";
+pub const MEMORY_FILE: &str = "memory.bin";
+pub const AIR_PUBLIC_INPUT_FILE: &str = "air_public_input.json";
+pub const AIR_PRIVATE_INPUT_FILE: &str = "air_private_input.json";
+pub const CAIRO_PIE_FILE: &str = "cairo_pie.zip";
+pub const INPUTS_FILE: &str = "inputs.json";
+pub const SCHEMA_FILE: &str = "schema.json";
+pub const ORACLE_TRANSCRIPT_FILE: &str = "oracle_transcript.json";
+pub const PROOF_FILE: &str = "proof.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// Settings of the run that produced a bundle, as recorded in its manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub function: String,
+    pub layout: String,
+    pub proof_mode: bool,
+    pub finalize_builtins: bool,
+    /// Whether the return values were copied to the output builtin, see `Cairo1RunConfig::copy_to_output`.
+    pub copy_to_output: bool,
+    /// Whether the function returns a `PanicResult`, its output then starting with a panic flag.
+    #[serde(default)]
+    pub panic_result: bool,
+    pub sierra_program_hash: String,
+    /// SHA-256 checksum of every other file of the bundle, by file name.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new(
+        function: &str,
+        layout: &str,
+        proof_mode: bool,
+        finalize_builtins: bool,
+        append_return_values: bool,
+        sierra_program: &SierraProgram,
+    ) -> Self {
+        let panic_result = find_function(sierra_program, function)
+            .is_ok_and(|f| is_panic_result(f.signature.ret_types.last()));
+        Self {
+            version: MANIFEST_VERSION,
+            function: function.to_string(),
+            layout: layout.to_string(),
+            proof_mode,
+            finalize_builtins,
+            copy_to_output: append_return_values || proof_mode,
+            panic_result,
+            sierra_program_hash: sierra_program_hash(sierra_program),
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+/// A directory holding every artifact needed to prove a run, described by its manifest.
+#[derive(Debug)]
+pub struct Bundle {
+    pub dir: PathBuf,
+    /// Directory the bundle is written to until it is finished, so that a failed run
+    /// leaves the previous bundle of `dir` as it was.
+    staging: Option<PathBuf>,
+}
+
+impl Bundle {
+    /// Starts writing a bundle, which replaces the one of `dir` once finished.
+    pub fn create(dir: &Path) -> Result<Self, Error> {
+        let dir = std::path::absolute(dir)?;
+        check_replaceable(&dir)?;
+        let name = dir
+            .file_name()
+            .ok_or_else(|| Error::Bundle(format!("invalid bundle directory {}", dir.display())))?;
+        // Next to the bundle, for it to be renamed on the same file system
+        let staging = dir.with_file_name(format!(
+            ".{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        Ok(Self {
+            dir,
+            staging: Some(staging),
+        })
+    }
+
+    /// Opens an existing bundle, checking its files against the manifest checksums.
+    pub fn open(dir: &Path) -> Result<(Self, Manifest), Error> {
+        let bundle = Self {
+            dir: dir.to_path_buf(),
+            staging: None,
+        };
+        let manifest = read_manifest(dir)?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Error::Bundle(format!(
+                "unsupported manifest version {}",
+                manifest.version
+            )));
+        }
+        for (file, checksum) in &manifest.files {
+            let actual = file_checksum(&bundle.path(file))?;
+            if actual != *checksum {
+                return Err(Error::Bundle(format!(
+                    "checksum mismatch of {file}, expected {checksum}, got {actual}"
+                )));
+            }
+        }
+        Ok((bundle, manifest))
+    }
+
+    /// Returns the path of a file of the bundle, in its staging directory until it is finished.
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.staging.as_ref().unwrap_or(&self.dir).join(file)
+    }
+
+    /// Returns the path of a file of the bundle if the manifest lists it.
+    pub fn artifact(&self, manifest: &Manifest, file: &str) -> Option<PathBuf> {
+        manifest.files.contains_key(file).then(|| self.path(file))
+    }
+
+    /// Writes the inputs, schema and oracle transcript of the run next to the artifacts
+    /// written by `run_1`, then the manifest listing their checksums, and moves the bundle
+    /// to its directory.
+    pub fn finish(
+        &mut self,
+        mut manifest: Manifest,
+        inputs: &Value,
+        schema: &Schema,
+        oracle_calls: &[OracleCall],
+    ) -> Result<Manifest, Error> {
+        let transcript = oracle_calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "selector": call.selector,
+                    "request": call.request,
+                    "response": call.response,
+                    "success": call.success,
+                    "latency_ms": call.latency.as_secs_f64() * 1000.0,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.write_json(INPUTS_FILE, inputs)?;
+        self.write_json(SCHEMA_FILE, schema)?;
+        self.write_json(ORACLE_TRANSCRIPT_FILE, &transcript)?;
+
+        // The private input points to the trace and memory by the path they were written to
+        let private_input_file = self.path(AIR_PRIVATE_INPUT_FILE);
+        if private_input_file.exists() {
+            let mut private_input: Value =
+                serde_json::from_str(&fs::read_to_string(&private_input_file)?)
+                    .map_err(|e| Error::Bundle(format!("invalid {AIR_PRIVATE_INPUT_FILE}: {e}")))?;
+            private_input["trace_path"] = json!(self.dir.join(TRACE_FILE));
+            private_input["memory_path"] = json!(self.dir.join(MEMORY_FILE));
+            self.write_json(AIR_PRIVATE_INPUT_FILE, &private_input)?;
+        }
+
+        manifest.files.clear();
+        for entry in fs::read_dir(self.path(""))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name != MANIFEST_FILE && entry.file_type()?.is_file() {
+                manifest.files.insert(name, file_checksum(&entry.path())?);
+            }
+        }
+        self.write_json(MANIFEST_FILE, &manifest)?;
+
+        if let Some(staging) = self.staging.take() {
+            // Checked again, the directory may have been filled during the run
+            if let Err(e) = check_replaceable(&self.dir) {
+                self.staging = Some(staging);
+                return Err(e);
+            }
+            if self.dir.exists() {
+                fs::remove_dir_all(&self.dir)?;
+            }
+            fs::rename(&staging, &self.dir)?;
+        }
+        Ok(manifest)
+    }
+
+    fn write_json(&self, file: &str, value: &impl Serialize) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(value)
+            .map_err(|e| Error::Bundle(format!("failed to serialize {file}: {e}")))?;
+        fs::write(self.path(file), json)?;
+        Ok(())
+    }
+}
+
+impl Drop for Bundle {
+    /// Removes the staging directory of a bundle that was not finished.
+    fn drop(&mut self) {
+        if let Some(staging) = &self.staging {
+            let _ = fs::remove_dir_all(staging);
+        }
+    }
+}
+
+/// Fails if a directory is not empty and holds no bundle, not to replace an unrelated one.
+fn check_replaceable(dir: &Path) -> Result<(), Error> {
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() && read_manifest(dir).is_err() {
+        return Err(Error::Bundle(format!(
+            "{} is not empty and holds no bundle",
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the manifest of a bundle.
+fn read_manifest(dir: &Path) -> Result<Manifest, Error> {
+    serde_json::from_str(&fs::read_to_string(dir.join(MANIFEST_FILE))?)
+        .map_err(|e| Error::Bundle(format!("invalid manifest: {e}")))
+}
+
+/// Returns the cells of the output segment listed in the public memory of an AIR public input.
+pub fn public_output(public_input: &Value) -> Result<Vec<Felt252>, Error> {
+    let segment = &public_input["memory_segments"]["output"];
+    let (Some(begin), Some(stop)) = (segment["begin_addr"].as_u64(), segment["stop_ptr"].as_u64())
+    else {
+        return Err(Error::Bundle(
+            "the public input has no output segment".to_string(),
+        ));
+    };
+
+    let mut cells = public_input["public_memory"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|cell| Some((cell["address"].as_u64()?, cell["value"].as_str()?)))
+        .filter(|(address, _)| (begin..stop).contains(address))
+        .collect::<Vec<_>>();
+    cells.sort_by_key(|(address, _)| *address);
+    cells.dedup_by_key(|(address, _)| *address);
+    if cells.len() as u64 != stop - begin {
+        return Err(Error::Bundle(
+            "the output segment is not entirely in the public memory".to_string(),
+        ));
+    }
+    cells
+        .into_iter()
+        .map(|(_, value)| {
+            Felt252::from_hex(value)
+                .map_err(|e| Error::Bundle(format!("invalid public memory value {value}: {e}")))
+        })
+        .collect()
+}
+
+/// Returns the return values held by the public output of a run, without its panic flag
+/// and the inputs copied after them.
+pub fn return_values(manifest: &Manifest, output: &[Felt252]) -> Result<Vec<Felt252>, Error> {
+    let output = if manifest.panic_result {
+        match output.split_first() {
+            Some((flag, rest)) if *flag == Felt252::ZERO => rest,
+            Some((_, rest)) => {
+                let panic_data = rest
+                    .first()
+                    .and_then(|len| len.to_usize())
+                    .and_then(|len| rest.get(1..=len))
+                    .unwrap_or_default();
+                return Err(Error::RunPanic {
+                    panic_data: panic_data.to_vec(),
+                    report: None,
+                });
+            }
+            None => output,
+        }
+    } else {
+        output
+    };
+    let len = output
+        .first()
+        .and_then(|len| len.to_usize())
+        .ok_or_else(|| Error::Bundle("the public output holds no return values".to_string()))?;
+    output
+        .get(1..=len)
+        .map(<[Felt252]>::to_vec)
+        .ok_or_else(|| Error::Bundle("the public output is shorter than its length".to_string()))
+}
+
+/// Returns the SHA-256 hash of a Sierra program, as a hex string.
+pub fn sierra_program_hash(sierra_program: &SierraProgram) -> String {
+    let json = serde_json::to_vec(sierra_program).expect("Sierra programs serialize to JSON");
+    hex::encode(Sha256::digest(json))
+}
+
+fn file_checksum(path: &Path) -> Result<String, Error> {
+    Ok(hex::encode(Sha256::digest(fs::read(path)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bundle = Bundle::create(&dir.path().join("bundle")).unwrap();
+        fs::write(bundle.path(TRACE_FILE), [1, 2, 3]).unwrap();
+
+        let manifest = Manifest::new(
+            "::main",
+            "all_cairo",
+            true,
+            false,
+            false,
+            &SierraProgram {
+                type_declarations: vec![],
+                libfunc_declarations: vec![],
+                statements: vec![],
+                funcs: vec![],
+            },
+        );
+        assert!(manifest.copy_to_output);
+        let manifest = bundle
+            .finish(manifest, &json!({"n": 1}), &Schema::default(), &[])
+            .unwrap();
+        assert_eq!(
+            manifest.files.keys().collect::<Vec<_>>(),
+            vec![INPUTS_FILE, ORACLE_TRANSCRIPT_FILE, SCHEMA_FILE, TRACE_FILE]
+        );
+
+        let (_, opened) = Bundle::open(&bundle.dir).unwrap();
+        assert_eq!(opened, manifest);
+
+        fs::write(bundle.path(TRACE_FILE), [1, 2, 4]).unwrap();
+        assert!(matches!(Bundle::open(&bundle.dir), Err(Error::Bundle(_))));
+    }
+
+    #[test]
+    fn test_bundle_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = SierraProgram {
+            type_declarations: vec![],
+            libfunc_declarations: vec![],
+            statements: vec![],
+            funcs: vec![],
+        };
+        let manifest = Manifest::new("::main", "all_cairo", true, false, false, &program);
+
+        // A directory holding something else than a bundle is left untouched
+        let other = dir.path().join("other");
+        fs::create_dir(&other).unwrap();
+        fs::write(other.join(MANIFEST_FILE), "{\"name\": \"other\"}").unwrap();
+        assert!(matches!(Bundle::create(&other), Err(Error::Bundle(_))));
+        assert!(other.join(MANIFEST_FILE).exists());
+
+        let path = dir.path().join("bundle");
+        let mut bundle = Bundle::create(&path).unwrap();
+        fs::write(bundle.path(TRACE_FILE), [1, 2, 3]).unwrap();
+        bundle
+            .finish(manifest.clone(), &json!({"n": 1}), &Schema::default(), &[])
+            .unwrap();
+
+        // The previous bundle is kept until the new one is finished
+        let bundle = Bundle::create(&path).unwrap();
+        fs::write(bundle.path(MEMORY_FILE), [4, 5, 6]).unwrap();
+        drop(bundle);
+        let (_, opened) = Bundle::open(&path).unwrap();
+        assert!(opened.files.contains_key(TRACE_FILE));
+        assert!(!opened.files.contains_key(MEMORY_FILE));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let mut bundle = Bundle::create(&path).unwrap();
+        fs::write(bundle.path(MEMORY_FILE), [4, 5, 6]).unwrap();
+        bundle
+            .finish(manifest, &json!({"n": 2}), &Schema::default(), &[])
+            .unwrap();
+        let (_, opened) = Bundle::open(&path).unwrap();
+        assert!(!opened.files.contains_key(TRACE_FILE));
+        assert!(opened.files.contains_key(MEMORY_FILE));
+    }
+
+    #[test]
+    fn test_public_output() {
+        let public_input = json!({
+            "layout": "recursive",
+            "memory_segments": {"output": {"begin_addr": 10, "stop_ptr": 15}},
+            "public_memory": [
+                {"address": 1, "value": "0x7", "page": 0},
+                {"address": 12, "value": "0x2a", "page": 0},
+                {"address": 10, "value": "0x0", "page": 0},
+                {"address": 11, "value": "0x2", "page": 0},
+                {"address": 13, "value": "0x2b", "page": 0},
+                {"address": 14, "value": "0x9", "page": 0},
+            ],
+        });
+        let output = public_output(&public_input).unwrap();
+        assert_eq!(output.len(), 5);
+
+        let mut manifest = Manifest::new(
+            "::main",
+            "recursive",
+            true,
+            true,
+            false,
+            &SierraProgram {
+                type_declarations: vec![],
+                libfunc_declarations: vec![],
+                statements: vec![],
+                funcs: vec![],
+            },
+        );
+        manifest.panic_result = true;
+        assert_eq!(
+            return_values(&manifest, &output).unwrap(),
+            vec![Felt252::from(42), Felt252::from(43)]
+        );
+    }
+}
@@ -12,12 +12,13 @@ use cairo_lang_casm::{
     inline::CasmContext,
     instructions::{Instruction, InstructionBody},
 };
+use cairo_lang_runner::token_gas_cost;
 use cairo_lang_sierra::{
     extensions::{
         bitwise::BitwiseType,
         core::{CoreLibfunc, CoreType},
         ec::EcOpType,
-        gas::GasBuiltinType,
+        gas::{CostTokenType, GasBuiltinType},
         pedersen::PedersenType,
         poseidon::PoseidonType,
         range_check::RangeCheckType,
@@ -30,8 +31,8 @@ use cairo_lang_sierra::{
     program_registry::ProgramRegistry,
 };
 use cairo_lang_sierra_to_casm::{
-    compiler::{CairoProgram, SierraToCasmConfig},
-    metadata::calc_metadata_ap_change_only,
+    compiler::SierraToCasmConfig,
+    metadata::{calc_metadata, calc_metadata_ap_change_only, MetadataComputationConfig},
 };
 use cairo_lang_sierra_type_size::get_type_size_map;
 use cairo_lang_utils::{
@@ -46,18 +47,61 @@ use cairo_vm::{
         relocatable::MaybeRelocatable,
     },
     vm::{
-        errors::{runner_errors::RunnerError, vm_errors::VirtualMachineError},
+        errors::vm_errors::VirtualMachineError,
         runners::cairo_runner::{CairoRunner, RunResources, RunnerMode},
         vm_core::VirtualMachine,
     },
     Felt252,
 };
 use itertools::{chain, Itertools};
+use log::debug;
 use num_bigint::{BigInt, Sign};
 use num_traits::{cast::ToPrimitive, Zero};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::{collections::HashMap, io, path::Path, time::Duration};
+
+use crate::{
+    compiled::{CompiledInstruction, CompiledProgram},
+    coverage::Coverage,
+    debugger::Debugger,
+    mock::OracleMock,
+    profiler::Profiler,
+    report::ExecutionReport,
+    rpc_hint_processor::Rpc1HintProcessor,
+    stepper::Stepper,
+    value_layout::{layout_args, write_arguments, write_return_value, ValueLayout},
+    Error, ResourceLimit,
+};
+
+/// Gas given to functions taking the gas builtin, unless the run sets its own
+const DEFAULT_AVAILABLE_GAS: usize = 9999999999999;
+
+/// Resource budget of a cairo run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunLimits {
+    /// Maximum number of VM steps
+    pub max_steps: Option<usize>,
+    /// Wall-clock limit, covering both VM execution and time spent waiting on oracles
+    pub timeout: Option<Duration>,
+    /// Maximum number of memory cells, checked every thousand steps and at the end of the run
+    pub max_memory_cells: Option<usize>,
+}
 
-use crate::{rpc_hint_processor::Rpc1HintProcessor, Error};
+/// Data collected from the trace of a cairo run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Instrumentation {
+    /// Attribute the steps of the run to Sierra functions
+    pub profile: bool,
+    /// Record the Sierra statements executed by the run
+    pub coverage: bool,
+}
+
+impl Instrumentation {
+    // Returns true if the trace is needed to collect the data
+    fn needs_trace(&self) -> bool {
+        self.profile || self.coverage
+    }
+}
 
 /// Configuration parameters for a cairo run
 #[derive(Debug)]
@@ -79,6 +123,18 @@ pub struct Cairo1RunConfig<'a> {
     pub finalize_builtins: bool,
     /// Appends the return and input values to the output segment. This is performed by default when running in proof_mode
     pub append_return_values: bool,
+    /// Resource budget of the run
+    pub limits: RunLimits,
+    /// Data to collect from the trace of the run
+    pub instrumentation: Instrumentation,
+    /// Step through the run from an interactive prompt on stdin/stderr
+    pub debug: bool,
+    /// Serve the oracle calls from these mocks instead of the oracle servers
+    pub mocks: Option<&'a [OracleMock]>,
+    /// ID of the run, sent to the oracle servers with every request
+    pub run_id: Option<&'a str>,
+    /// Gas given to the function if it takes the gas builtin, a large amount by default
+    pub available_gas: Option<usize>,
 }
 
 impl Default for Cairo1RunConfig<'_> {
@@ -92,6 +148,12 @@ impl Default for Cairo1RunConfig<'_> {
             proof_mode: false,
             finalize_builtins: false,
             append_return_values: false,
+            limits: RunLimits::default(),
+            instrumentation: Instrumentation::default(),
+            debug: false,
+            mocks: None,
+            run_id: None,
+            available_gas: None,
         }
     }
 }
@@ -104,30 +166,154 @@ impl Cairo1RunConfig<'_> {
     }
 }
 
+/// A Sierra program compiled to CASM, whose functions can be run many times without recompiling it
+pub struct PreparedProgram {
+    pub sierra_program: SierraProgram,
+    pub compiled_program: CompiledProgram,
+}
+
+impl PreparedProgram {
+    /// Compiles the program, or loads it from `cache_dir` if it was compiled before
+    pub fn new(sierra_program: SierraProgram, cache_dir: Option<&Path>) -> Result<Self, Error> {
+        let cached = cache_dir.and_then(|dir| CompiledProgram::load_cached(dir, &sierra_program));
+        let compiled_program = match cached {
+            Some(compiled_program) => compiled_program,
+            None => {
+                let compiled_program = compile_casm(&sierra_program)?;
+                if let Some(dir) = cache_dir {
+                    // Failing to cache the program only costs compiling it again on the next run
+                    let _ = compiled_program.store_cached(dir, &sierra_program);
+                }
+                compiled_program
+            }
+        };
+        Ok(Self {
+            sierra_program,
+            compiled_program,
+        })
+    }
+}
+
+fn compile_casm(sierra_program: &SierraProgram) -> Result<CompiledProgram, Error> {
+    // The gas costs can only be computed for programs withdrawing gas, compiled with `enable-gas`
+    let gas_usage_check = withdraws_gas(sierra_program);
+    let metadata = if gas_usage_check {
+        calc_metadata(sierra_program, MetadataComputationConfig::default())?
+    } else {
+        calc_metadata_ap_change_only(sierra_program)?
+    };
+    let config = SierraToCasmConfig {
+        gas_usage_check,
+        max_bytecode_size: usize::MAX,
+    };
+    let casm_program =
+        cairo_lang_sierra_to_casm::compiler::compile(sierra_program, &metadata, config)?;
+    let function_gas_costs = gas_usage_check.then(|| {
+        metadata
+            .gas_info
+            .function_costs
+            .iter()
+            .map(|(function_id, costs)| {
+                let cost: usize = costs
+                    .iter()
+                    .map(|(token_type, count)| {
+                        (*count).into_or_panic::<usize>() * token_gas_cost(*token_type)
+                    })
+                    .sum();
+                (function_id.id, cost)
+            })
+            .collect()
+    });
+    Ok(CompiledProgram {
+        function_gas_costs,
+        ..CompiledProgram::new(&casm_program)
+    })
+}
+
+// Returns true if the program withdraws gas, needing the gas costs of its functions
+fn withdraws_gas(sierra_program: &SierraProgram) -> bool {
+    sierra_program.libfunc_declarations.iter().any(|libfunc| {
+        let generic_id = &libfunc.long_id.generic_id.0;
+        generic_id == "withdraw_gas" || generic_id == "withdraw_gas_all"
+    })
+}
+
 // Runs a Cairo 1 program
-// Returns the runner & VM after execution + the return values
+// Returns the runner & VM after execution + the return values + the execution report
+#[allow(clippy::type_complexity)]
 pub fn cairo_run_program(
     sierra_program: &SierraProgram,
     cairo_run_config: Cairo1RunConfig,
     configuration: &Configuration,
     entry_func_name: &str,
     schema: &Schema,
-) -> Result<(CairoRunner, Vec<MaybeRelocatable>, Option<String>), Error> {
-    let metadata = calc_metadata_ap_change_only(sierra_program)
-        .map_err(|_| VirtualMachineError::Unexpected)?;
+) -> Result<(CairoRunner, Vec<MaybeRelocatable>, Option<String>, ExecutionReport), Error> {
+    let compiled_program = compile_casm(sierra_program)?;
+    run_compiled_program(
+        sierra_program,
+        &compiled_program,
+        cairo_run_config,
+        configuration,
+        entry_func_name,
+        schema,
+    )
+}
+
+// Runs a function of a prepared program, like `cairo_run_program`
+#[allow(clippy::type_complexity)]
+pub fn cairo_run_prepared(
+    prepared: &PreparedProgram,
+    cairo_run_config: Cairo1RunConfig,
+    configuration: &Configuration,
+    entry_func_name: &str,
+    schema: &Schema,
+) -> Result<(CairoRunner, Vec<MaybeRelocatable>, Option<String>, ExecutionReport), Error> {
+    let run_id = cairo_run_config.run_id.unwrap_or("-");
+    debug!("run {run_id}: running {entry_func_name}");
+    let result = run_compiled_program(
+        &prepared.sierra_program,
+        &prepared.compiled_program,
+        cairo_run_config,
+        configuration,
+        entry_func_name,
+        schema,
+    );
+    match &result {
+        Ok((_, _, _, report)) => debug!(
+            "run {run_id}: {entry_func_name} finished after {} steps and {} oracle calls",
+            report.n_steps, report.oracle_calls.count
+        ),
+        Err(err) => debug!("run {run_id}: {entry_func_name} failed: {err}"),
+    }
+    result
+}
+
+#[allow(clippy::type_complexity)]
+fn run_compiled_program(
+    sierra_program: &SierraProgram,
+    compiled_program: &CompiledProgram,
+    cairo_run_config: Cairo1RunConfig,
+    configuration: &Configuration,
+    entry_func_name: &str,
+    schema: &Schema,
+) -> Result<(CairoRunner, Vec<MaybeRelocatable>, Option<String>, ExecutionReport), Error> {
     let sierra_program_registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(sierra_program)?;
     let type_sizes =
         get_type_size_map(sierra_program, &sierra_program_registry).unwrap_or_default();
-    let config = SierraToCasmConfig {
-        gas_usage_check: false,
-        max_bytecode_size: usize::MAX,
-    };
-    let casm_program =
-        cairo_lang_sierra_to_casm::compiler::compile(sierra_program, &metadata, config)?;
 
     let main_func = find_function(sierra_program, entry_func_name)?;
 
-    let initial_gas = 9999999999999_usize;
+    // The gas the function requires upfront is withdrawn before calling it
+    let available_gas = cairo_run_config
+        .available_gas
+        .unwrap_or(DEFAULT_AVAILABLE_GAS);
+    let required_gas = compiled_program.required_gas(&main_func.id);
+    let initial_gas = available_gas
+        .checked_sub(required_gas)
+        .ok_or(Error::NotEnoughGas {
+            required: required_gas,
+            available: available_gas,
+        })?;
 
     // Fetch return type data
     let return_type_id = match main_func.signature.ret_types.last() {
@@ -141,28 +327,54 @@ pub fn cairo_run_program(
         _ => None,
     };
 
-    if cairo_run_config.copy_to_output()
-        && !check_only_array_felt_input_type(
-            &main_func.signature.param_types,
-            &sierra_program_registry,
+    // Typed parameters are loaded from the serialized arguments, while a single `Array<felt252>`
+    // or `Span<felt252>` parameter receives them as is
+    let param_types = user_param_types(&main_func.signature.param_types, &sierra_program_registry)?;
+    let param_layouts = if takes_serialized_args(&param_types) {
+        None
+    } else {
+        Some(
+            param_types
+                .iter()
+                .map(|ty| ValueLayout::new(ty, &sierra_program_registry))
+                .collect::<Result<Vec<_>, _>>()?,
         )
-    {
-        return Err(Error::IlegalInputValue);
     };
-    if cairo_run_config.copy_to_output()
+    let laid_out_args;
+    let cairo_run_config = match &param_layouts {
+        Some(layouts) => {
+            laid_out_args = layout_args(cairo_run_config.args, layouts)?;
+            Cairo1RunConfig {
+                args: &laid_out_args,
+                ..cairo_run_config
+            }
+        }
+        None => cairo_run_config,
+    };
+    // Return values other than `Array<felt252>` are serialized into the output segment by the entry code
+    let return_layout = if cairo_run_config.copy_to_output()
         && !check_only_array_felt_return_type(return_type_id, &sierra_program_registry)
     {
-        return Err(Error::IlegalReturnValue);
+        Some(
+            match result_inner_type(return_type_id, &sierra_program_registry).or(return_type_id) {
+                Some(ty) => ValueLayout::new(ty, &sierra_program_registry)?,
+                None => ValueLayout::Flat(0),
+            },
+        )
+    } else {
+        None
     };
 
     // Modified entry code to be compatible with custom cairo1 Proof Mode.
     // This adds code that's needed for dictionaries, adjusts ap for builtin pointers, adds initial gas for the gas builtin if needed, and sets up other necessary code for cairo1
     let (entry_code, builtins) = create_entry_code(
         &sierra_program_registry,
-        &casm_program,
+        compiled_program,
         &type_sizes,
         main_func,
         &cairo_run_config,
+        param_layouts.as_deref(),
+        return_layout.as_ref(),
     )?;
 
     // Fetch return type data
@@ -176,26 +388,43 @@ pub fn cairo_run_program(
 
     // This is the program we are actually running/proving
     // With (embedded proof mode), cairo1 header and the libfunc footer
+    let entry_instructions = entry_code
+        .instructions
+        .iter()
+        .map(CompiledInstruction::from)
+        .collect_vec();
+    let footer_instructions = libfunc_footer
+        .iter()
+        .map(CompiledInstruction::from)
+        .collect_vec();
     let instructions = chain!(
-        entry_code.instructions.iter(),
-        casm_program.instructions.iter(),
-        libfunc_footer.iter(),
+        entry_instructions.iter(),
+        compiled_program.instructions.iter(),
+        footer_instructions.iter(),
     );
 
     let (processor_hints, program_hints) = build_hints_vec(instructions.clone());
 
+    let run_resources = cairo_run_config
+        .limits
+        .max_steps
+        .map(RunResources::new)
+        .unwrap_or_default();
     let hint_processor = Cairo1HintProcessor::new(
         &processor_hints,
-        RunResources::default(),
+        run_resources,
         cairo_run_config.copy_to_output(),
     );
-    let mut hint_processor = Rpc1HintProcessor::new(hint_processor, configuration)?;
+    let mut hint_processor =
+        Rpc1HintProcessor::new(hint_processor, configuration, cairo_run_config.limits)?
+            .with_mocks(cairo_run_config.mocks)
+            .with_run_id(cairo_run_config.run_id);
 
     let data: Vec<MaybeRelocatable> = instructions
-        .flat_map(|inst| inst.assemble().encode())
-        .map(|x| Felt252::from(&x))
+        .flat_map(|inst| inst.encoded.iter().copied())
         .map(MaybeRelocatable::from)
         .collect();
+    let program_size = data.len();
 
     let program = if cairo_run_config.proof_mode {
         Program::new_for_proof(
@@ -238,29 +467,102 @@ pub fn cairo_run_program(
         &program,
         cairo_run_config.layout,
         runner_mode,
-        cairo_run_config.trace_enabled,
+        cairo_run_config.trace_enabled || cairo_run_config.instrumentation.needs_trace(),
     )?;
     let end = runner.initialize(cairo_run_config.proof_mode)?;
     load_arguments(&mut runner, &cairo_run_config, main_func, initial_gas)?;
+    if compiled_program.function_gas_costs.is_some() {
+        write_builtin_costs(&mut runner.vm, program_size)?;
+    }
+    let profiler = Profiler::new(sierra_program, compiled_program, entry_code.current_code_offset);
 
     // Run it until the end / infinite loop in proof_mode
-    runner.run_until_pc(end, &mut hint_processor)?;
-    if cairo_run_config.proof_mode {
-        runner.run_for_steps(1, &mut hint_processor)?;
+    let stepper = Stepper::new(
+        processor_hints.iter().map(|(offset, _)| *offset),
+        &hint_processor,
+    )?;
+    let run_result = if cairo_run_config.debug {
+        Debugger::new(&profiler, io::stdin().lock(), io::stderr()).run_until_pc(
+            &mut runner,
+            end,
+            &stepper,
+            &mut hint_processor,
+        )
+    } else {
+        stepper
+            .run_until_pc(&mut runner, end, &mut hint_processor)
+            .map_err(Error::from)
+    }
+    .and_then(|_| {
+        if cairo_run_config.proof_mode {
+            Ok(stepper.step(&mut runner, &mut hint_processor)?)
+        } else {
+            Ok(())
+        }
+    });
+
+    // Report exceeded limits before any other error, as they are usually the cause of it
+    let memory_cells = used_memory_cells(&mut runner.vm);
+    let exceeded_limit = hint_processor.exceeded_limit().or_else(|| {
+        cairo_run_config
+            .limits
+            .max_memory_cells
+            .filter(|max| memory_cells > *max)
+            .map(ResourceLimit::MemoryCells)
+    });
+    if let Some(limit) = exceeded_limit {
+        return Err(Error::ResourceLimitExceeded {
+            limit,
+            steps: runner.vm.get_current_step(),
+            memory_cells,
+            elapsed: hint_processor.elapsed(),
+        });
     }
+    run_result?;
+
+    // The returned gas is only found on the stack when the return values were not copied to the output
+    let gas_usage = if cairo_run_config.copy_to_output() {
+        None
+    } else {
+        remaining_gas(
+            &runner.vm,
+            &main_func.signature.ret_types,
+            &type_sizes,
+            builtin_count,
+        )
+        .map(|remaining_gas| available_gas.saturating_sub(remaining_gas))
+    };
 
     runner.end_run(false, false, &mut hint_processor)?;
 
     let result_inner_type_size =
         result_inner_type_size(return_type_id, &sierra_program_registry, &type_sizes);
     // Fetch return values
-    let return_values = fetch_return_values(
+    let return_values = match fetch_return_values(
         return_type_size,
         result_inner_type_size,
         &runner.vm,
         builtin_count,
         cairo_run_config.copy_to_output(),
-    )?;
+    ) {
+        Err(Error::RunPanic { panic_data, .. }) => {
+            // Relocate so the report of the panicked run can still be built from its trace
+            runner.relocate(true)?;
+            let report = execution_report(
+                &runner,
+                &mut hint_processor,
+                cairo_run_config.instrumentation,
+                &profiler,
+                compiled_program,
+                gas_usage,
+            )?;
+            return Err(Error::RunPanic {
+                panic_data,
+                report: Some(Box::new(report)),
+            });
+        }
+        return_values => return_values?,
+    };
 
     let serialized_output = if cairo_run_config.serialize_output {
         if cairo_run_config.copy_to_output() {
@@ -327,12 +629,44 @@ pub fn cairo_run_program(
 
     runner.relocate(true)?;
 
-    Ok((runner, return_values, serialized_output))
+    let report = execution_report(
+        &runner,
+        &mut hint_processor,
+        cairo_run_config.instrumentation,
+        &profiler,
+        compiled_program,
+        gas_usage,
+    )?;
+
+    Ok((runner, return_values, serialized_output, report))
+}
+
+/// Builds the report of a finished and relocated run.
+fn execution_report(
+    runner: &CairoRunner,
+    hint_processor: &mut Rpc1HintProcessor,
+    instrumentation: Instrumentation,
+    profiler: &Profiler,
+    compiled_program: &CompiledProgram,
+    gas_usage: Option<usize>,
+) -> Result<ExecutionReport, Error> {
+    let trace = runner.relocated_trace.as_deref().unwrap_or_default();
+    let profile = instrumentation.profile.then(|| profiler.profile(trace));
+    let coverage = instrumentation
+        .coverage
+        .then(|| Coverage::new(profiler, compiled_program, trace));
+    ExecutionReport::new(
+        runner,
+        &hint_processor.take_oracle_calls(),
+        profile,
+        coverage,
+        gas_usage,
+    )
 }
 
 #[allow(clippy::type_complexity)]
 fn build_hints_vec<'b>(
-    instructions: impl Iterator<Item = &'b Instruction>,
+    instructions: impl Iterator<Item = &'b CompiledInstruction>,
 ) -> (Vec<(usize, Vec<Hint>)>, HashMap<usize, Vec<HintParams>>) {
     let mut hints: Vec<(usize, Vec<Hint>)> = Vec::new();
     let mut program_hints: HashMap<usize, Vec<HintParams>> = HashMap::new();
@@ -354,27 +688,90 @@ fn build_hints_vec<'b>(
                 }],
             );
         }
-        hint_offset += instruction.body.op_size();
+        hint_offset += instruction.size();
     }
     (hints, program_hints)
 }
 
-/// Finds first function ending with `name_suffix`.
-fn find_function<'a>(
+/// Finds the function named `name`, or whose last path segments are `name`.
+pub(crate) fn find_function<'a>(
     sierra_program: &'a SierraProgram,
-    name_suffix: &'a str,
-) -> Result<&'a Function, RunnerError> {
-    sierra_program
+    name: &str,
+) -> Result<&'a Function, Error> {
+    let matches = sierra_program
         .funcs
         .iter()
-        .find(|f| {
-            if let Some(name) = &f.id.debug_name {
-                name.ends_with(name_suffix)
-            } else {
-                false
-            }
+        .filter(|f| {
+            f.id.debug_name
+                .as_ref()
+                .is_some_and(|debug_name| function_matches(debug_name, name))
+        })
+        .collect_vec();
+    // A full path always wins over the functions it is a suffix of
+    if let Some(function) = matches
+        .iter()
+        .copied()
+        .find(|f| f.id.debug_name.as_deref() == Some(name.trim_start_matches("::")))
+    {
+        return Ok(function);
+    }
+    match matches[..] {
+        [function] => Ok(function),
+        [] => Err(Error::MissingFunction(name.to_string())),
+        _ => Err(Error::AmbiguousFunction {
+            name: name.to_string(),
+            matches: matches.iter().map(|f| f.id.to_string()).collect(),
+        }),
+    }
+}
+
+/// Returns true if `pattern` is the function's full name or its last path segments.
+pub(crate) fn function_matches(name: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_start_matches("::");
+    name == pattern || name.ends_with(&format!("::{pattern}"))
+}
+
+/// Signature of a Sierra function, without its implicit arguments (builtins, gas, system).
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionSignature {
+    pub name: String,
+    pub params: Vec<String>,
+    pub ret_types: Vec<String>,
+}
+
+/// Lists the functions of the Sierra program that could be used as entry points.
+pub fn list_functions(sierra_program: &SierraProgram) -> Result<Vec<FunctionSignature>, Error> {
+    let sierra_program_registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(sierra_program)?;
+    let explicit_types = |types: &[ConcreteTypeId]| {
+        types
+            .iter()
+            .filter(|ty| {
+                get_info(&sierra_program_registry, ty)
+                    .is_some_and(|info| !is_implicit_generic_id(&info.long_id.generic_id))
+            })
+            .map(|ty| ty.to_string())
+            .collect_vec()
+    };
+
+    Ok(sierra_program
+        .funcs
+        .iter()
+        .filter_map(|f| {
+            f.id.debug_name.as_ref().map(|name| FunctionSignature {
+                name: name.to_string(),
+                params: explicit_types(&f.signature.param_types),
+                ret_types: explicit_types(&f.signature.ret_types),
+            })
         })
-        .ok_or_else(|| RunnerError::MissingMain)
+        .collect())
+}
+
+/// Returns the number of memory cells used so far by the run.
+pub(crate) fn used_memory_cells(vm: &mut VirtualMachine) -> usize {
+    let used_cells = vm.segments.compute_effective_sizes().iter().sum();
+    // The sizes are cached and would be stale once the run continues
+    vm.segments.segment_used_sizes = None;
+    used_cells
 }
 
 /// Creates a list of instructions that will be appended to the program's bytecode.
@@ -524,10 +921,12 @@ fn load_arguments(
 /// function, as well as the builtins required to execute the program.
 fn create_entry_code(
     sierra_program_registry: &ProgramRegistry<CoreType, CoreLibfunc>,
-    casm_program: &CairoProgram,
+    compiled_program: &CompiledProgram,
     type_sizes: &UnorderedHashMap<ConcreteTypeId, i16>,
     func: &Function,
     config: &Cairo1RunConfig,
+    param_layouts: Option<&[ValueLayout]>,
+    return_layout: Option<&ValueLayout>,
 ) -> Result<(CasmContext, Vec<BuiltinName>), Error> {
     let copy_to_output_builtin = config.copy_to_output();
     let signature = &func.signature;
@@ -672,56 +1071,74 @@ fn create_entry_code(
             .rev()
             .map(|i| ctx.add_var(CellExpression::Deref(deref!([ap - i]))))
             .collect_vec();
-        let (array_start_ptr, array_end_ptr) = if is_panic_result(signature.ret_types.last()) {
-            // Write panic flag value
-            let panic_flag = outputs[0];
+        if let Some(return_layout) = return_layout {
+            write_return_value(
+                &mut ctx,
+                output_ptr,
+                &outputs,
+                return_layout,
+                is_panic_result(signature.ret_types.last()),
+            );
+        } else {
+            let (array_start_ptr, array_end_ptr) = if is_panic_result(signature.ret_types.last()) {
+                // Write panic flag value
+                let panic_flag = outputs[0];
+                casm_build_extend! {ctx,
+                    assert panic_flag = *(output_ptr++);
+                };
+                // If the run did panic, these will point to the panic data
+                (outputs[1], outputs[2])
+            } else {
+                (outputs[0], outputs[1])
+            };
             casm_build_extend! {ctx,
-                assert panic_flag = *(output_ptr++);
+                // Calculate size of array and write it into the output segment
+                tempvar array_size = array_end_ptr - array_start_ptr;
+                assert array_size = *(output_ptr++);
+                // Create loop variables
+                tempvar remaining_elements = array_size;
+                tempvar array_ptr = array_start_ptr;
+                tempvar write_ptr = output_ptr;
+                // Enter copying loop
+                rescope{remaining_elements = remaining_elements, array_ptr = array_ptr, write_ptr = write_ptr};
+                jump CopyOutputArray if remaining_elements != 0;
+                jump EndOutputCopy;
+
+                // Main Loop
+                CopyOutputArray:
+                #{steps = 0;}
+                // Write array value into output segment
+                tempvar val = *(array_ptr++);
+                assert val = *(write_ptr++);
+                const one = 1;
+                // Create loop variables
+                tempvar new_remaining_elements = remaining_elements - one;
+                tempvar new_array_ptr = array_ptr;
+                tempvar new_write_ptr = write_ptr;
+                // Continue the loop
+                rescope{remaining_elements = new_remaining_elements, array_ptr = new_array_ptr, write_ptr = new_write_ptr};
+                jump CopyOutputArray if remaining_elements != 0;
+
+                EndOutputCopy:
             };
-            // If the run did panic, these will point to the panic data
-            (outputs[1], outputs[2])
-        } else {
-            (outputs[0], outputs[1])
-        };
-        casm_build_extend! {ctx,
-            // Calculate size of array and write it into the output segment
-            tempvar array_size = array_end_ptr - array_start_ptr;
-            assert array_size = *(output_ptr++);
-            // Create loop variables
-            tempvar remaining_elements = array_size;
-            tempvar array_ptr = array_start_ptr;
-            tempvar write_ptr = output_ptr;
-            // Enter copying loop
-            rescope{remaining_elements = remaining_elements, array_ptr = array_ptr, write_ptr = write_ptr};
-            jump CopyOutputArray if remaining_elements != 0;
-            jump EndOutputCopy;
-
-            // Main Loop
-            CopyOutputArray:
-            #{steps = 0;}
-            // Write array value into output segment
-            tempvar val = *(array_ptr++);
-            assert val = *(write_ptr++);
-            const one = 1;
-            // Create loop variables
-            tempvar new_remaining_elements = remaining_elements - one;
-            tempvar new_array_ptr = array_ptr;
-            tempvar new_write_ptr = write_ptr;
-            // Continue the loop
-            rescope{remaining_elements = new_remaining_elements, array_ptr = new_array_ptr, write_ptr = new_write_ptr};
-            jump CopyOutputArray if remaining_elements != 0;
-
-            EndOutputCopy:
-        };
-        if !actual_args_size.is_zero() {
+        }
+        // len(builtins - output) + len(builtins) + if segment_arena: segment_arena_ptr + info_ptr + 0 + (segment_arena_ptr + 3) + (gas_builtin)
+        let offset = (2 * builtins.len() - 1
+            + 4 * got_segment_arena as usize
+            + got_gas_builtin as usize) as i16;
+        if let Some(param_layouts) = param_layouts.filter(|layouts| !layouts.is_empty()) {
+            // Serialize the typed input values into the output segment
+            // The last instruction will write the last output ptr so we can find it in [ap - 1]
+            let output_ptr = ctx.add_var(CellExpression::Deref(deref!([ap - 1])));
+            let args = (0..expected_arguments_size)
+                .map(|i| ctx.add_var(CellExpression::Deref(deref!([fp + offset + i]))))
+                .collect_vec();
+            write_arguments(&mut ctx, output_ptr, &args, param_layouts);
+        } else if !actual_args_size.is_zero() {
             // Serialize the input values into the output segment
             // We lost the output_ptr var after re-scoping, so we need to create it again
             // The last instruction will write the last output ptr so we can find it in [ap - 1]
             let output_ptr = ctx.add_var(CellExpression::Deref(deref!([ap - 1])));
-            // len(builtins - output) + len(builtins) + if segment_arena: segment_arena_ptr + info_ptr + 0 + (segment_arena_ptr + 3) + (gas_builtin)
-            let offset = (2 * builtins.len() - 1
-                + 4 * got_segment_arena as usize
-                + got_gas_builtin as usize) as i16;
             let array_start_ptr = ctx.add_var(CellExpression::Deref(deref!([fp + offset])));
             let array_end_ptr = ctx.add_var(CellExpression::Deref(deref!([fp + offset + 1])));
             casm_build_extend! {ctx,
@@ -849,8 +1266,7 @@ fn create_entry_code(
         panic!("Expected call instruction");
     };
     inst.target = deref_or_immediate!(
-        post_call_size
-            + casm_program.debug_info.sierra_statement_info[func.entry_point.0].start_offset
+        post_call_size + compiled_program.statement_offsets[func.entry_point.0]
     );
     Ok((
         CasmContext {
@@ -906,31 +1322,27 @@ fn get_function_builtins(
     (builtins, builtin_offset)
 }
 
-// Checks that the program input (if present) is of type Array<Felt252>
-fn check_only_array_felt_input_type(
-    params: &[ConcreteTypeId],
+// Returns the types of the program input, without the implicit arguments (builtins, gas)
+fn user_param_types<'a>(
+    params: &'a [ConcreteTypeId],
     sierra_program_registry: &ProgramRegistry<CoreType, CoreLibfunc>,
-) -> bool {
-    // Filter implicit arguments (builtins, gas)
-    let arg_types = params
-        .iter()
-        .filter(|ty| {
-            let info = get_info(sierra_program_registry, ty).unwrap();
-            let generic_ty = &info.long_id.generic_id;
-            !is_implicit_generic_id(generic_ty)
-        })
-        .collect_vec();
-    if arg_types.is_empty() {
-        // No inputs
-        true
-    } else if arg_types.len() == 1 {
-        arg_types[0]
-            .debug_name
-            .as_ref()
-            .is_some_and(|name| name == "Array<felt252>")
-    } else {
-        false
+) -> Result<Vec<&'a ConcreteTypeId>, Error> {
+    let mut arg_types = vec![];
+    for ty in params {
+        let info = get_info(sierra_program_registry, ty)
+            .ok_or_else(|| Error::NoInfoForType(ty.clone()))?;
+        if !is_implicit_generic_id(&info.long_id.generic_id) {
+            arg_types.push(ty);
+        }
     }
+    Ok(arg_types)
+}
+
+// Checks that the program input is a single Array<Felt252> or Span<Felt252>, receiving the serialized arguments
+fn takes_serialized_args(arg_types: &[&ConcreteTypeId]) -> bool {
+    matches!(arg_types, [ty] if ty.debug_name.as_ref().is_some_and(|name| {
+        name == "Array<felt252>" || name == "core::array::Span::<core::felt252>"
+    }))
 }
 
 // Returns true if the generic id corresponds to an implicit argument (aka a builtin, gas, or system type)
@@ -978,7 +1390,7 @@ fn check_only_array_felt_return_type(
     }
 }
 
-fn is_panic_result(return_type_id: Option<&ConcreteTypeId>) -> bool {
+pub(crate) fn is_panic_result(return_type_id: Option<&ConcreteTypeId>) -> bool {
     return_type_id
         .map(|id| {
             id.debug_name
@@ -1040,6 +1452,8 @@ fn fetch_return_values(
         // In this case we will find the serialized return value in the format:
         // [*panic_flag, array_len, array[0], array[1],..., array[array_len-1]]
         // *: If the return value is a PanicResult
+        // The array is the returned Array<felt252>, the Serde serialization of any other return
+        // value, or the panic data
 
         // Output Builtin will always be on segment 2
         let return_values =
@@ -1060,12 +1474,14 @@ fn fetch_return_values(
         let return_values = &return_values[0..output_len];
         // Return Ok or Err based on panic_flag
         if panic_flag {
-            return Err(Error::RunPanic(
-                return_values
+            return Err(Error::RunPanic {
+                // Without the length of the panic data, as when read from the stack
+                panic_data: return_values[1..]
                     .iter()
                     .map(|mr| mr.get_int().unwrap_or_default())
                     .collect_vec(),
-            ));
+                report: None,
+            });
         } else {
             return Ok(return_values.to_vec());
         }
@@ -1094,9 +1510,10 @@ fn fetch_return_values(
                 panic_data_start,
                 (panic_data_end - panic_data_start).map_err(VirtualMachineError::Math)?,
             )?;
-            return Err(Error::RunPanic(
-                panic_data.iter().map(|c| *c.as_ref()).collect(),
-            ));
+            return Err(Error::RunPanic {
+                panic_data: panic_data.iter().map(|c| *c.as_ref()).collect(),
+                report: None,
+            });
         } else {
             if return_values.len() < inner_type_size as usize {
                 return Err(Error::FailedToExtractReturnValues);
@@ -1108,6 +1525,45 @@ fn fetch_return_values(
     Ok(return_values)
 }
 
+// Returns the gas left by the function, returned below the final builtins written by the entry code
+fn remaining_gas(
+    vm: &VirtualMachine,
+    main_ret_types: &[ConcreteTypeId],
+    type_sizes: &UnorderedHashMap<ConcreteTypeId, i16>,
+    builtin_count: i16,
+) -> Option<usize> {
+    let gas_index = main_ret_types
+        .iter()
+        .position(|ty| ty.debug_name.as_ref().is_some_and(|n| n == "GasBuiltin"))?;
+    // The size of the gas builtin and of the values returned after it
+    let offset: i16 = main_ret_types[gas_index..]
+        .iter()
+        .map(|ty| type_sizes.get(ty).copied().unwrap_or_default())
+        .sum();
+    let gas = vm
+        .get_integer((vm.get_ap() - (offset + builtin_count) as usize).ok()?)
+        .ok()?;
+    gas.to_usize()
+}
+
+// Writes the table of the builtin gas costs, which the code withdrawing gas finds through a pointer
+// written right after the program, as `cairo_lang_runner::initialize_vm` does
+fn write_builtin_costs(vm: &mut VirtualMachine, program_size: usize) -> Result<(), Error> {
+    let builtin_costs = vm.add_memory_segment();
+    for token_type in CostTokenType::iter_precost() {
+        vm.insert_value(
+            (builtin_costs + token_type.offset_in_builtin_costs() as usize)
+                .map_err(VirtualMachineError::Math)?,
+            Felt252::from(token_gas_cost(*token_type)),
+        )?;
+    }
+    vm.insert_value(
+        (vm.get_pc() + program_size).map_err(VirtualMachineError::Math)?,
+        builtin_costs,
+    )?;
+    Ok(())
+}
+
 // Calculates builtins' final_stack setting each stop_ptr
 // Calling this function is a must if either air_public_input or cairo_pie are needed
 fn finalize_builtins(
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cairo_lang_casm::{hints::Hint, instructions::Instruction};
+use cairo_lang_sierra::ids::FunctionId;
+use cairo_lang_sierra::program::Program as SierraProgram;
+use cairo_lang_sierra_to_casm::compiler::CairoProgram;
+use cairo_vm::Felt252;
+use serde::{Deserialize, Serialize};
+
+use crate::bundle::sierra_program_hash;
+
+/// Directory of the compiled programs cache, next to the Sierra programs in the target dir.
+pub const CASM_CACHE_DIR: &str = "casm-cache";
+
+/// An instruction assembled to the words of the program, with its hints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledInstruction {
+    pub encoded: Vec<Felt252>,
+    pub hints: Vec<Hint>,
+}
+
+impl CompiledInstruction {
+    /// Size of the instruction in the program.
+    pub fn size(&self) -> usize {
+        self.encoded.len()
+    }
+}
+
+impl From<&Instruction> for CompiledInstruction {
+    fn from(instruction: &Instruction) -> Self {
+        Self {
+            encoded: instruction
+                .assemble()
+                .encode()
+                .iter()
+                .map(Felt252::from)
+                .collect(),
+            hints: instruction.hints.clone(),
+        }
+    }
+}
+
+/// The CASM of a Sierra program, holding what is needed to run it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledProgram {
+    pub instructions: Vec<CompiledInstruction>,
+    /// Start offset of each Sierra statement in the program.
+    pub statement_offsets: Vec<usize>,
+    /// Gas each function requires upfront, by the id of its Sierra function, if the program
+    /// withdraws gas.
+    #[serde(default)]
+    pub function_gas_costs: Option<HashMap<u64, usize>>,
+}
+
+impl CompiledProgram {
+    pub fn new(casm_program: &CairoProgram) -> Self {
+        Self {
+            instructions: casm_program
+                .instructions
+                .iter()
+                .map(CompiledInstruction::from)
+                .collect(),
+            statement_offsets: casm_program
+                .debug_info
+                .sierra_statement_info
+                .iter()
+                .map(|info| info.start_offset)
+                .collect(),
+            function_gas_costs: None,
+        }
+    }
+
+    /// Gas the function requires upfront, none if the program does not withdraw gas.
+    pub fn required_gas(&self, function_id: &FunctionId) -> usize {
+        self.function_gas_costs
+            .as_ref()
+            .and_then(|costs| costs.get(&function_id.id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Size of the program.
+    pub fn size(&self) -> usize {
+        self.instructions
+            .iter()
+            .map(CompiledInstruction::size)
+            .sum()
+    }
+
+    /// Loads the program compiled from `sierra_program` from the cache, if it was stored in it.
+    pub fn load_cached(cache_dir: &Path, sierra_program: &SierraProgram) -> Option<Self> {
+        let content = fs::read(cache_path(cache_dir, sierra_program)).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    /// Stores the program compiled from `sierra_program` in the cache.
+    pub fn store_cached(
+        &self,
+        cache_dir: &Path,
+        sierra_program: &SierraProgram,
+    ) -> std::io::Result<()> {
+        fs::create_dir_all(cache_dir)?;
+        let path = cache_path(cache_dir, sierra_program);
+        // Written then renamed, so concurrent runs never read a partially written program
+        let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+}
+
+/// Cached programs are keyed by the hash of their Sierra program and the version compiling them.
+fn cache_path(cache_dir: &Path, sierra_program: &SierraProgram) -> PathBuf {
+    cache_dir.join(format!(
+        "{}-{}.json",
+        sierra_program_hash(sierra_program),
+        env!("CARGO_PKG_VERSION")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cairo_lang_casm::casm;
+
+    #[test]
+    fn test_compiled_program_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let sierra_program = SierraProgram {
+            type_declarations: vec![],
+            libfunc_declarations: vec![],
+            statements: vec![],
+            funcs: vec![],
+        };
+        assert!(CompiledProgram::load_cached(dir.path(), &sierra_program).is_none());
+
+        let program = CompiledProgram {
+            instructions: vec![
+                CompiledInstruction::from(&casm!([ap + 0] = 5, ap++;).instructions[0]),
+                CompiledInstruction::from(&casm!(ret;).instructions[0]),
+            ],
+            statement_offsets: vec![0, 2],
+            function_gas_costs: None,
+        };
+        program.store_cached(dir.path(), &sierra_program).unwrap();
+
+        let cached = CompiledProgram::load_cached(dir.path(), &sierra_program).unwrap();
+        assert_eq!(cached.size(), 3);
+        assert_eq!(
+            cached.instructions[0].encoded,
+            program.instructions[0].encoded
+        );
+        assert_eq!(cached.statement_offsets, vec![0, 2]);
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::BTreeSet;
+
+use cairo_lang_casm::hints::{Hint, StarknetHint};
+use cairo_vm::vm::trace::trace_entry::RelocatedTraceEntry;
+
+use crate::compiled::CompiledProgram;
+use crate::profiler::Profiler;
+
+/// Sierra statements executed by a run.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// Statements executed at least once.
+    pub executed_statements: BTreeSet<usize>,
+    /// Statements calling an oracle, executed or not.
+    pub oracle_statements: BTreeSet<usize>,
+}
+
+impl Coverage {
+    pub(crate) fn new(
+        profiler: &Profiler,
+        compiled_program: &CompiledProgram,
+        trace: &[RelocatedTraceEntry],
+    ) -> Self {
+        let executed_statements = trace
+            .iter()
+            .filter_map(|entry| profiler.statement_at(entry.pc))
+            .collect();
+
+        let mut oracle_statements = BTreeSet::new();
+        let mut offset = 0;
+        for instruction in &compiled_program.instructions {
+            let calls_oracle = instruction
+                .hints
+                .iter()
+                .any(|hint| matches!(hint, Hint::Starknet(StarknetHint::Cheatcode { .. })));
+            if calls_oracle {
+                oracle_statements.extend(profiler.statement_at_offset(offset));
+            }
+            offset += instruction.size();
+        }
+
+        Self {
+            executed_statements,
+            oracle_statements,
+        }
+    }
+}
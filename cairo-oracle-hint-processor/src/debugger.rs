@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use cairo_vm::types::relocatable::Relocatable;
+use cairo_vm::vm::errors::vm_errors::VirtualMachineError;
+use cairo_vm::vm::runners::cairo_runner::{CairoRunner, ResourceTracker};
+use cairo_vm::vm::vm_core::VirtualMachine;
+
+use crate::cairo_run::function_matches;
+use crate::profiler::{Profiler, ENTRY_CODE};
+use crate::report::OracleCall;
+use crate::rpc_hint_processor::Rpc1HintProcessor;
+use crate::stepper::Stepper;
+use crate::Error;
+
+/// Maximum number of cells printed by the `frame` command.
+const MAX_FRAME_CELLS: usize = 32;
+
+/// Maximum number of frames printed by the `backtrace` command.
+const MAX_BACKTRACE_DEPTH: usize = 64;
+
+const HELP: &str = "\
+commands:
+  break <function>      pause when entering the Sierra functions matching <function>
+  delete <function>     remove the breakpoints matching <function>
+  breakpoints           list the breakpoints
+  step [n]              execute n instructions (default 1)
+  next                  run to the next Sierra statement of the current function
+  finish                run until the current function returns
+  continue              run until the next breakpoint or the end of the program
+  frame                 show the registers and memory of the current frame
+  backtrace             show the call stack
+  memory <seg>:<off> [n]  show n memory cells (default 8)
+  calls                 show the oracle calls made so far
+  quit                  abort the run";
+
+/// A command of the debugger prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Break(String),
+    Delete(String),
+    Breakpoints,
+    Step(usize),
+    Next,
+    Finish,
+    Continue,
+    Frame,
+    Backtrace,
+    Memory(Relocatable, usize),
+    Calls,
+    Help,
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("step");
+        let arg = words.next();
+        let count = |arg: Option<&str>, default: usize| {
+            arg.map_or(Ok(default), |n| {
+                n.parse::<usize>().map_err(|_| format!("invalid count: {n}"))
+            })
+        };
+        let function = |arg: Option<&str>| {
+            arg.map(str::to_string)
+                .ok_or_else(|| format!("usage: {command} <function>"))
+        };
+
+        match command {
+            "b" | "break" => function(arg).map(Command::Break),
+            "d" | "delete" => function(arg).map(Command::Delete),
+            "breakpoints" => Ok(Command::Breakpoints),
+            "s" | "step" => count(arg, 1).map(Command::Step),
+            "n" | "next" => Ok(Command::Next),
+            "f" | "finish" => Ok(Command::Finish),
+            "c" | "continue" => Ok(Command::Continue),
+            "frame" => Ok(Command::Frame),
+            "bt" | "backtrace" => Ok(Command::Backtrace),
+            "x" | "memory" => {
+                let address = arg.ok_or("usage: memory <segment>:<offset> [n]")?;
+                let (segment, offset) = address
+                    .split_once(':')
+                    .and_then(|(segment, offset)| {
+                        Some((segment.parse::<isize>().ok()?, offset.parse::<usize>().ok()?))
+                    })
+                    .ok_or_else(|| format!("invalid address: {address}"))?;
+                Ok(Command::Memory(
+                    Relocatable::from((segment, offset)),
+                    count(words.next(), 8)?,
+                ))
+            }
+            "calls" => Ok(Command::Calls),
+            "h" | "help" => Ok(Command::Help),
+            "q" | "quit" => Ok(Command::Quit),
+            _ => Err(format!("unknown command: {command}, type `help` for the commands")),
+        }
+    }
+}
+
+/// How execution resumes after the prompt.
+enum Resume {
+    /// Pause after the given number of instructions.
+    Step(usize),
+    /// Pause at the next statement of the frame, or when it returns.
+    Next { fp: Relocatable, statement: Option<usize> },
+    /// Pause when the frame returns.
+    Finish { fp: Relocatable },
+    /// Pause at the next breakpoint only.
+    Continue,
+}
+
+/// Interactive debugger stepping through a run while the wrapped hint processor serves the oracles.
+pub(crate) struct Debugger<'a, R, W> {
+    profiler: &'a Profiler<'a>,
+    input: R,
+    output: W,
+    /// Functions to pause in, by the program counter of their entry.
+    breakpoints: BTreeMap<usize, &'a str>,
+    /// Number of oracle calls already shown.
+    shown_calls: usize,
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W> {
+    pub(crate) fn new(profiler: &'a Profiler<'a>, input: R, output: W) -> Self {
+        Self {
+            profiler,
+            input,
+            output,
+            breakpoints: BTreeMap::new(),
+            shown_calls: 0,
+        }
+    }
+
+    /// Runs the program until `end`, pausing at the prompt from the first instruction.
+    ///
+    /// The run stops once a limit is exceeded, the caller reporting it.
+    pub(crate) fn run_until_pc(
+        &mut self,
+        runner: &mut CairoRunner,
+        end: Relocatable,
+        stepper: &Stepper,
+        hint_processor: &mut Rpc1HintProcessor,
+    ) -> Result<(), Error> {
+        writeln!(self.output, "{HELP}")?;
+        self.show_location(&runner.vm)?;
+        let mut resume = self.prompt(&runner.vm, hint_processor)?;
+
+        while runner.vm.get_pc() != end && !hint_processor.consumed() {
+            let step = stepper.step(runner, hint_processor);
+            self.show_oracle_calls(hint_processor.oracle_calls())?;
+            if let Err(error) = step {
+                // Let the failing frame be inspected before reporting the error
+                writeln!(self.output, "error: {error}")?;
+                self.show_location(&runner.vm)?;
+                self.prompt(&runner.vm, hint_processor)?;
+                return Err(error.into());
+            }
+            if self.should_pause(&mut resume, &runner.vm) {
+                self.show_location(&runner.vm)?;
+                resume = self.prompt(&runner.vm, hint_processor)?;
+            }
+        }
+
+        if runner.vm.get_pc() != end {
+            return Err(VirtualMachineError::UnfinishedExecution.into());
+        }
+        writeln!(
+            self.output,
+            "program finished after {} steps",
+            runner.vm.get_current_step()
+        )?;
+        Ok(())
+    }
+
+    /// Reads commands until one resumes the execution.
+    fn prompt(
+        &mut self,
+        vm: &VirtualMachine,
+        hint_processor: &Rpc1HintProcessor,
+    ) -> Result<Resume, Error> {
+        loop {
+            write!(self.output, "(agent-debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                // The input was closed, let the run finish
+                return Ok(Resume::Continue);
+            }
+
+            let command = match Command::parse(&line) {
+                Ok(command) => command,
+                Err(message) => {
+                    writeln!(self.output, "{message}")?;
+                    continue;
+                }
+            };
+            match command {
+                Command::Break(pattern) => self.add_breakpoints(&pattern)?,
+                Command::Delete(pattern) => {
+                    self.breakpoints
+                        .retain(|_, name| !function_matches(name, &pattern));
+                }
+                Command::Breakpoints => {
+                    for name in self.breakpoints.values() {
+                        writeln!(self.output, "  {name}")?;
+                    }
+                }
+                Command::Step(n) => return Ok(Resume::Step(n.max(1))),
+                Command::Next => {
+                    return Ok(Resume::Next {
+                        fp: vm.get_fp(),
+                        statement: self.statement_at(vm.get_pc()),
+                    })
+                }
+                Command::Finish => return Ok(Resume::Finish { fp: vm.get_fp() }),
+                Command::Continue => return Ok(Resume::Continue),
+                Command::Frame => self.show_frame(vm)?,
+                Command::Backtrace => self.show_backtrace(vm)?,
+                Command::Memory(address, n) => self.show_memory(vm, address, n)?,
+                Command::Calls => {
+                    for call in hint_processor.oracle_calls() {
+                        self.show_oracle_call(call)?;
+                    }
+                }
+                Command::Help => writeln!(self.output, "{HELP}")?,
+                Command::Quit => return Err(Error::DebuggerAborted),
+            }
+        }
+    }
+
+    fn should_pause(&self, resume: &mut Resume, vm: &VirtualMachine) -> bool {
+        let pc = vm.get_pc();
+        if pc.segment_index == 0 && self.breakpoints.contains_key(&relocated_pc(pc)) {
+            return true;
+        }
+        match resume {
+            Resume::Step(n) => {
+                *n -= 1;
+                *n == 0
+            }
+            // Frames live on the execution segment, a lower fp means the frame returned
+            Resume::Next { fp, statement } => {
+                vm.get_fp() < *fp
+                    || (vm.get_fp() == *fp && self.statement_at(pc) != *statement)
+            }
+            Resume::Finish { fp } => vm.get_fp() < *fp,
+            Resume::Continue => false,
+        }
+    }
+
+    fn add_breakpoints(&mut self, pattern: &str) -> Result<(), Error> {
+        let matching = self
+            .profiler
+            .function_entries()
+            .filter(|(name, _)| function_matches(name, pattern))
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            writeln!(self.output, "no function matches {pattern}")?;
+        }
+        for (name, pc) in matching {
+            writeln!(self.output, "breakpoint set in {name}")?;
+            self.breakpoints.insert(pc, name);
+        }
+        Ok(())
+    }
+
+    fn statement_at(&self, pc: Relocatable) -> Option<usize> {
+        self.profiler.statement_at(relocated_pc(pc))
+    }
+
+    fn function_at(&self, pc: Relocatable) -> &'a str {
+        self.profiler
+            .function_at(relocated_pc(pc))
+            .unwrap_or(ENTRY_CODE)
+    }
+
+    fn show_location(&mut self, vm: &VirtualMachine) -> Result<(), Error> {
+        let pc = vm.get_pc();
+        let statement = self
+            .statement_at(pc)
+            .map_or("-".to_string(), |statement| statement.to_string());
+        writeln!(
+            self.output,
+            "step {} in {} (statement {statement}, pc {pc})",
+            vm.get_current_step(),
+            self.function_at(pc)
+        )?;
+        Ok(())
+    }
+
+    fn show_frame(&mut self, vm: &VirtualMachine) -> Result<(), Error> {
+        let (pc, ap, fp) = (vm.get_pc(), vm.get_ap(), vm.get_fp());
+        writeln!(self.output, "{} pc={pc} ap={ap} fp={fp}", self.function_at(pc))?;
+        // The caller's fp and the return pc are stored right below the frame
+        let start = (fp - 2).unwrap_or(fp);
+        let len = (ap - start).unwrap_or_default();
+        self.show_memory(vm, start, len.min(MAX_FRAME_CELLS))?;
+        if len > MAX_FRAME_CELLS {
+            writeln!(self.output, "  ... {} more cells", len - MAX_FRAME_CELLS)?;
+        }
+        Ok(())
+    }
+
+    fn show_backtrace(&mut self, vm: &VirtualMachine) -> Result<(), Error> {
+        let mut pc = vm.get_pc();
+        let mut fp = vm.get_fp();
+        for depth in 0..MAX_BACKTRACE_DEPTH {
+            if self.profiler.function_at(relocated_pc(pc)).is_none() {
+                break;
+            }
+            writeln!(self.output, "#{depth} {} (pc {pc})", self.function_at(pc))?;
+            let caller = (fp - 2)
+                .and_then(|address| Ok((address, (fp - 1)?)))
+                .ok()
+                .and_then(|(fp_address, pc_address)| {
+                    Some((
+                        vm.get_relocatable(fp_address).ok()?,
+                        vm.get_relocatable(pc_address).ok()?,
+                    ))
+                });
+            match caller {
+                Some((caller_fp, return_pc)) => {
+                    fp = caller_fp;
+                    pc = return_pc;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn show_memory(
+        &mut self,
+        vm: &VirtualMachine,
+        start: Relocatable,
+        n: usize,
+    ) -> Result<(), Error> {
+        for i in 0..n {
+            let address = (start + i).map_err(VirtualMachineError::Math)?;
+            let value = vm
+                .get_maybe(&address)
+                .map_or("<unknown>".to_string(), |value| value.to_string());
+            writeln!(self.output, "  [{address}] {value}")?;
+        }
+        Ok(())
+    }
+
+    /// Shows the oracle calls made since the last time.
+    fn show_oracle_calls(&mut self, calls: &[OracleCall]) -> Result<(), Error> {
+        for call in &calls[self.shown_calls..] {
+            self.show_oracle_call(call)?;
+        }
+        self.shown_calls = calls.len();
+        Ok(())
+    }
+
+    fn show_oracle_call(&mut self, call: &OracleCall) -> Result<(), Error> {
+        let status = if call.success { "ok" } else { "failed" };
+        writeln!(
+            self.output,
+            "oracle call {} {status} in {:?}\n  request: {}",
+            call.selector, call.latency, call.request
+        )?;
+        if let Some(response) = &call.response {
+            writeln!(self.output, "  response: {response}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the program counter as relocated at the end of the run, the program segment starting at 1.
+fn relocated_pc(pc: Relocatable) -> usize {
+    1 + pc.offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("\n"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("s 10"), Ok(Command::Step(10)));
+        assert_eq!(
+            Command::parse("break ::main"),
+            Ok(Command::Break("::main".to_string()))
+        );
+        assert_eq!(
+            Command::parse("memory 1:4 2"),
+            Ok(Command::Memory(Relocatable::from((1, 4)), 2))
+        );
+        assert!(Command::parse("break").is_err());
+        assert!(Command::parse("memory 1").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+
+    #[test]
+    fn test_function_matches() {
+        assert!(function_matches("agent::oracle::fetch", "fetch"));
+        assert!(function_matches("agent::oracle::fetch", "oracle::fetch"));
+        assert!(function_matches("agent::main", "::main"));
+        assert!(!function_matches("agent::prefetch", "fetch"));
+    }
+}
@@ -1,18 +1,21 @@
+use std::fmt;
 use std::io;
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::Result;
 use bincode::enc::write::Writer;
 use cairo_io_serde::schema::Schema;
 use cairo_io_serde::FuncArgs;
 use cairo_lang_sierra::ids::ConcreteTypeId;
-use cairo_lang_sierra::program::Program as SierraProgram;
 use cairo_lang_sierra::program_registry::ProgramRegistryError;
 use cairo_lang_sierra_to_casm::compiler::CompilationError;
 use cairo_lang_sierra_to_casm::metadata::MetadataError;
 use cairo_proto_serde::configuration::Configuration;
-use cairo_run::Cairo1RunConfig;
+use cairo_run::{Cairo1RunConfig, Instrumentation, PreparedProgram, RunLimits};
+use mock::OracleMock;
+use report::ExecutionReport;
 use cairo_vm::air_public_input::PublicInputError;
 use cairo_vm::cairo_run::EncodeTraceError;
 use cairo_vm::types::errors::program_errors::ProgramError;
@@ -25,8 +28,18 @@ use cairo_vm::vm::runners::cairo_runner::CairoRunner;
 use cairo_vm::Felt252;
 use thiserror::Error;
 
+pub mod bundle;
 pub mod cairo_run;
+pub mod compiled;
+pub mod coverage;
+mod debugger;
+pub mod mock;
+pub mod profiler;
+pub mod report;
 pub mod rpc_hint_processor;
+mod stepper;
+pub mod telemetry;
+mod value_layout;
 
 mod hint_processor_utils;
 
@@ -58,8 +71,16 @@ pub enum Error {
     Program(#[from] ProgramError),
     #[error(transparent)]
     Memory(#[from] MemoryError),
-    #[error("Program panicked with {0:?}")]
-    RunPanic(Vec<Felt252>),
+    #[error("Program panicked with {panic_data:?}")]
+    RunPanic {
+        panic_data: Vec<Felt252>,
+        /// Resources consumed until the panic.
+        report: Option<Box<ExecutionReport>>,
+    },
+    #[error("Function {0} not found in the program")]
+    MissingFunction(String),
+    #[error("Function {name} is ambiguous, it matches {}", .matches.join(", "))]
+    AmbiguousFunction { name: String, matches: Vec<String> },
     #[error("Function signature has no return types")]
     NoRetTypesInSignature,
     #[error("No size for concrete type id: {0}")]
@@ -77,14 +98,45 @@ pub enum Error {
         param_index: usize,
         arg_index: usize,
     },
-    #[error("Only programs returning `Array<Felt252>` can be currently proven. Try serializing the final values before returning them")]
-    IlegalReturnValue,
-    #[error("Only programs with `Array<Felt252>` as an input can be currently proven. Try inputing the serialized version of the input and deserializing it on main")]
-    IlegalInputValue,
+    #[error("Type {0} of the function is not supported, only felts, integers, `bool`, structs and tuples of them, and arrays and spans of those are. Try serializing the value into an `Array<felt252>`")]
+    UnsupportedEntryPointType(String),
+    #[error("Invalid arguments: {0}")]
+    InvalidArguments(String),
     #[error("Configuration error: {0}")]
     ConfigError(String),
     #[error("Servers configuration file error: {0}")]
     ServersConfigFileError(String),
+    #[error("Run exceeded its {limit} after {steps} steps, {memory_cells} memory cells and {elapsed:?}")]
+    ResourceLimitExceeded {
+        limit: ResourceLimit,
+        steps: usize,
+        memory_cells: usize,
+        elapsed: Duration,
+    },
+    #[error("Function requires {required} gas to be called, only {available} is available")]
+    NotEnoughGas { required: usize, available: usize },
+    #[error("Run aborted from the debugger")]
+    DebuggerAborted,
+    #[error("Invalid run bundle: {0}")]
+    Bundle(String),
+}
+
+/// A resource budget a run can exceed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceLimit {
+    Steps(usize),
+    Timeout(Duration),
+    MemoryCells(usize),
+}
+
+impl fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceLimit::Steps(n) => write!(f, "step limit of {n}"),
+            ResourceLimit::Timeout(timeout) => write!(f, "timeout of {timeout:?}"),
+            ResourceLimit::MemoryCells(n) => write!(f, "memory budget of {n} cells"),
+        }
+    }
 }
 
 pub struct FileWriter {
@@ -120,35 +172,88 @@ impl FileWriter {
     }
 }
 
+/// Options of `run_1`: how the function is run and the artifacts of the run it writes.
+#[derive(Debug, Clone)]
+pub struct RunOptions<'a> {
+    pub layout: LayoutName,
+    pub trace_file: Option<PathBuf>,
+    pub memory_file: Option<PathBuf>,
+    pub cairo_pie_output: Option<PathBuf>,
+    pub air_public_input: Option<PathBuf>,
+    pub air_private_input: Option<PathBuf>,
+    pub proof_mode: bool,
+    /// Sets the stop pointers of the builtins, done anyway when a PIE or AIR public input is written
+    pub finalize_builtins: bool,
+    pub append_return_values: bool,
+    pub limits: RunLimits,
+    pub instrumentation: Instrumentation,
+    pub debug: bool,
+    pub mocks: Option<&'a [OracleMock]>,
+    pub run_id: Option<&'a str>,
+    /// Gas given to the function if it takes the gas builtin, a large amount by default
+    pub available_gas: Option<usize>,
+}
+
+impl Default for RunOptions<'_> {
+    fn default() -> Self {
+        Self {
+            layout: LayoutName::all_cairo,
+            trace_file: None,
+            memory_file: None,
+            cairo_pie_output: None,
+            air_public_input: None,
+            air_private_input: None,
+            proof_mode: false,
+            finalize_builtins: false,
+            append_return_values: false,
+            limits: RunLimits::default(),
+            instrumentation: Instrumentation::default(),
+            debug: false,
+            mocks: None,
+            run_id: None,
+            available_gas: None,
+        }
+    }
+}
+
 pub fn run_1(
     configuration: &Configuration,
-    layout: &LayoutName,
-    trace_file: &Option<PathBuf>,
-    memory_file: &Option<PathBuf>,
-    cairo_pie_output: &Option<PathBuf>,
-    air_public_input: &Option<PathBuf>,
-    air_private_input: &Option<PathBuf>,
+    program: &PreparedProgram,
+    entry_func_name: &str,
     args: &FuncArgs,
     schema: &Schema,
-    sierra_program: &SierraProgram,
-    entry_func_name: &str,
-    proof_mode: bool,
-    finalize_builtins: Option<bool>,
-    append_return_values: Option<bool>
-) -> Result<(Option<String>, CairoRunner), Error> {
+    options: &RunOptions,
+) -> Result<(Option<String>, CairoRunner, ExecutionReport), Error> {
+    let RunOptions {
+        trace_file,
+        memory_file,
+        cairo_pie_output,
+        air_public_input,
+        air_private_input,
+        ..
+    } = options;
     let cairo_run_config = Cairo1RunConfig {
-        proof_mode: proof_mode,
+        proof_mode: options.proof_mode,
         serialize_output: true,
         relocate_mem: memory_file.is_some(), //|| air_public_input.is_some(),
-        layout: *layout,
+        layout: options.layout,
         trace_enabled: trace_file.is_some(), //|| args.air_public_input.is_some(),
         args: &args.0,
-        finalize_builtins: cairo_pie_output.is_some() || finalize_builtins.unwrap_or_default(),
-        append_return_values: append_return_values.unwrap_or_default(),
+        // The AIR public input is built from the stop pointers and the finalized segments
+        finalize_builtins: cairo_pie_output.is_some()
+            || air_public_input.is_some()
+            || options.finalize_builtins,
+        append_return_values: options.append_return_values,
+        limits: options.limits,
+        instrumentation: options.instrumentation,
+        debug: options.debug,
+        mocks: options.mocks,
+        run_id: options.run_id,
+        available_gas: options.available_gas,
     };
 
-    let (runner, _vm, return_values) = cairo_run::cairo_run_program(
-        &sierra_program,
+    let (runner, _vm, return_values, report) = cairo_run::cairo_run_prepared(
+        program,
         cairo_run_config,
         configuration,
         entry_func_name,
@@ -161,7 +266,7 @@ pub fn run_1(
     }
 
     if let (Some(file_path), Some(trace_file), Some(memory_file)) =
-        (air_private_input, trace_file.clone(), memory_file.clone())
+        (air_private_input, trace_file, memory_file)
     {
         // Get absolute paths of trace_file & memory_file
         let trace_path = trace_file
@@ -210,5 +315,387 @@ pub fn run_1(
         memory_writer.flush()?;
     }
 
-    Ok((return_values, runner))
+    Ok((return_values, runner, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bundle::{
+        public_output, return_values, Bundle, Manifest, AIR_PRIVATE_INPUT_FILE,
+        AIR_PUBLIC_INPUT_FILE, MEMORY_FILE, TRACE_FILE,
+    };
+    use cairo_io_serde::FuncArg;
+    use cairo_lang_sierra::ids::FunctionId;
+    use cairo_lang_sierra::program::Function;
+    use cairo_lang_sierra::ProgramParser;
+    use indoc::indoc;
+    use rpc_hint_processor::LIMITS_CHECK_INTERVAL;
+    use serde_json::{json, Value};
+
+    /// `Fibonacci(a, b, n)`, recursing `n` times without gas nor builtins.
+    const FIBONACCI: &str = indoc! {"
+        type felt252 = felt252;
+        type NonZeroFelt252 = NonZero<felt252>;
+
+        libfunc branch_align = branch_align;
+        libfunc store_temp_felt252 = store_temp<felt252>;
+        libfunc felt252_const_minus_1 = felt252_const<-1>;
+        libfunc felt252_add = felt252_add;
+        libfunc felt252_dup = dup<felt252>;
+        libfunc felt252_drop = drop<felt252>;
+        libfunc felt252_is_zero = felt252_is_zero;
+        libfunc felt252_unwrap_non_zero = unwrap_non_zero<felt252>;
+        libfunc call_lib = function_call<user@Fibonacci>;
+        libfunc disable_ap_tracking = disable_ap_tracking;
+
+        felt252_is_zero(n) { fallthrough() PrepArgs(n) };
+        branch_align() -> ();
+        felt252_drop(b) -> ();
+        store_temp_felt252(a) -> (a);
+        disable_ap_tracking() -> ();
+        return(a);
+        PrepArgs:
+        branch_align() -> ();
+        felt252_unwrap_non_zero(n) -> (n);
+        felt252_const_minus_1() -> (minus1);
+        felt252_add(n, minus1) -> (n);
+        felt252_dup(b) -> (b, b_);
+        felt252_add(a, b_) -> (a_plus_b);
+        store_temp_felt252(b) -> (b);
+        store_temp_felt252(a_plus_b) -> (a_plus_b);
+        store_temp_felt252(n) -> (n);
+        call_lib(b, a_plus_b, n) -> (r);
+        return(r);
+
+        Fibonacci@0(a: felt252, b: felt252, n: felt252) -> (felt252);
+    "};
+
+    fn fibonacci() -> PreparedProgram {
+        PreparedProgram::new(ProgramParser::new().parse(FIBONACCI).unwrap(), None).unwrap()
+    }
+
+    fn fibonacci_args(n: u64) -> FuncArgs {
+        FuncArgs(
+            [1, 1, n]
+                .map(|value| FuncArg::Single(Felt252::from(value)))
+                .to_vec(),
+        )
+    }
+
+    fn fibonacci_schema() -> Schema {
+        let felt = json!({ "type": "Primitive", "name": "felt252" });
+        serde_json::from_value(json!({
+            "schemas": {
+                "Input": { "fields": [{ "a": felt }, { "b": felt }, { "n": felt }] },
+                "Output": { "fields": [{ "result": felt }] },
+            },
+            "cairo_input": "Input",
+            "cairo_output": "Output",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_proof_mode_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bundle = Bundle::create(&dir.path().join("bundle")).unwrap();
+        let program = fibonacci();
+        let schema = fibonacci_schema();
+
+        // Builtins are finalized for the AIR public input without being asked to
+        let (output, _, report) = run_1(
+            &Configuration::default(),
+            &program,
+            "Fibonacci",
+            &fibonacci_args(5),
+            &schema,
+            &RunOptions {
+                trace_file: Some(bundle.path(TRACE_FILE)),
+                memory_file: Some(bundle.path(MEMORY_FILE)),
+                air_public_input: Some(bundle.path(AIR_PUBLIC_INPUT_FILE)),
+                air_private_input: Some(bundle.path(AIR_PRIVATE_INPUT_FILE)),
+                proof_mode: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let output: Value = serde_json::from_str(&output.unwrap()).unwrap();
+        assert_eq!(output, json!({ "result": "0x8" }));
+
+        let manifest = Manifest::new(
+            "Fibonacci",
+            "all_cairo",
+            true,
+            true,
+            false,
+            &program.sierra_program,
+        );
+        bundle
+            .finish(manifest, &Value::Null, &schema, &report.oracle_transcript)
+            .unwrap();
+        let (bundle, manifest) = Bundle::open(&bundle.dir).unwrap();
+        assert!(manifest.finalize_builtins);
+        assert!(bundle.artifact(&manifest, AIR_PRIVATE_INPUT_FILE).is_some());
+
+        let public_input: Value = serde_json::from_str(
+            &std::fs::read_to_string(bundle.path(AIR_PUBLIC_INPUT_FILE)).unwrap(),
+        )
+        .unwrap();
+        let output = public_output(&public_input).unwrap();
+        assert_eq!(
+            return_values(&manifest, &output).unwrap(),
+            vec![Felt252::from(8)]
+        );
+    }
+
+    /// `agent::check(input: Input) -> PanicResult<(Input,)>`, with
+    /// `Input { values: Span<u32>, scale: u32 }`, returning its input or panicking with 7 if
+    /// its scale is zero.
+    const CHECK_SCALE: &str = indoc! {"
+        type u32 = u32;
+        type felt252 = felt252;
+        type NonZeroU32 = NonZero<u32>;
+        type ArrayU32 = Array<u32>;
+        type SnapshotArrayU32 = Snapshot<ArrayU32>;
+        type SpanU32 = Struct<ut@core::array::Span::<core::integer::u32>, SnapshotArrayU32>;
+        type agent::Input = Struct<ut@agent::Input, SpanU32, u32>;
+        type ArrayFelt252 = Array<felt252>;
+        type core::panics::Panic = Struct<ut@core::panics::Panic>;
+        type TupleInput = Struct<ut@Tuple, agent::Input>;
+        type TuplePanic = Struct<ut@Tuple, core::panics::Panic, ArrayFelt252>;
+        type core::panics::PanicResult::<(agent::Input,)> = Enum<ut@core::panics::PanicResult::<(agent::Input,)>, TupleInput, TuplePanic>;
+
+        libfunc branch_align = branch_align;
+        libfunc input_deconstruct = struct_deconstruct<agent::Input>;
+        libfunc input_construct = struct_construct<agent::Input>;
+        libfunc u32_dup = dup<u32>;
+        libfunc u32_drop = drop<u32>;
+        libfunc u32_is_zero = u32_is_zero;
+        libfunc non_zero_u32_drop = drop<NonZeroU32>;
+        libfunc span_drop = drop<SpanU32>;
+        libfunc array_new_felt252 = array_new<felt252>;
+        libfunc array_append_felt252 = array_append<felt252>;
+        libfunc felt252_const_7 = felt252_const<7>;
+        libfunc store_temp_felt252 = store_temp<felt252>;
+        libfunc panic_construct = struct_construct<core::panics::Panic>;
+        libfunc tuple_panic_construct = struct_construct<TuplePanic>;
+        libfunc tuple_input_construct = struct_construct<TupleInput>;
+        libfunc result_ok = enum_init<core::panics::PanicResult::<(agent::Input,)>, 0>;
+        libfunc result_err = enum_init<core::panics::PanicResult::<(agent::Input,)>, 1>;
+        libfunc store_temp_result = store_temp<core::panics::PanicResult::<(agent::Input,)>>;
+
+        input_deconstruct(input) -> (values, scale);
+        u32_dup(scale) -> (scale, scale_);
+        u32_is_zero(scale_) { fallthrough() ScaleNonZero(scale_) };
+        branch_align() -> ();
+        span_drop(values) -> ();
+        u32_drop(scale) -> ();
+        array_new_felt252() -> (panic_data);
+        felt252_const_7() -> (error);
+        store_temp_felt252(error) -> (error);
+        array_append_felt252(panic_data, error) -> (panic_data);
+        panic_construct() -> (panic);
+        tuple_panic_construct(panic, panic_data) -> (err);
+        result_err(err) -> (result);
+        store_temp_result(result) -> (result);
+        return(result);
+        ScaleNonZero:
+        branch_align() -> ();
+        non_zero_u32_drop(scale_) -> ();
+        input_construct(values, scale) -> (input);
+        tuple_input_construct(input) -> (ok);
+        result_ok(ok) -> (result);
+        store_temp_result(result) -> (result);
+        return(result);
+
+        agent::check@0(input: agent::Input) -> (core::panics::PanicResult::<(agent::Input,)>);
+    "};
+
+    fn check_scale() -> PreparedProgram {
+        PreparedProgram::new(ProgramParser::new().parse(CHECK_SCALE).unwrap(), None).unwrap()
+    }
+
+    fn check_scale_schema() -> Schema {
+        let u32 = json!({ "type": "Primitive", "name": "u32" });
+        serde_json::from_value(json!({
+            "schemas": {
+                "Input": {
+                    "fields": [{ "values": { "type": "Span", "item_type": u32 } }, { "scale": u32 }]
+                },
+            },
+            "cairo_input": "Input",
+            "cairo_output": "Input",
+        }))
+        .unwrap()
+    }
+
+    /// Runs `agent::check` in proof mode, returning its output and the public output of the run.
+    fn run_check_scale(
+        program: &PreparedProgram,
+        values: &[u64],
+        scale: u64,
+    ) -> Result<(Value, Vec<Felt252>), Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let public_input_file = dir.path().join(AIR_PUBLIC_INPUT_FILE);
+        // Serialized as `Input`, the length of the span first
+        let mut args = vec![values.len() as u64];
+        args.extend(values);
+        args.push(scale);
+        let (output, _, _) = run_1(
+            &Configuration::default(),
+            program,
+            "check",
+            &FuncArgs(
+                args.into_iter()
+                    .map(|value| FuncArg::Single(Felt252::from(value)))
+                    .collect(),
+            ),
+            &check_scale_schema(),
+            &RunOptions {
+                trace_file: Some(dir.path().join(TRACE_FILE)),
+                memory_file: Some(dir.path().join(MEMORY_FILE)),
+                air_public_input: Some(public_input_file.clone()),
+                proof_mode: true,
+                ..Default::default()
+            },
+        )?;
+        let public_input: Value =
+            serde_json::from_str(&std::fs::read_to_string(public_input_file).unwrap()).unwrap();
+        Ok((
+            serde_json::from_str(&output.unwrap()).unwrap(),
+            public_output(&public_input).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_proof_mode_typed_values() {
+        let program = check_scale();
+        let (output, public_output) = run_check_scale(&program, &[1, 2, 3], 2).unwrap();
+        assert_eq!(output, json!({ "values": [1, 2, 3], "scale": 2 }));
+        // The panic flag, then the return value and the arguments, each prefixed with its length
+        assert_eq!(
+            public_output,
+            [0, 5, 3, 1, 2, 3, 2, 5, 3, 1, 2, 3, 2].map(Felt252::from)
+        );
+
+        let manifest = Manifest::new(
+            "check",
+            "all_cairo",
+            true,
+            true,
+            false,
+            &program.sierra_program,
+        );
+        assert!(manifest.panic_result);
+        assert_eq!(
+            return_values(&manifest, &public_output).unwrap(),
+            [3, 1, 2, 3, 2].map(Felt252::from)
+        );
+    }
+
+    #[test]
+    fn test_proof_mode_panic() {
+        let error = run_check_scale(&check_scale(), &[1, 2, 3], 0)
+            .err()
+            .unwrap();
+        match error {
+            Error::RunPanic { panic_data, .. } => assert_eq!(panic_data, [Felt252::from(7)]),
+            error => panic!("unexpected error: {error}"),
+        }
+    }
+
+    /// Runs a Fibonacci recursing long enough to exceed the given limits.
+    fn exceed_limits(limits: RunLimits) -> (ResourceLimit, usize, usize) {
+        let error = run_1(
+            &Configuration::default(),
+            &fibonacci(),
+            "Fibonacci",
+            &fibonacci_args(100_000),
+            &fibonacci_schema(),
+            &RunOptions {
+                limits,
+                ..Default::default()
+            },
+        )
+        .err()
+        .unwrap();
+        match error {
+            Error::ResourceLimitExceeded {
+                limit,
+                steps,
+                memory_cells,
+                ..
+            } => (limit, steps, memory_cells),
+            error => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let (limit, steps, _) = exceed_limits(RunLimits {
+            max_steps: Some(100),
+            ..Default::default()
+        });
+        assert_eq!(limit, ResourceLimit::Steps(100));
+        assert_eq!(steps, 100);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (limit, steps, _) = exceed_limits(RunLimits {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        assert_eq!(limit, ResourceLimit::Timeout(Duration::ZERO));
+        assert!(steps <= LIMITS_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        // Checked while stepping, not only at the end of the run
+        let (limit, steps, memory_cells) = exceed_limits(RunLimits {
+            max_memory_cells: Some(200),
+            ..Default::default()
+        });
+        assert_eq!(limit, ResourceLimit::MemoryCells(200));
+        assert_eq!(steps, LIMITS_CHECK_INTERVAL);
+        assert!(memory_cells > 200);
+    }
+
+    #[test]
+    fn test_find_function() {
+        let mut program = ProgramParser::new().parse(FIBONACCI).unwrap();
+        let function = program.funcs.pop().unwrap();
+        let names = [
+            "agent::add",
+            "core::integer::u256_checked_add",
+            "agent::quote",
+            "other::quote",
+        ];
+        program.funcs = names
+            .iter()
+            .map(|name| Function {
+                id: FunctionId::from_string(*name),
+                ..function.clone()
+            })
+            .collect();
+        let find = |name| cairo_run::find_function(&program, name).map(|f| f.id.to_string());
+
+        assert_eq!(find("add").unwrap(), "agent::add");
+        assert_eq!(find("::add").unwrap(), "agent::add");
+        assert_eq!(
+            find("integer::u256_checked_add").unwrap(),
+            "core::integer::u256_checked_add"
+        );
+        assert_eq!(find("other::quote").unwrap(), "other::quote");
+        assert!(matches!(
+            find("quote"),
+            Err(Error::AmbiguousFunction { matches, .. }) if matches == ["agent::quote", "other::quote"]
+        ));
+        assert!(
+            matches!(find("checked_add"), Err(Error::MissingFunction(name)) if name == "checked_add")
+        );
+        assert!(matches!(find("main"), Err(Error::MissingFunction(_))));
+    }
 }
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A canned oracle response, served instead of querying the oracle server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleMock {
+    /// Selector of the cheatcode.
+    pub selector: String,
+    /// Pattern the decoded request must match, any request matches if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    /// Decoded response returned to the program.
+    pub response: Value,
+}
+
+impl OracleMock {
+    /// Returns true if the mock answers the given call.
+    pub fn matches(&self, selector: &str, request: &Value) -> bool {
+        self.selector == selector
+            && self
+                .request
+                .as_ref()
+                .map_or(true, |pattern| value_matches(pattern, request))
+    }
+}
+
+/// Oracle mocks of a test suite, read from a fixture file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OracleMocks {
+    /// Mocks available to every test.
+    #[serde(default)]
+    pub default: Vec<OracleMock>,
+    /// Mocks of each test, by test name or its last path segments.
+    #[serde(default)]
+    pub tests: BTreeMap<String, Vec<OracleMock>>,
+}
+
+impl OracleMocks {
+    /// Returns the mocks of a test, its own ones taking precedence over the default ones.
+    ///
+    /// When several entries match the test, the mocks of the most specific come first: the
+    /// exact test name, then the longest path segments.
+    pub fn for_test(&self, test_name: &str) -> Vec<OracleMock> {
+        let mut matching = self
+            .tests
+            .iter()
+            .filter(|(name, _)| {
+                test_name == name.as_str() || test_name.ends_with(&format!("::{name}"))
+            })
+            .collect::<Vec<_>>();
+        // Stable, entries as specific as each other staying in the order of their names
+        matching.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        matching
+            .into_iter()
+            .flat_map(|(_, mocks)| mocks.iter().cloned())
+            .chain(self.default.iter().cloned())
+            .collect()
+    }
+}
+
+/// Returns true if every field of the pattern is found with the same value in the request.
+fn value_matches(pattern: &Value, request: &Value) -> bool {
+    match (pattern, request) {
+        (Value::Object(pattern), Value::Object(request)) => pattern.iter().all(|(key, value)| {
+            request
+                .get(key)
+                .is_some_and(|request_value| value_matches(value, request_value))
+        }),
+        _ => pattern == request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_matches() {
+        let mock = OracleMock {
+            selector: "price".to_string(),
+            request: Some(json!({"pair": {"base": "ETH"}})),
+            response: json!({"price": 3000}),
+        };
+
+        assert!(mock.matches("price", &json!({"pair": {"base": "ETH", "quote": "USD"}})));
+        assert!(!mock.matches("price", &json!({"pair": {"base": "BTC", "quote": "USD"}})));
+        assert!(!mock.matches("volume", &json!({"pair": {"base": "ETH"}})));
+    }
+
+    #[test]
+    fn test_mocks_for_test() {
+        let mocks: OracleMocks = serde_json::from_value(json!({
+            "default": [{"selector": "price", "response": {"price": 1}}],
+            "tests": {
+                "tests::test_price": [{"selector": "price", "response": {"price": 2}}],
+                "other": [{"selector": "price", "response": {"price": 3}}],
+                "test_price": [{"selector": "price", "response": {"price": 4}}],
+                "agent::tests::test_price": [{"selector": "price", "response": {"price": 5}}],
+            },
+        }))
+        .unwrap();
+
+        let responses = |test_name| {
+            mocks
+                .for_test(test_name)
+                .into_iter()
+                .map(|mock| mock.response)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            responses("agent::tests::test_price"),
+            vec![
+                json!({"price": 5}),
+                json!({"price": 2}),
+                json!({"price": 4}),
+                json!({"price": 1})
+            ]
+        );
+        assert_eq!(
+            responses("other_agent::tests::test_price"),
+            vec![
+                json!({"price": 2}),
+                json!({"price": 4}),
+                json!({"price": 1})
+            ]
+        );
+    }
+}
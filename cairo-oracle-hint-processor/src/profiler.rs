@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use cairo_lang_sierra::program::{Program as SierraProgram, StatementIdx};
+use cairo_vm::vm::trace::trace_entry::RelocatedTraceEntry;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::compiled::CompiledProgram;
+
+/// Name used for steps executed outside of any Sierra function.
+pub(crate) const ENTRY_CODE: &str = "<entry code>";
+
+/// Steps spent in each Sierra function of a run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Profile {
+    /// Steps per call stack, with functions separated by `;` from the outermost one.
+    pub stacks: BTreeMap<String, usize>,
+}
+
+/// Steps attributed to a function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionSteps {
+    pub name: String,
+    /// Steps spent in the function itself.
+    pub self_steps: usize,
+    /// Steps spent in the function and its callees.
+    pub total_steps: usize,
+}
+
+/// Maps program counters back to the Sierra functions they were compiled from.
+pub(crate) struct Profiler<'a> {
+    /// Start offset of each Sierra statement in the CASM program.
+    statement_offsets: Vec<usize>,
+    /// Entry point and name of each Sierra function, sorted by entry point.
+    functions: Vec<(StatementIdx, &'a str)>,
+    /// Size of the code added before the CASM program.
+    header_size: usize,
+    /// Size of the CASM program.
+    program_size: usize,
+}
+
+impl<'a> Profiler<'a> {
+    pub(crate) fn new(
+        sierra_program: &'a SierraProgram,
+        compiled_program: &CompiledProgram,
+        header_size: usize,
+    ) -> Self {
+        let functions = sierra_program
+            .funcs
+            .iter()
+            .map(|f| (f.entry_point, f.id.debug_name.as_deref().unwrap_or("<unknown>")))
+            .sorted_by_key(|(entry_point, _)| entry_point.0)
+            .collect();
+        Self {
+            statement_offsets: compiled_program.statement_offsets.clone(),
+            functions,
+            header_size,
+            program_size: compiled_program.size(),
+        }
+    }
+
+    /// Returns the Sierra statement compiled to the given offset of the CASM program.
+    pub(crate) fn statement_at_offset(&self, offset: usize) -> Option<usize> {
+        if offset >= self.program_size {
+            return None;
+        }
+        self.statement_offsets
+            .partition_point(|start| *start <= offset)
+            .checked_sub(1)
+    }
+
+    /// Returns the Sierra statement the program counter of a relocated trace entry belongs to.
+    pub(crate) fn statement_at(&self, pc: usize) -> Option<usize> {
+        // The program segment is relocated right after the reserved first address
+        let offset = pc.checked_sub(1 + self.header_size)?;
+        self.statement_at_offset(offset)
+    }
+
+    /// Returns the name of the Sierra function the program counter belongs to.
+    pub(crate) fn function_at(&self, pc: usize) -> Option<&'a str> {
+        let statement = self.statement_at(pc)?;
+        let function = self
+            .functions
+            .partition_point(|(entry_point, _)| entry_point.0 <= statement)
+            .checked_sub(1)?;
+        Some(self.functions[function].1)
+    }
+
+    /// Returns the name of each Sierra function with the program counter of its first instruction.
+    pub(crate) fn function_entries(&self) -> impl Iterator<Item = (&'a str, usize)> + '_ {
+        self.functions.iter().filter_map(|(entry_point, name)| {
+            let offset = self.statement_offsets.get(entry_point.0)?;
+            Some((*name, 1 + self.header_size + offset))
+        })
+    }
+
+    /// Attributes every step of the trace to the call stack it was executed in.
+    pub(crate) fn profile(&self, trace: &[RelocatedTraceEntry]) -> Profile {
+        let mut stacks = BTreeMap::<String, usize>::new();
+        // Frames of the current call stack, with the fp they were entered with
+        let mut frames: Vec<(usize, &str)> = Vec::new();
+        for entry in trace {
+            while frames.last().is_some_and(|(fp, _)| *fp > entry.fp) {
+                frames.pop();
+            }
+            if frames.last().map_or(true, |(fp, _)| *fp < entry.fp) {
+                // A new frame was entered, code outside of functions (e.g. the footer) stays in its caller
+                if let Some(function) = self.function_at(entry.pc) {
+                    frames.push((entry.fp, function));
+                }
+            }
+            let stack = if frames.is_empty() {
+                ENTRY_CODE.to_string()
+            } else {
+                frames.iter().map(|(_, name)| *name).join(";")
+            };
+            *stacks.entry(stack).or_default() += 1;
+        }
+        Profile { stacks }
+    }
+}
+
+impl Profile {
+    /// Returns the profile in the folded stacks format used by flamegraph tools.
+    pub fn folded(&self) -> String {
+        self.stacks
+            .iter()
+            .fold(String::new(), |mut folded, (stack, steps)| {
+                let _ = writeln!(folded, "{stack} {steps}");
+                folded
+            })
+    }
+
+    /// Returns the steps of each function, the most expensive first.
+    pub fn functions(&self) -> Vec<FunctionSteps> {
+        let mut functions = BTreeMap::<&str, FunctionSteps>::new();
+        for (stack, steps) in &self.stacks {
+            let frames = stack.split(';').collect_vec();
+            // Recursive functions only count once per stack
+            let mut seen = HashSet::new();
+            for (depth, name) in frames.iter().enumerate() {
+                let function = functions.entry(*name).or_insert_with(|| FunctionSteps {
+                    name: name.to_string(),
+                    self_steps: 0,
+                    total_steps: 0,
+                });
+                if seen.insert(*name) {
+                    function.total_steps += steps;
+                }
+                if depth == frames.len() - 1 {
+                    function.self_steps += steps;
+                }
+            }
+        }
+        functions
+            .into_values()
+            .sorted_by(|a, b| b.self_steps.cmp(&a.self_steps).then(a.name.cmp(&b.name)))
+            .collect()
+    }
+
+    /// Returns a table of the `n` functions with the most steps.
+    pub fn table(&self, n: usize) -> String {
+        let functions = self.functions();
+        let total: usize = self.stacks.values().sum();
+        let mut table = format!("{:>10} {:>7} {:>10}  function\n", "self", "%", "total");
+        for function in functions.iter().take(n) {
+            let _ = writeln!(
+                table,
+                "{:>10} {:>6.2}% {:>10}  {}",
+                function.self_steps,
+                100.0 * function.self_steps as f64 / total.max(1) as f64,
+                function.total_steps,
+                function.name
+            );
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_functions() {
+        let profile = Profile {
+            stacks: BTreeMap::from([
+                ("main".to_string(), 5),
+                ("main;foo".to_string(), 10),
+                ("main;foo;foo".to_string(), 3),
+                ("main;bar".to_string(), 7),
+            ]),
+        };
+
+        assert_eq!(
+            profile.functions(),
+            vec![
+                FunctionSteps {
+                    name: "foo".to_string(),
+                    self_steps: 13,
+                    total_steps: 13,
+                },
+                FunctionSteps {
+                    name: "bar".to_string(),
+                    self_steps: 7,
+                    total_steps: 7,
+                },
+                FunctionSteps {
+                    name: "main".to_string(),
+                    self_steps: 5,
+                    total_steps: 25,
+                },
+            ]
+        );
+        assert_eq!(profile.folded(), "main 5\nmain;bar 7\nmain;foo 10\nmain;foo;foo 3\n");
+    }
+}
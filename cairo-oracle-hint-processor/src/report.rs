@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use cairo_vm::vm::runners::cairo_runner::CairoRunner;
+use serde::Serialize;
+
+use crate::coverage::Coverage;
+use crate::profiler::Profile;
+use crate::Error;
+
+/// An oracle call made during a run.
+#[derive(Debug, Clone)]
+pub struct OracleCall {
+    /// Selector of the cheatcode.
+    pub selector: String,
+    /// Time spent waiting on the oracle, including polling.
+    pub latency: Duration,
+    /// Whether the oracle returned a response that could be written to memory.
+    pub success: bool,
+    /// Decoded request sent to the oracle, `null` if it was never sent.
+    pub request: serde_json::Value,
+    /// Decoded response of the oracle, if it answered.
+    pub response: Option<serde_json::Value>,
+    /// When the call started.
+    pub started_at: SystemTime,
+    /// URL of the oracle server, `None` if the call was mocked or never sent.
+    pub url: Option<String>,
+    /// Whether the response came from a mock.
+    pub mocked: bool,
+    /// HTTP requests sent to the server, status polls included.
+    pub attempts: usize,
+    /// Size in bytes of the request body.
+    pub request_size: usize,
+    /// Size in bytes of the response bodies.
+    pub response_size: usize,
+    /// Why the call failed.
+    pub error: Option<String>,
+}
+
+/// Aggregated oracle calls of a selector.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OracleCallsReport {
+    pub count: usize,
+    pub failed: usize,
+    pub total_latency_ms: f64,
+    pub max_latency_ms: f64,
+}
+
+impl OracleCallsReport {
+    fn add(&mut self, call: &OracleCall) {
+        let latency_ms = call.latency.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.failed += usize::from(!call.success);
+        self.total_latency_ms += latency_ms;
+        self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+    }
+}
+
+/// Resources consumed by a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionReport {
+    pub n_steps: usize,
+    pub n_memory_holes: usize,
+    pub builtin_instance_counter: BTreeMap<String, usize>,
+    pub oracle_calls: OracleCallsReport,
+    pub oracle_calls_by_selector: BTreeMap<String, OracleCallsReport>,
+    /// Every oracle call of the run, in order.
+    #[serde(skip)]
+    pub oracle_transcript: Vec<OracleCall>,
+    /// Steps per Sierra function, if the run was profiled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// Sierra statements executed, if coverage was recorded.
+    #[serde(skip)]
+    pub coverage: Option<Coverage>,
+    /// Gas consumed by the run, if the function takes the gas builtin and its return values were
+    /// not appended to the output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_usage: Option<usize>,
+}
+
+impl ExecutionReport {
+    /// Builds the report of a finished run.
+    pub fn new(
+        runner: &CairoRunner,
+        oracle_calls: &[OracleCall],
+        profile: Option<Profile>,
+        coverage: Option<Coverage>,
+        gas_usage: Option<usize>,
+    ) -> Result<Self, Error> {
+        let resources = runner.get_execution_resources()?;
+
+        let mut total = OracleCallsReport::default();
+        let mut by_selector = BTreeMap::<String, OracleCallsReport>::new();
+        for call in oracle_calls {
+            total.add(call);
+            by_selector
+                .entry(call.selector.clone())
+                .or_default()
+                .add(call);
+        }
+
+        Ok(Self {
+            n_steps: resources.n_steps,
+            n_memory_holes: resources.n_memory_holes,
+            builtin_instance_counter: resources
+                .builtin_instance_counter
+                .iter()
+                .map(|(name, count)| (name.to_str().to_string(), *count))
+                .collect(),
+            oracle_calls: total,
+            oracle_calls_by_selector: by_selector,
+            oracle_transcript: oracle_calls.to_vec(),
+            profile,
+            coverage,
+            gas_usage,
+        })
+    }
+}
+
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "steps: {}", self.n_steps)?;
+        writeln!(f, "memory holes: {}", self.n_memory_holes)?;
+        if let Some(gas_usage) = self.gas_usage {
+            writeln!(f, "gas usage: {gas_usage}")?;
+        }
+        for (builtin, count) in &self.builtin_instance_counter {
+            writeln!(f, "{builtin} builtin: {count}")?;
+        }
+        write!(
+            f,
+            "oracle calls: {} ({} failed, {:.1} ms total)",
+            self.oracle_calls.count, self.oracle_calls.failed, self.oracle_calls.total_latency_ms
+        )?;
+        for (selector, calls) in &self.oracle_calls_by_selector {
+            write!(
+                f,
+                "\n  {selector}: {} calls, {:.1} ms total, {:.1} ms max",
+                calls.count, calls.total_latency_ms, calls.max_latency_ms
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use super::Error;
+use super::{Error, ResourceLimit};
+use crate::cairo_run::{used_memory_cells, RunLimits};
+use crate::mock::OracleMock;
+use crate::report::OracleCall;
 use crate::hint_processor_utils::{cell_ref_to_relocatable, extract_buffer, get_ptr};
 use crate::insert_value_to_cellref;
 use cairo_lang_casm::{
@@ -25,33 +28,151 @@ use cairo_vm::{
     },
 };
 use core::any::Any;
+use log::{debug, trace, warn};
 use reqwest::Url;
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of steps between two checks of the run deadline and memory budget.
+pub(crate) const LIMITS_CHECK_INTERVAL: usize = 1024;
+
+/// Timeout of a single oracle request when the run has no deadline.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(240);
+
+/// Header carrying the ID of the run in the requests to the oracle servers.
+pub const RUN_ID_HEADER: &str = "x-run-id";
 
 /// HintProcessor for Cairo 1 compiler hints.
 pub struct Rpc1HintProcessor<'a> {
     inner_processor: Cairo1HintProcessor,
     configuration: &'a Configuration,
+    limits: RunLimits,
+    started_at: Instant,
+    steps: usize,
+    exceeded_limit: Option<ResourceLimit>,
+    oracle_calls: Vec<OracleCall>,
+    mocks: Option<&'a [OracleMock]>,
+    run_id: Option<&'a str>,
 }
 
 impl<'a> Rpc1HintProcessor<'a> {
     pub fn new(
         inner_processor: Cairo1HintProcessor,
         configuration: &'a Configuration,
+        limits: RunLimits,
     ) -> Result<Self, Error> {
         Ok(Self {
             inner_processor,
             configuration,
+            limits,
+            started_at: Instant::now(),
+            steps: 0,
+            exceeded_limit: None,
+            oracle_calls: Vec::new(),
+            mocks: None,
+            run_id: None,
         })
     }
 
-    /// Executes a cheatcode.
+    /// Serves the cheatcodes from the given mocks instead of the oracle servers.
+    pub fn with_mocks(mut self, mocks: Option<&'a [OracleMock]>) -> Self {
+        self.mocks = mocks;
+        self
+    }
+
+    /// Sends the ID of the run to the oracle servers with every request.
+    pub fn with_run_id(mut self, run_id: Option<&'a str>) -> Self {
+        self.run_id = run_id;
+        self
+    }
+
+    /// Returns the oracle calls made so far.
+    pub fn oracle_calls(&self) -> &[OracleCall] {
+        &self.oracle_calls
+    }
+
+    /// Returns the oracle calls made so far, leaving none recorded.
+    pub fn take_oracle_calls(&mut self) -> Vec<OracleCall> {
+        std::mem::take(&mut self.oracle_calls)
+    }
+
+    /// Returns the limit that stopped the run, if any.
+    pub fn exceeded_limit(&self) -> Option<ResourceLimit> {
+        self.exceeded_limit.or_else(|| {
+            self.limits
+                .max_steps
+                .filter(|_| self.inner_processor.consumed())
+                .map(ResourceLimit::Steps)
+        })
+    }
+
+    /// Returns the time elapsed since the processor was created.
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Adds the run ID header to a request to an oracle server.
+    fn with_run_id_header(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::blocking::RequestBuilder {
+        match self.run_id {
+            Some(run_id) => request.header(RUN_ID_HEADER, run_id),
+            None => request,
+        }
+    }
+
+    /// Returns the time left before the run deadline, if the run has one.
+    fn remaining_time(&self) -> Option<Duration> {
+        self.limits
+            .timeout
+            .map(|timeout| timeout.saturating_sub(self.elapsed()))
+    }
+
+    /// Records the timeout as exceeded if the deadline has passed.
+    fn check_deadline(&mut self) -> Result<(), HintError> {
+        match (self.limits.timeout, self.remaining_time()) {
+            (Some(timeout), Some(remaining)) if remaining.is_zero() => {
+                self.exceeded_limit = Some(ResourceLimit::Timeout(timeout));
+                Err(HintError::CustomHint(Box::from("Run timed out".to_string())))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the memory budget as exceeded if the run uses more cells than allowed.
+    fn check_memory(&mut self, vm: &mut VirtualMachine) {
+        if let Some(max_memory_cells) = self.limits.max_memory_cells {
+            if used_memory_cells(vm) > max_memory_cells {
+                self.exceeded_limit = Some(ResourceLimit::MemoryCells(max_memory_cells));
+            }
+        }
+    }
+
+    /// Counts a step of the run, checking the deadline and memory budget every
+    /// `LIMITS_CHECK_INTERVAL` steps.
+    ///
+    /// An exceeded limit stops the run through `consumed`, and is reported by `exceeded_limit`.
+    pub(crate) fn consume_run_step(&mut self, vm: &mut VirtualMachine) {
+        self.consume_step();
+        if self.steps % LIMITS_CHECK_INTERVAL == 0 && self.check_deadline().is_ok() {
+            self.check_memory(vm);
+        }
+    }
+
+    /// Returns the timeout of an oracle request, bounded by the run deadline.
+    fn request_timeout(&self, timeout: Duration) -> Duration {
+        self.remaining_time()
+            .map_or(timeout, |remaining| remaining.min(timeout))
+    }
+
+    /// Executes a cheatcode, recording the decoded request and response in `call`.
     fn execute_cheatcode(
         &mut self,
         selector: &BigIntAsHex,
         [input_start, input_end]: [&ResOperand; 2],
         [output_start, output_end]: [&CellRef; 2],
+        call: &mut OracleCall,
         vm: &mut VirtualMachine,
         _exec_scopes: &mut ExecutionScopes,
     ) -> Result<(), HintError> {
@@ -66,9 +187,6 @@ impl<'a> Rpc1HintProcessor<'a> {
         let input_end = extract_relocatable(vm, input_end)?;
         let inputs = vm_get_range(vm, input_start, input_end)?;
 
-        let mut res_segment = MemBuffer::new_segment(vm);
-        let res_segment_start = res_segment.ptr;
-
         let Some(configuration) = self
             .configuration
             .services
@@ -80,6 +198,34 @@ impl<'a> Rpc1HintProcessor<'a> {
             ))));
         };
 
+        let data = deserialize_cairo_serde(
+            self.configuration,
+            &configuration.input,
+            &mut inputs.as_ref(),
+        );
+        call.request = data.clone();
+        call.request_size = data.to_string().len();
+
+        if let Some(mocks) = self.mocks {
+            call.mocked = true;
+            let output = mocks
+                .iter()
+                .find(|mock| mock.matches(selector, &data))
+                .map(|mock| mock.response.clone())
+                .ok_or_else(|| {
+                    HintError::CustomHint(Box::from(format!(
+                        "No oracle mock matches the call to {selector} with request: {data}"
+                    )))
+                })?;
+            call.response_size = output.to_string().len();
+            call.response = Some(output.clone());
+            let data = serialize_cairo_serde(self.configuration, &configuration.output, &output);
+            return write_response(vm, &data, [output_start, output_end]);
+        }
+
+        let mut res_segment = MemBuffer::new_segment(vm);
+        let res_segment_start = res_segment.ptr;
+
         let server_config = self
             .configuration
             .servers_config
@@ -99,12 +245,7 @@ impl<'a> Rpc1HintProcessor<'a> {
             .path_segments_mut()
             .expect("cannot be a base URL")
             .push(selector);
-
-        let data = deserialize_cairo_serde(
-            self.configuration,
-            &configuration.input,
-            &mut inputs.as_ref(),
-        );
+        call.url = Some(server_url.to_string());
 
         let use_polling = server_config.polling.unwrap_or(false);
 
@@ -122,7 +263,7 @@ impl<'a> Rpc1HintProcessor<'a> {
                 .unwrap_or(&default_polling_config);
 
             let client = reqwest::blocking::ClientBuilder::new()
-                .timeout(Duration::from_secs(polling_config.request_timeout))
+                .timeout(self.request_timeout(Duration::from_secs(polling_config.request_timeout)))
                 .build()
                 .map_err(|e| {
                     HintError::CustomHint(Box::from(format!("Failed to create HTTP client: {}", e)))
@@ -134,8 +275,9 @@ impl<'a> Rpc1HintProcessor<'a> {
             let overall_timeout = Duration::from_secs(polling_config.overall_timeout);
 
             // Initial request to start the job
-            let response = client
-                .post(server_url.clone())
+            call.attempts += 1;
+            let response = self
+                .with_run_id_header(client.post(server_url.clone()))
                 .json(&data)
                 .header("x-admin-api-key", "qwerty")
                 .send()
@@ -150,7 +292,8 @@ impl<'a> Rpc1HintProcessor<'a> {
                 HintError::CustomHint(Box::from(format!("Failed to get response body: {}", e)))
             })?;
 
-            println!("Initial response body: {}", response_body);
+            call.response_size += response_body.len();
+            trace!("{selector}: initial response body: {response_body}");
 
             let response_json: serde_json::Value =
                 serde_json::from_str(&response_body).map_err(|e| {
@@ -166,10 +309,11 @@ impl<'a> Rpc1HintProcessor<'a> {
                 .ok_or_else(|| HintError::CustomHint(Box::from("Failed to get jobId")))?
                 .to_string();
 
-            println!("Received job_id: {}", job_id);
+            debug!("{selector}: received job {job_id} from {server_url}");
 
             let mut attempt = 0;
             loop {
+                self.check_deadline()?;
                 if attempt >= max_attempts || start_time.elapsed() > overall_timeout {
                     return Err(HintError::CustomHint(Box::from(
                         "Polling timed out".to_string(),
@@ -185,14 +329,18 @@ impl<'a> Rpc1HintProcessor<'a> {
                         )))
                     })?;
 
-                println!("Checking status at URL: {}", status_url);
+                trace!("{selector}: checking status at {status_url}");
 
-                let status_response = client.get(status_url.clone()).send().map_err(|e| {
-                    HintError::CustomHint(Box::from(format!(
-                        "Failed to send status request: {}",
-                        e
-                    )))
-                })?;
+                call.attempts += 1;
+                let status_response = self
+                    .with_run_id_header(client.get(status_url.clone()))
+                    .send()
+                    .map_err(|e| {
+                        HintError::CustomHint(Box::from(format!(
+                            "Failed to send status request: {}",
+                            e
+                        )))
+                    })?;
 
                 let status_body = status_response.text().map_err(|e| {
                     HintError::CustomHint(Box::from(format!(
@@ -201,21 +349,16 @@ impl<'a> Rpc1HintProcessor<'a> {
                     )))
                 })?;
 
-                println!("Status response body: {}", status_body);
+                call.response_size += status_body.len();
+                trace!("{selector}: status response body: {status_body}");
 
-                let status_json: serde_json::Value = match serde_json::from_str(&status_body) {
-                    Ok(json) => json,
-                    Err(e) => {
-                        println!(
-                            "Failed to parse status JSON: {}. Raw response: {}",
-                            e, status_body
-                        );
-                        return Err(HintError::CustomHint(Box::from(format!(
+                let status_json: serde_json::Value =
+                    serde_json::from_str(&status_body).map_err(|e| {
+                        HintError::CustomHint(Box::from(format!(
                             "Failed to parse status JSON: {}. Raw response: {}",
                             e, status_body
-                        ))));
-                    }
-                };
+                        )))
+                    })?;
 
                 if status_json.get("status").and_then(|s| s.as_str()) == Some("completed") {
                     if let Some(output) = status_json.get("result") {
@@ -224,7 +367,8 @@ impl<'a> Rpc1HintProcessor<'a> {
                             &configuration.output,
                             output,
                         );
-                        println!("Output: {output}");
+                        trace!("{selector}: output: {output}");
+                        call.response = Some(output.clone());
                         res_segment.write_data(data.iter()).map_err(|e| {
                             HintError::CustomHint(Box::from(format!(
                                 "Failed to write data to result segment: {}",
@@ -251,7 +395,7 @@ impl<'a> Rpc1HintProcessor<'a> {
                         return Ok(());
                     }
                 } else {
-                    println!("Job not completed. Current status: {:?}", status_json);
+                    debug!("{selector}: job {job_id} not completed, status: {status_json}");
                 }
 
                 std::thread::sleep(polling_interval);
@@ -259,10 +403,11 @@ impl<'a> Rpc1HintProcessor<'a> {
             }
         } else {
             let client = reqwest::blocking::Client::new();
-            let response = client
-                .post(server_url.clone())
+            call.attempts += 1;
+            let response = self
+                .with_run_id_header(client.post(server_url.clone()))
                 .json(&data)
-                .timeout(Duration::from_secs(240))
+                .timeout(self.request_timeout(DEFAULT_REQUEST_TIMEOUT))
                 .header("x-admin-api-key", "qwerty")
                 .send()
                 .map_err(|e| {
@@ -275,6 +420,8 @@ impl<'a> Rpc1HintProcessor<'a> {
             let response_body = response.text().map_err(|e| {
                 HintError::CustomHint(Box::from(format!("Failed to get response body: {}", e)))
             })?;
+            call.response_size += response_body.len();
+            trace!("{selector}: response body: {response_body}");
 
             let response_json: serde_json::Value =
                 serde_json::from_str(&response_body).map_err(|e| {
@@ -292,6 +439,7 @@ impl<'a> Rpc1HintProcessor<'a> {
                 ))));
             };
 
+            call.response = Some(output.clone());
             let data = serialize_cairo_serde(self.configuration, &configuration.output, &output);
             res_segment.write_data(data.iter()).map_err(|e| {
                 HintError::CustomHint(Box::from(format!(
@@ -347,6 +495,7 @@ impl<'a> HintProcessorLogic for Rpc1HintProcessor<'a> {
         _constants: &std::collections::HashMap<String, Felt252>,
     ) -> Result<(), cairo_vm::vm::errors::hint_errors::HintError> {
         let hints: &Vec<Hint> = hint_data.downcast_ref().ok_or(HintError::WrongHintData)?;
+        self.check_deadline()?;
 
         for hint in hints {
             match hint {
@@ -357,13 +506,36 @@ impl<'a> HintProcessorLogic for Rpc1HintProcessor<'a> {
                     output_start,
                     output_end,
                 }) => {
-                    self.execute_cheatcode(
+                    let started_at = Instant::now();
+                    let mut call = OracleCall {
+                        selector: String::from_utf8_lossy(&selector.value.to_bytes_be().1)
+                            .into_owned(),
+                        latency: Duration::ZERO,
+                        success: false,
+                        request: serde_json::Value::Null,
+                        response: None,
+                        started_at: SystemTime::now(),
+                        url: None,
+                        mocked: false,
+                        attempts: 0,
+                        request_size: 0,
+                        response_size: 0,
+                        error: None,
+                    };
+                    let result = self.execute_cheatcode(
                         selector,
                         [input_start, input_end],
                         [output_start, output_end],
+                        &mut call,
                         vm,
                         exec_scopes,
-                    )?;
+                    );
+                    call.latency = started_at.elapsed();
+                    call.success = result.is_ok();
+                    call.error = result.as_ref().err().map(ToString::to_string);
+                    log_oracle_call(&call);
+                    self.oracle_calls.push(call);
+                    result?;
                 }
                 _ => {
                     self.inner_processor.execute(vm, exec_scopes, hint)?;
@@ -376,10 +548,11 @@ impl<'a> HintProcessorLogic for Rpc1HintProcessor<'a> {
 
 impl<'a> ResourceTracker for Rpc1HintProcessor<'a> {
     fn consumed(&self) -> bool {
-        self.inner_processor.consumed()
+        self.inner_processor.consumed() || self.exceeded_limit.is_some()
     }
 
     fn consume_step(&mut self) {
+        self.steps += 1;
         self.inner_processor.consume_step()
     }
 
@@ -392,6 +565,25 @@ impl<'a> ResourceTracker for Rpc1HintProcessor<'a> {
     }
 }
 
+/// Logs a finished oracle call, as a warning if it failed.
+fn log_oracle_call(call: &OracleCall) {
+    let target = if call.mocked {
+        "mock"
+    } else {
+        call.url.as_deref().unwrap_or("no server")
+    };
+    match &call.error {
+        None => debug!(
+            "{} on {target}: {:?}, {} attempts, {} bytes sent, {} bytes received",
+            call.selector, call.latency, call.attempts, call.request_size, call.response_size
+        ),
+        Some(error) => warn!(
+            "{} on {target} failed after {:?} and {} attempts: {error}",
+            call.selector, call.latency, call.attempts
+        ),
+    }
+}
+
 /// Extracts a parameter assumed to be a buffer, and converts it into a relocatable.
 fn extract_relocatable(
     vm: &VirtualMachine,
@@ -416,6 +608,26 @@ fn vm_get_range(
     Ok(values)
 }
 
+/// Writes a serialized oracle response to a new segment, delimited by the output cells.
+fn write_response(
+    vm: &mut VirtualMachine,
+    data: &[Felt252],
+    [output_start, output_end]: [&CellRef; 2],
+) -> Result<(), HintError> {
+    let mut res_segment = MemBuffer::new_segment(vm);
+    let res_segment_start = res_segment.ptr;
+    res_segment.write_data(data.iter()).map_err(|e| {
+        HintError::CustomHint(Box::from(format!(
+            "Failed to write data to result segment: {}",
+            e
+        )))
+    })?;
+    let res_segment_end = res_segment.ptr;
+    insert_value_to_cellref!(vm, output_start, res_segment_start)?;
+    insert_value_to_cellref!(vm, output_end, res_segment_end)?;
+    Ok(())
+}
+
 /// Wrapper trait for a VM owner.
 trait VMWrapper {
     fn vm(&mut self) -> &mut VirtualMachine;
@@ -0,0 +1,81 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use cairo_vm::hint_processor::hint_processor_definition::HintProcessorLogic;
+use cairo_vm::serde::deserialize_program::ApTracking;
+use cairo_vm::types::relocatable::Relocatable;
+use cairo_vm::vm::errors::vm_errors::VirtualMachineError;
+use cairo_vm::vm::runners::cairo_runner::{CairoRunner, ResourceTracker};
+use cairo_vm::Felt252;
+
+use crate::rpc_hint_processor::Rpc1HintProcessor;
+
+/// Executes a run one step at a time, checking the limits of the run as it goes.
+///
+/// The data of the hints is compiled once, where `CairoRunner::run_for_steps` compiles it
+/// again on every call.
+pub(crate) struct Stepper {
+    /// Compiled data of the hints, by the program counter of their instruction.
+    hint_datas: HashMap<usize, Box<dyn Any>>,
+    constants: HashMap<String, Felt252>,
+}
+
+impl Stepper {
+    /// Compiles the hints found at the given offsets of the program.
+    pub(crate) fn new(
+        hint_offsets: impl IntoIterator<Item = usize>,
+        hint_processor: &Rpc1HintProcessor,
+    ) -> Result<Self, VirtualMachineError> {
+        let hint_datas = hint_offsets
+            .into_iter()
+            .map(|offset| {
+                // The code of a hint is the offset of its instruction, see `build_hints_vec`
+                let code = offset.to_string();
+                hint_processor
+                    .compile_hint(&code, &ApTracking::default(), &HashMap::new(), &[])
+                    .map(|data| (offset, data))
+                    .map_err(|_| VirtualMachineError::CompileHintFail(code.into()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            hint_datas,
+            constants: HashMap::new(),
+        })
+    }
+
+    /// Executes the instruction at the program counter, with its hints.
+    pub(crate) fn step(
+        &self,
+        runner: &mut CairoRunner,
+        hint_processor: &mut Rpc1HintProcessor,
+    ) -> Result<(), VirtualMachineError> {
+        let hint_data = self
+            .hint_datas
+            .get(&runner.vm.get_pc().offset)
+            .map_or(&[][..], std::slice::from_ref);
+        runner.vm.step(
+            hint_processor,
+            &mut runner.exec_scopes,
+            hint_data,
+            &self.constants,
+        )?;
+        hint_processor.consume_run_step(&mut runner.vm);
+        Ok(())
+    }
+
+    /// Runs the program until `end`, like `CairoRunner::run_until_pc` does.
+    pub(crate) fn run_until_pc(
+        &self,
+        runner: &mut CairoRunner,
+        end: Relocatable,
+        hint_processor: &mut Rpc1HintProcessor,
+    ) -> Result<(), VirtualMachineError> {
+        while runner.vm.get_pc() != end && !hint_processor.consumed() {
+            self.step(runner, hint_processor)?;
+        }
+        if runner.vm.get_pc() != end {
+            return Err(VirtualMachineError::UnfinishedExecution);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::report::{ExecutionReport, OracleCall};
+use crate::Error;
+
+/// Name of the instrumentation scope of the exported spans.
+const SCOPE_NAME: &str = "cairo-oracle-hint-processor";
+
+/// `SPAN_KIND_INTERNAL` and `SPAN_KIND_CLIENT` of OTLP.
+const SPAN_KIND_INTERNAL: u8 = 1;
+const SPAN_KIND_CLIENT: u8 = 3;
+
+/// `STATUS_CODE_OK` and `STATUS_CODE_ERROR` of OTLP.
+const STATUS_CODE_OK: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// A finished run, traced as a span with a child span per oracle call.
+#[derive(Debug, Clone)]
+pub struct RunTrace<'a> {
+    pub run_id: &'a str,
+    /// Function that was run.
+    pub function: &'a str,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    /// Report of the run, if it got far enough to have one.
+    pub report: Option<&'a ExecutionReport>,
+    /// Why the run failed.
+    pub error: Option<String>,
+}
+
+impl<'a> RunTrace<'a> {
+    /// Traces a run that started at `started_at` and just finished with `outcome`.
+    pub fn new(
+        run_id: &'a str,
+        function: &'a str,
+        started_at: SystemTime,
+        outcome: Result<&'a ExecutionReport, &'a Error>,
+    ) -> Self {
+        let (report, error) = match outcome {
+            Ok(report) => (Some(report), None),
+            Err(err) => {
+                // Panicked runs still report the resources they consumed
+                let report = match err {
+                    Error::RunPanic { report, .. } => report.as_deref(),
+                    _ => None,
+                };
+                (report, Some(err.to_string()))
+            }
+        };
+        Self {
+            run_id,
+            function,
+            started_at,
+            duration: started_at.elapsed().unwrap_or_default(),
+            report,
+            error,
+        }
+    }
+
+    /// Trace ID of the run, the run ID itself when it is a valid one.
+    pub fn trace_id(&self) -> String {
+        if self.run_id.len() == 32 && self.run_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            self.run_id.to_ascii_lowercase()
+        } else {
+            hex::encode(&Sha256::digest(self.run_id.as_bytes())[..16])
+        }
+    }
+
+    /// Returns the run as an OTLP `ExportTraceServiceRequest` in its JSON encoding.
+    pub fn to_otlp(&self, service_name: &str) -> Value {
+        let trace_id = self.trace_id();
+        let span_id = |index: usize| {
+            hex::encode(&Sha256::digest(format!("{trace_id}:{index}").as_bytes())[..8])
+        };
+        let run_span_id = span_id(0);
+
+        let mut attributes = vec![
+            attribute("agent.run_id", json!({ "stringValue": self.run_id })),
+            attribute("cairo.function", json!({ "stringValue": self.function })),
+        ];
+        if let Some(report) = self.report {
+            attributes.push(attribute(
+                "cairo.n_steps",
+                json!({ "intValue": report.n_steps.to_string() }),
+            ));
+            attributes.push(attribute(
+                "cairo.oracle_calls",
+                json!({ "intValue": report.oracle_calls.count.to_string() }),
+            ));
+        }
+        let mut spans = vec![json!({
+            "traceId": trace_id,
+            "spanId": run_span_id,
+            "name": "run",
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": unix_nanos(self.started_at),
+            "endTimeUnixNano": unix_nanos(self.started_at + self.duration),
+            "attributes": attributes,
+            "status": status(self.error.as_deref()),
+        })];
+
+        let calls = self
+            .report
+            .map_or(&[][..], |report| &report.oracle_transcript);
+        spans.extend(calls.iter().enumerate().map(|(i, call)| {
+            json!({
+                "traceId": trace_id,
+                "spanId": span_id(i + 1),
+                "parentSpanId": run_span_id,
+                "name": call.selector,
+                "kind": SPAN_KIND_CLIENT,
+                "startTimeUnixNano": unix_nanos(call.started_at),
+                "endTimeUnixNano": unix_nanos(call.started_at + call.latency),
+                "attributes": call_attributes(call),
+                "status": status(call.error.as_deref()),
+            })
+        }));
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", json!({ "stringValue": service_name }))]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": SCOPE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }]
+        })
+    }
+
+    /// Appends the run to a file holding a request per line, as written by OTLP file exporters.
+    pub fn export(&self, path: &Path, service_name: &str) -> Result<(), Error> {
+        let mut line = self.to_otlp(service_name).to_string().into_bytes();
+        line.push(b'\n');
+        // A single write, so that the lines of concurrent runs are not interleaved
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+}
+
+fn call_attributes(call: &OracleCall) -> Vec<Value> {
+    let mut attributes = vec![
+        attribute("oracle.selector", json!({ "stringValue": call.selector })),
+        attribute("oracle.mocked", json!({ "boolValue": call.mocked })),
+        attribute(
+            "oracle.attempts",
+            json!({ "intValue": call.attempts.to_string() }),
+        ),
+        attribute(
+            "oracle.retries",
+            json!({ "intValue": call.attempts.saturating_sub(1).to_string() }),
+        ),
+        attribute(
+            "http.request.body.size",
+            json!({ "intValue": call.request_size.to_string() }),
+        ),
+        attribute(
+            "http.response.body.size",
+            json!({ "intValue": call.response_size.to_string() }),
+        ),
+    ];
+    if let Some(url) = &call.url {
+        attributes.push(attribute("url.full", json!({ "stringValue": url })));
+    }
+    attributes
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn status(error: Option<&str>) -> Value {
+    match error {
+        None => json!({ "code": STATUS_CODE_OK }),
+        Some(message) => json!({ "code": STATUS_CODE_ERROR, "message": message }),
+    }
+}
+
+/// Timestamps are strings in the JSON encoding of OTLP, as they overflow JSON numbers.
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_run_trace_to_otlp() {
+        let started_at = UNIX_EPOCH + Duration::from_secs(1);
+        let call = OracleCall {
+            selector: "sqrt".to_string(),
+            latency: Duration::from_millis(5),
+            success: false,
+            request: json!({ "n": 9 }),
+            response: None,
+            started_at,
+            url: Some("http://localhost:3000/sqrt".to_string()),
+            mocked: false,
+            attempts: 3,
+            request_size: 8,
+            response_size: 0,
+            error: Some("connection refused".to_string()),
+        };
+        let report = ExecutionReport {
+            n_steps: 10,
+            n_memory_holes: 0,
+            builtin_instance_counter: BTreeMap::new(),
+            oracle_calls: Default::default(),
+            oracle_calls_by_selector: BTreeMap::new(),
+            oracle_transcript: vec![call],
+            profile: None,
+            coverage: None,
+            gas_usage: None,
+        };
+        let trace = RunTrace {
+            run_id: "run-1",
+            function: "main",
+            started_at,
+            duration: Duration::from_millis(20),
+            report: Some(&report),
+            error: None,
+        };
+
+        let otlp = trace.to_otlp("agent");
+        let spans = &otlp["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 2);
+        assert_eq!(spans[0]["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(spans[0]["endTimeUnixNano"], "1020000000");
+        assert_eq!(spans[1]["parentSpanId"], spans[0]["spanId"]);
+        assert_eq!(spans[1]["name"], "sqrt");
+        assert_eq!(spans[1]["status"]["code"], STATUS_CODE_ERROR);
+        assert!(spans[1]["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&attribute("oracle.retries", json!({ "intValue": "2" }))));
+    }
+}
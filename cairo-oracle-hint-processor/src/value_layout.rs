@@ -0,0 +1,400 @@
+use cairo_io_serde::FuncArg;
+use cairo_lang_casm::{
+    builder::{CasmBuilder, Var},
+    casm_build_extend,
+};
+use cairo_lang_sierra::{
+    extensions::core::{CoreLibfunc, CoreType, CoreTypeConcrete},
+    ids::ConcreteTypeId,
+    program_registry::ProgramRegistry,
+};
+use cairo_vm::Felt252;
+use num_traits::ToPrimitive;
+
+use crate::Error;
+
+/// Memory layout of an entry point argument or return value, for the types whose Serde
+/// serialization can be read from or written to the output segment by the entry code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ValueLayout {
+    /// Cells holding their own serialization: felts, integers, `bool` and structs of them.
+    Flat(i16),
+    /// `Array<T>` or `Span<T>` of flat elements of the given size, serialized as their length
+    /// followed by the elements.
+    Array(i16),
+    /// Members of a struct or tuple, serialized in order.
+    Struct(Vec<ValueLayout>),
+}
+
+impl ValueLayout {
+    pub(crate) fn new(
+        ty: &ConcreteTypeId,
+        sierra_program_registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    ) -> Result<Self, Error> {
+        let layout = match sierra_program_registry.get_type(ty)? {
+            CoreTypeConcrete::Felt252(_)
+            | CoreTypeConcrete::Bytes31(_)
+            | CoreTypeConcrete::Uint8(_)
+            | CoreTypeConcrete::Uint16(_)
+            | CoreTypeConcrete::Uint32(_)
+            | CoreTypeConcrete::Uint64(_)
+            | CoreTypeConcrete::Uint128(_)
+            | CoreTypeConcrete::Sint8(_)
+            | CoreTypeConcrete::Sint16(_)
+            | CoreTypeConcrete::Sint32(_)
+            | CoreTypeConcrete::Sint64(_)
+            | CoreTypeConcrete::Sint128(_) => Self::Flat(1),
+            CoreTypeConcrete::NonZero(info) => Self::new(&info.ty, sierra_program_registry)?,
+            CoreTypeConcrete::Snapshot(info) => Self::new(&info.ty, sierra_program_registry)?,
+            // Enums of at most two variants without data, like `bool`, are stored as their variant index
+            CoreTypeConcrete::Enum(info)
+                if info.variants.len() <= 2
+                    && info.variants.iter().all(|variant| {
+                        Self::new(variant, sierra_program_registry)
+                            .is_ok_and(|layout| layout.size() == 0)
+                    }) =>
+            {
+                Self::Flat(1)
+            }
+            CoreTypeConcrete::Struct(info) => {
+                let members = info
+                    .members
+                    .iter()
+                    .map(|member| Self::new(member, sierra_program_registry))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match members.iter().map(Self::flat_size).sum::<Option<i16>>() {
+                    Some(size) => Self::Flat(size),
+                    None => Self::Struct(members),
+                }
+            }
+            CoreTypeConcrete::Array(info) => {
+                match Self::new(&info.ty, sierra_program_registry)?.flat_size() {
+                    Some(size) => Self::Array(size),
+                    None => return Err(Error::UnsupportedEntryPointType(ty.to_string())),
+                }
+            }
+            _ => return Err(Error::UnsupportedEntryPointType(ty.to_string())),
+        };
+        Ok(layout)
+    }
+
+    /// Number of memory cells of the value.
+    pub(crate) fn size(&self) -> i16 {
+        match self {
+            Self::Flat(size) => *size,
+            Self::Array(_) => 2,
+            Self::Struct(members) => members.iter().map(Self::size).sum(),
+        }
+    }
+
+    fn flat_size(&self) -> Option<i16> {
+        match self {
+            Self::Flat(size) => Some(*size),
+            _ => None,
+        }
+    }
+
+    fn load(
+        &self,
+        felts: &mut impl Iterator<Item = Felt252>,
+        args: &mut Vec<FuncArg>,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Flat(size) => {
+                for _ in 0..*size {
+                    args.push(FuncArg::Single(next_felt(felts)?));
+                }
+            }
+            Self::Array(elem_size) => {
+                let len = next_felt(felts)?
+                    .to_usize()
+                    .ok_or_else(|| Error::InvalidArguments("invalid array length".to_string()))?;
+                let cells = (0..len * *elem_size as usize)
+                    .map(|_| next_felt(felts))
+                    .collect::<Result<Vec<_>, _>>()?;
+                args.push(FuncArg::Array(cells));
+            }
+            Self::Struct(members) => {
+                for member in members {
+                    member.load(felts, args)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn next_felt(felts: &mut impl Iterator<Item = Felt252>) -> Result<Felt252, Error> {
+    felts.next().ok_or_else(|| {
+        Error::InvalidArguments("fewer values than the function parameters".to_string())
+    })
+}
+
+/// Lays the serialized arguments out in memory, following the parameters of the entry point.
+pub(crate) fn layout_args(
+    args: &[FuncArg],
+    layouts: &[ValueLayout],
+) -> Result<Vec<FuncArg>, Error> {
+    let mut felts = args
+        .iter()
+        .flat_map(|arg| match arg {
+            FuncArg::Single(felt) => std::slice::from_ref(felt),
+            FuncArg::Array(felts) => felts.as_slice(),
+        })
+        .copied();
+    let mut laid_out = vec![];
+    for layout in layouts {
+        layout.load(&mut felts, &mut laid_out)?;
+    }
+    if felts.next().is_some() {
+        return Err(Error::InvalidArguments(
+            "more values than the function parameters".to_string(),
+        ));
+    }
+    Ok(laid_out)
+}
+
+/// Emits the code writing the serialized return value held in `outputs` into the output segment,
+/// after the panic flag of a `PanicResult`. The final output pointer is left in `[ap - 1]`.
+pub(crate) fn write_return_value(
+    ctx: &mut CasmBuilder,
+    output_ptr: Var,
+    outputs: &[Var],
+    layout: &ValueLayout,
+    panic_result: bool,
+) {
+    if !panic_result {
+        let mut writer = OutputWriter::new(ctx, "Output", output_ptr, outputs);
+        writer.write(layout, 0);
+        writer.finish();
+        return;
+    }
+
+    let panic_flag = outputs[0];
+    casm_build_extend!(ctx, assert panic_flag = *(output_ptr++););
+    // Both variants are right-aligned after the flag, the panic one holding the panic data array
+    let payload_size = outputs.len() as i16 - 1;
+    let mut writer = OutputWriter::new(ctx, "Output", output_ptr, &outputs[1..]);
+    writer.ctx.jump_nz(panic_flag, "OutputPanicked".to_owned());
+    writer.write(layout, payload_size - layout.size());
+    writer.rescope();
+    writer.ctx.jump("OutputWritten".to_owned());
+    writer.ctx.label("OutputPanicked".to_owned());
+    // Written as is, like the return value of a function returning `Array<felt252>`
+    writer.write_array(payload_size - 2, 1, false);
+    writer.rescope();
+    writer.ctx.label("OutputWritten".to_owned());
+    writer.finish();
+}
+
+/// Emits the code writing the serialized arguments held in `args` into the output segment.
+/// The final output pointer is left in `[ap - 1]`.
+pub(crate) fn write_arguments(
+    ctx: &mut CasmBuilder,
+    output_ptr: Var,
+    args: &[Var],
+    layouts: &[ValueLayout],
+) {
+    let mut writer = OutputWriter::new(ctx, "Input", output_ptr, args);
+    let mut offset = 0;
+    for layout in layouts {
+        writer.write(layout, offset);
+        offset += layout.size();
+    }
+    writer.finish();
+}
+
+/// Writes the serialization of a value into the output segment, prefixed with its length.
+///
+/// The value is first copied into a new segment, so it can still be read after the rescopes of
+/// the array copying loops. Those keep `block`, `write_ptr` and `len_ptr` as their last cells, so
+/// that every branch reaches the end of a loop with the same variables.
+struct OutputWriter<'a> {
+    ctx: &'a mut CasmBuilder,
+    /// Prefix of the labels of the writer, unique in the entry code.
+    name: &'static str,
+    loops: usize,
+    block: Var,
+    write_ptr: Var,
+    len_ptr: Var,
+}
+
+impl<'a> OutputWriter<'a> {
+    fn new(ctx: &'a mut CasmBuilder, name: &'static str, output_ptr: Var, cells: &[Var]) -> Self {
+        casm_build_extend! {ctx,
+            tempvar block;
+            hint AllocSegment {} into {dst: block};
+            tempvar block_ptr = block;
+        };
+        for &cell in cells {
+            casm_build_extend!(ctx, assert cell = *(block_ptr++););
+        }
+        casm_build_extend! {ctx,
+            const one = 1;
+            // The length is written once the whole value is
+            tempvar len_ptr = output_ptr;
+            tempvar write_ptr = len_ptr + one;
+        };
+        Self {
+            ctx,
+            name,
+            loops: 0,
+            block,
+            write_ptr,
+            len_ptr,
+        }
+    }
+
+    fn write(&mut self, layout: &ValueLayout, offset: i16) {
+        match layout {
+            ValueLayout::Flat(size) => {
+                for i in 0..*size {
+                    self.write_cell(offset + i);
+                }
+            }
+            ValueLayout::Array(elem_size) => self.write_array(offset, *elem_size, true),
+            ValueLayout::Struct(members) => {
+                let mut offset = offset;
+                for member in members {
+                    self.write(member, offset);
+                    offset += member.size();
+                }
+            }
+        }
+    }
+
+    fn write_cell(&mut self, offset: i16) {
+        let ctx = &mut *self.ctx;
+        let write_ptr = self.write_ptr;
+        let cell = ctx.double_deref(self.block, offset);
+        casm_build_extend! {ctx,
+            tempvar val = cell;
+            assert val = *(write_ptr++);
+        };
+    }
+
+    /// Copies the elements of the array whose pointers are at `offset`, after its length if `with_len`.
+    fn write_array(&mut self, offset: i16, elem_size: i16, with_len: bool) {
+        let copy_label = format!("{}CopyArray{}", self.name, self.loops);
+        let end_label = format!("{}EndCopy{}", self.name, self.loops);
+        self.loops += 1;
+        let ctx = &mut *self.ctx;
+        let (block, write_ptr, len_ptr) = (self.block, self.write_ptr, self.len_ptr);
+        let start = ctx.double_deref(block, offset);
+        let end = ctx.double_deref(block, offset + 1);
+        casm_build_extend! {ctx,
+            tempvar array_start = start;
+            tempvar array_end = end;
+            tempvar array_size = array_end - array_start;
+        };
+        if with_len && elem_size == 1 {
+            casm_build_extend!(ctx, assert array_size = *(write_ptr++););
+        } else if with_len {
+            casm_build_extend! {ctx,
+                const elem_cells = elem_size;
+                tempvar array_len = array_size / elem_cells;
+                assert array_len = *(write_ptr++);
+            };
+        }
+        casm_build_extend! {ctx,
+            // Create loop variables
+            tempvar remaining_cells = array_size;
+            tempvar array_ptr = array_start;
+            tempvar new_write_ptr = write_ptr;
+            tempvar new_block = block;
+            tempvar new_len_ptr = len_ptr;
+            rescope{
+                remaining_cells = remaining_cells,
+                array_ptr = array_ptr,
+                write_ptr = new_write_ptr,
+                block = new_block,
+                len_ptr = new_len_ptr
+            };
+        };
+        ctx.jump_nz(remaining_cells, copy_label.clone());
+        ctx.jump(end_label.clone());
+
+        // Main Loop
+        ctx.label(copy_label.clone());
+        casm_build_extend! {ctx,
+            #{steps = 0;}
+            tempvar val = *(array_ptr++);
+            assert val = *(write_ptr++);
+            const one = 1;
+            // Create loop variables
+            tempvar new_remaining_cells = remaining_cells - one;
+            tempvar new_array_ptr = array_ptr;
+            tempvar new_write_ptr = write_ptr;
+            tempvar new_block = block;
+            tempvar new_len_ptr = len_ptr;
+            // Continue the loop
+            rescope{
+                remaining_cells = new_remaining_cells,
+                array_ptr = new_array_ptr,
+                write_ptr = new_write_ptr,
+                block = new_block,
+                len_ptr = new_len_ptr
+            };
+        };
+        ctx.jump_nz(remaining_cells, copy_label);
+        ctx.label(end_label);
+    }
+
+    /// Keeps only the writer variables, in the same cells on every branch.
+    fn rescope(&mut self) {
+        let ctx = &mut *self.ctx;
+        let (block, write_ptr, len_ptr) = (self.block, self.write_ptr, self.len_ptr);
+        casm_build_extend! {ctx,
+            tempvar new_write_ptr = write_ptr;
+            tempvar new_block = block;
+            tempvar new_len_ptr = len_ptr;
+            rescope{write_ptr = new_write_ptr, block = new_block, len_ptr = new_len_ptr};
+        };
+    }
+
+    /// Writes the length of the serialization, leaving the final output pointer in `[ap - 1]`.
+    fn finish(self) {
+        let ctx = self.ctx;
+        let (write_ptr, len_ptr) = (self.write_ptr, self.len_ptr);
+        casm_build_extend! {ctx,
+            tempvar end_ptr = write_ptr;
+            tempvar data_size = end_ptr - len_ptr;
+            const one = 1;
+            tempvar data_len = data_size - one;
+            assert data_len = *(len_ptr++);
+            tempvar final_write_ptr = end_ptr;
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_args() {
+        // (u32, Array<(felt252, felt252)>, u8)
+        let layouts = [
+            ValueLayout::Flat(1),
+            ValueLayout::Struct(vec![ValueLayout::Array(2), ValueLayout::Flat(1)]),
+        ];
+        let felts = [7, 2, 1, 2, 3, 4, 9].map(Felt252::from).to_vec();
+        assert_eq!(
+            layout_args(&[FuncArg::Array(felts.clone())], &layouts).unwrap(),
+            vec![
+                FuncArg::Single(Felt252::from(7)),
+                FuncArg::Array([1, 2, 3, 4].map(Felt252::from).to_vec()),
+                FuncArg::Single(Felt252::from(9)),
+            ]
+        );
+
+        assert!(matches!(
+            layout_args(&[FuncArg::Array(felts[..6].to_vec())], &layouts),
+            Err(Error::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            layout_args(&[FuncArg::Array(felts)], &layouts[..1]),
+            Err(Error::InvalidArguments(_))
+        ));
+    }
+}
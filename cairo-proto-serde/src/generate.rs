@@ -0,0 +1,143 @@
+use rand::Rng;
+use serde_json::{json, Map, Value};
+
+use crate::configuration::{Configuration, FieldType, PrimitiveType};
+
+/// Generates a random JSON value of a message field type, as accepted by `serialize_cairo_serde`.
+///
+/// Arrays and byte arrays are at most `max_len` long.
+pub fn random_value<R: Rng>(
+    config: &Configuration,
+    ty: &FieldType,
+    max_len: usize,
+    rng: &mut R,
+) -> Value {
+    match ty {
+        FieldType::Primitive(ty) => random_primitive(ty, max_len, rng),
+        FieldType::Message(message_ty) => {
+            let mut object = Map::new();
+            for field in config.messages.get(message_ty).into_iter().flatten() {
+                object.insert(
+                    field.name.clone(),
+                    random_value(config, &field.ty, max_len, rng),
+                );
+            }
+            Value::Object(object)
+        }
+        FieldType::Enum(enum_ty) => {
+            let mappings = config.enums.get(enum_ty).map_or(&[][..], Vec::as_slice);
+            if mappings.is_empty() {
+                json!(0)
+            } else {
+                json!(mappings[rng.gen_range(0..mappings.len())].nb)
+            }
+        }
+        FieldType::Option(inner_ty) => {
+            if rng.gen_ratio(1, 4) {
+                Value::Null
+            } else {
+                random_value(config, inner_ty, max_len, rng)
+            }
+        }
+        FieldType::Array(value_ty) => {
+            let len = rng.gen_range(0..=max_len);
+            Value::Array(
+                (0..len)
+                    .map(|_| random_value(config, value_ty, max_len, rng))
+                    .collect(),
+            )
+        }
+    }
+}
+
+fn random_primitive<R: Rng>(ty: &PrimitiveType, max_len: usize, rng: &mut R) -> Value {
+    // Bounds of the range are picked more often, being the usual edge cases
+    let edge = rng.gen_ratio(1, 4);
+    match ty {
+        PrimitiveType::U64 if edge => json!([0, 1, u64::MAX][rng.gen_range(0..3)]),
+        PrimitiveType::U64 => json!(rng.gen::<u64>()),
+        PrimitiveType::U32 if edge => json!([0, 1, u32::MAX][rng.gen_range(0..3)]),
+        PrimitiveType::U32 => json!(rng.gen::<u32>()),
+        PrimitiveType::I32 if edge => json!([i32::MIN, -1, 0, 1, i32::MAX][rng.gen_range(0..5)]),
+        PrimitiveType::I32 => json!(rng.gen::<i32>()),
+        PrimitiveType::I64 if edge => json!([i64::MIN, -1, 0, 1, i64::MAX][rng.gen_range(0..5)]),
+        PrimitiveType::I64 => json!(rng.gen::<i64>()),
+        PrimitiveType::BOOL => json!(rng.gen::<bool>()),
+        PrimitiveType::BYTEARRAY => {
+            let len = rng.gen_range(0..=max_len);
+            json!((0..len)
+                .map(|_| rng.gen_range(' '..='~'))
+                .collect::<String>())
+        }
+        PrimitiveType::FELT252 if edge => json!("0x0"),
+        PrimitiveType::FELT252 => json!(format!("{:#x}", rng.gen::<u128>())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::configuration::{Field, Mapping};
+    use crate::{deserialize_cairo_serde, serialize_cairo_serde};
+
+    #[test]
+    fn test_random_value_roundtrips() {
+        let configuration = Configuration {
+            enums: BTreeMap::from([(
+                "Side".to_string(),
+                vec![
+                    Mapping {
+                        name: "BUY".to_string(),
+                        nb: 0,
+                    },
+                    Mapping {
+                        name: "SELL".to_string(),
+                        nb: 1,
+                    },
+                ],
+            )]),
+            messages: BTreeMap::from([(
+                "Order".to_string(),
+                vec![
+                    Field {
+                        name: "side".to_string(),
+                        ty: FieldType::Enum("Side".to_string()),
+                    },
+                    Field {
+                        name: "amount".to_string(),
+                        ty: FieldType::Primitive(PrimitiveType::U32),
+                    },
+                    Field {
+                        name: "memo".to_string(),
+                        ty: FieldType::Option(Box::new(FieldType::Primitive(
+                            PrimitiveType::BYTEARRAY,
+                        ))),
+                    },
+                    Field {
+                        name: "ids".to_string(),
+                        ty: FieldType::Array(Box::new(FieldType::Primitive(
+                            PrimitiveType::FELT252,
+                        ))),
+                    },
+                ],
+            )]),
+            ..Default::default()
+        };
+        let ty = FieldType::Message("Order".to_string());
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for _ in 0..50 {
+            let value = random_value(&configuration, &ty, 4, &mut rng);
+            let felts = serialize_cairo_serde(&configuration, &ty, &value);
+            let decoded = deserialize_cairo_serde(&configuration, &ty, &mut felts.as_ref());
+            assert_eq!(decoded["amount"], value["amount"]);
+            assert_eq!(decoded["side"], value["side"]);
+            assert!(value["ids"].as_array().unwrap().len() <= 4);
+        }
+    }
+}
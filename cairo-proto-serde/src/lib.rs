@@ -6,6 +6,7 @@ use num_traits::Zero;
 use serde_json::{json, Map, Value};
 
 pub mod configuration;
+pub mod generate;
 
 fn serialize_primitive(ty: &PrimitiveType, value: &Value) -> Vec<Felt252> {
     let element = match ty {
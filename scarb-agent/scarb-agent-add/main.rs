@@ -0,0 +1,533 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::{Parser, Subcommand};
+use indexmap::IndexMap;
+use indoc::formatdoc;
+use scarb_agent_lib::config::AgentConfig;
+use scarb_metadata::{MetadataCommand, PackageMetadata};
+use scarb_ui::args::PackagesFilter;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Python server of the projects created with `scarb agent-new`.
+const PYTHON_SERVER_PATH: &str = "python/src/main.py";
+
+/// Line starting the block running the Python server, stub routes being added before it.
+const PYTHON_MAIN_GUARD: &str = "if __name__ == \"__main__\":";
+
+/// Add a component to an agent project.
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    packages_filter: PackagesFilter,
+
+    #[command(subcommand)]
+    component: Component,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Component {
+    /// Add an oracle service to the proto definitions, servers config and Python server.
+    Oracle(OracleArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct OracleArgs {
+    /// Name of the service, in PascalCase, e.g. `PriceOracle`.
+    name: String,
+
+    /// Name of the method of the service, the cheatcode selector, the snake case name if not set.
+    #[arg(long)]
+    method: Option<String>,
+
+    /// URL of the server answering the oracle.
+    #[arg(long, default_value = "http://127.0.0.1:3000")]
+    server_url: String,
+
+    /// Do not run `scarb agent-generate` once the oracle is added.
+    #[arg(long, default_value_t = false)]
+    no_generate: bool,
+}
+
+/// What adding a component did to a file.
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    Added,
+    /// The component was already there.
+    Unchanged,
+    /// The file does not exist.
+    Skipped,
+}
+
+/// A JSON value keeping the order of the keys of its objects, not to reorder files written by hand.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum OrderedValue {
+    Object(IndexMap<String, OrderedValue>),
+    Array(Vec<OrderedValue>),
+    Other(Value),
+}
+
+impl Change {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Unchanged => "unchanged",
+            Change::Skipped => "skipped",
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let result = match add() {
+        Ok(data) => json!({
+            "status": "success",
+            "data": data
+        }),
+        Err(err) => json!({
+            "status": "error",
+            "message": format!("{err:#}")
+        }),
+    };
+
+    println!("{}", serde_json::to_string(&result)?);
+
+    std::process::exit(if result["status"] == "error" { 1 } else { 0 });
+}
+
+fn add() -> Result<Value> {
+    let args: Args = Args::parse();
+    let metadata = MetadataCommand::new().inherit_stderr().exec()?;
+    let package = args.packages_filter.match_one(&metadata)?;
+    let config = AgentConfig::load(&package)?;
+
+    match args.component {
+        Component::Oracle(oracle) => add_oracle(&package, &config, oracle),
+    }
+}
+
+fn add_oracle(package: &PackageMetadata, config: &AgentConfig, args: OracleArgs) -> Result<Value> {
+    let name = args.name;
+    ensure!(
+        name.starts_with(|c: char| c.is_ascii_uppercase())
+            && name.chars().all(|c| c.is_ascii_alphanumeric()),
+        "the name of the oracle must be in PascalCase, e.g. `PriceOracle`"
+    );
+    let method = args.method.unwrap_or_else(|| to_snake_case(&name));
+    ensure!(
+        method.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && method
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+        "the method of the oracle must be in snake_case, e.g. `price_oracle`"
+    );
+    // The key of the profiles in the servers config
+    ensure!(
+        method != "profiles",
+        "the method of the oracle cannot be `profiles`, choose another one with --method"
+    );
+
+    let definitions = config
+        .definitions(None)
+        .context("the package defines no oracles, set `definitions` in the [tool.agent] section of its Scarb.toml")?;
+    // Every file is checked before any is written, not to leave the project half updated
+    let proto = fs::read_to_string(&definitions)
+        .with_context(|| format!("failed to read {}", definitions.display()))?;
+    let proto_addition = proto_addition(&proto, &name, &method)?;
+
+    let servers_path = config.servers_config(None);
+    let servers = match fs::read_to_string(&servers_path) {
+        Ok(content) => Some(content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", servers_path.display()))
+        }
+    };
+    let servers = with_server(servers.as_deref(), &method, &args.server_url)
+        .with_context(|| format!("failed to parse {}", servers_path.display()))?;
+
+    let python_path = config.path(PYTHON_SERVER_PATH);
+    let python = python_path
+        .exists()
+        .then(|| fs::read_to_string(&python_path))
+        .transpose()
+        .with_context(|| format!("failed to read {}", python_path.display()))?;
+
+    let proto_change = match proto_addition {
+        Some(addition) => {
+            write(&definitions, format!("{}\n{addition}", proto.trim_end()))?;
+            Change::Added
+        }
+        None => Change::Unchanged,
+    };
+
+    let servers_change = match servers {
+        Some(servers) => {
+            write(&servers_path, servers)?;
+            Change::Added
+        }
+        None => Change::Unchanged,
+    };
+
+    let python_change = match python {
+        None => Change::Skipped,
+        Some(python) if has_route(&python, &method) => Change::Unchanged,
+        Some(python) => {
+            write(&python_path, with_route(&python, &name, &method))?;
+            Change::Added
+        }
+    };
+
+    let generated = !args.no_generate;
+    if generated {
+        generate(package)?;
+    }
+
+    Ok(json!({
+        "oracle": name,
+        "selector": method,
+        "proto": { "path": definitions, "change": proto_change.as_str() },
+        "servers_config": { "path": servers_path, "change": servers_change.as_str() },
+        "python": { "path": python_path, "change": python_change.as_str() },
+        "generated": generated,
+    }))
+}
+
+/// The service and messages to append to the proto definitions, `None` if already defined.
+fn proto_addition(proto: &str, name: &str, method: &str) -> Result<Option<String>> {
+    let tokens = proto_tokens(proto);
+    let defines = |keyword: &str, ident: &str| {
+        tokens
+            .windows(2)
+            .any(|pair| pair[0] == keyword && pair[1] == ident)
+    };
+    let request = format!("{name}Request");
+    let response = format!("{name}Response");
+
+    if defines("service", name) {
+        // Added before, or written by hand, either way not to be touched
+        return Ok(None);
+    }
+    if defines("rpc", method) {
+        bail!("the selector `{method}` is already used by another service, choose another one with --method");
+    }
+    for message in [&request, &response] {
+        if defines("message", message) {
+            bail!("the message `{message}` is already defined, rename the oracle or remove it");
+        }
+    }
+
+    Ok(Some(formatdoc! {r#"
+
+        // ======== {name} ========
+
+        message {request} {{
+            int64 value = 1;
+        }}
+
+        message {response} {{
+            int64 result = 1;
+        }}
+
+        service {name} {{
+            rpc {method}({request}) returns ({response}) {{}}
+        }}
+    "#}))
+}
+
+/// Adds the server of the oracle to the servers config, `None` if it already has one.
+fn with_server(servers: Option<&str>, method: &str, server_url: &str) -> Result<Option<String>> {
+    let mut servers = match servers {
+        Some(content) => serde_json::from_str::<IndexMap<String, OrderedValue>>(content)?,
+        None => IndexMap::new(),
+    };
+    // An existing entry is kept, its URL may have been changed
+    if servers.contains_key(method) {
+        return Ok(None);
+    }
+    let server = IndexMap::from([
+        (
+            "server_url".to_string(),
+            OrderedValue::Other(json!(server_url)),
+        ),
+        ("polling".to_string(), OrderedValue::Other(json!(false))),
+    ]);
+    servers.insert(method.to_string(), OrderedValue::Object(server));
+    Ok(Some(serde_json::to_string_pretty(&servers)?))
+}
+
+/// Splits proto definitions into identifiers and symbols, leaving out comments and strings.
+fn proto_tokens(proto: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = proto.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' | '\'' => {
+                chars.by_ref().take_while(|&q| q != c).for_each(drop);
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+            c if c.is_whitespace() => {}
+            c => tokens.push(c.to_string()),
+        }
+    }
+    tokens
+}
+
+fn has_route(python: &str, method: &str) -> bool {
+    python.contains(&format!("@app.post(\"/{method}\")"))
+}
+
+/// Adds a stub route of the oracle to the Python server, before it is run.
+fn with_route(python: &str, name: &str, method: &str) -> String {
+    let route = formatdoc! {r#"
+
+        # ========== {name} ==========
+        @app.post("/{method}")
+        async def {method}(request: Request):
+            """
+            Answers the {name} oracle, receiving a {name}Request and returning a {name}Response.
+            """
+            data = await request.json()
+            # Insert custom oracle logic here
+            return {{"result": data["value"]}}
+    "#};
+    match python.find(PYTHON_MAIN_GUARD) {
+        Some(index) => {
+            let (head, tail) = python.split_at(index);
+            format!("{}\n{route}\n{tail}", head.trim_end())
+        }
+        None => format!("{}\n{route}", python.trim_end()),
+    }
+}
+
+/// Runs `scarb agent-generate` on the package, its output being kept off the JSON output.
+fn generate(package: &PackageMetadata) -> Result<()> {
+    let scarb = std::env::var("SCARB").unwrap_or_else(|_| "scarb".to_string());
+    let output = Command::new(scarb)
+        .arg("--manifest-path")
+        .arg(&package.manifest_path)
+        .arg("agent-generate")
+        .output()
+        .context("failed to run `scarb agent-generate`")?;
+    if !output.status.success() {
+        bail!(
+            "`scarb agent-generate` failed, fix the definitions and run it again:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+fn write(path: &Path, content: String) -> Result<()> {
+    fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    const PROTO: &str = indoc! {r#"
+        syntax = "proto3";
+
+        // service Commented { rpc commented(A) returns (B) {} }
+        /* message Hidden { int64 value = 1; } */
+        option go_package = "service Quoted";
+
+        message SqrtRequest {
+            int64 n = 1;
+        }
+
+        message SqrtResponse {
+            int64 result = 1;
+        }
+
+        service SqrtOracle {
+            rpc sqrt(SqrtRequest) returns (SqrtResponse) {}
+        }
+    "#};
+
+    const PYTHON: &str = indoc! {r#"
+        app = FastAPI()
+
+        @app.post("/sqrt")
+        async def sqrt(request: Request):
+            return {"result": 1}
+
+        if __name__ == "__main__":
+            uvicorn.run(app)
+    "#};
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("PriceOracle"), "price_oracle");
+        assert_eq!(to_snake_case("Sqrt"), "sqrt");
+        assert_eq!(to_snake_case("Oracle2"), "oracle2");
+    }
+
+    #[test]
+    fn test_proto_tokens() {
+        let tokens = proto_tokens(PROTO);
+        assert!(tokens
+            .windows(2)
+            .any(|pair| pair == ["service", "SqrtOracle"]));
+        assert!(tokens.windows(2).any(|pair| pair == ["rpc", "sqrt"]));
+        // Comments and strings are left out
+        for ident in ["Commented", "commented", "Hidden", "Quoted"] {
+            assert!(!tokens.iter().any(|token| token == ident), "{ident}");
+        }
+        assert_eq!(
+            proto_tokens("option a.b = 1;"),
+            vec!["option", "a.b", "=", "1", ";"]
+        );
+    }
+
+    #[test]
+    fn test_proto_addition() {
+        let addition = proto_addition(PROTO, "PriceOracle", "price_oracle")
+            .unwrap()
+            .unwrap();
+        assert!(addition.contains("message PriceOracleRequest {"));
+        assert!(addition.contains("message PriceOracleResponse {"));
+        assert!(addition
+            .contains("rpc price_oracle(PriceOracleRequest) returns (PriceOracleResponse) {}"));
+
+        // Adding an oracle again changes nothing
+        let proto = format!("{PROTO}{addition}");
+        assert!(proto_addition(&proto, "PriceOracle", "price_oracle")
+            .unwrap()
+            .is_none());
+        assert!(proto_addition(PROTO, "SqrtOracle", "other")
+            .unwrap()
+            .is_none());
+
+        // Names only found in comments and strings are free
+        assert!(proto_addition(PROTO, "Commented", "commented")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_proto_addition_clash() {
+        let error = proto_addition(PROTO, "PriceOracle", "sqrt").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("the selector `sqrt` is already used by another service"));
+
+        let error = proto_addition(PROTO, "Sqrt", "price").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("the message `SqrtRequest` is already defined"));
+    }
+
+    #[test]
+    fn test_with_route() {
+        assert!(has_route(PYTHON, "sqrt"));
+        assert!(!has_route(PYTHON, "price_oracle"));
+
+        let python = with_route(PYTHON, "PriceOracle", "price_oracle");
+        assert!(has_route(&python, "price_oracle"));
+        let route = python.find("@app.post(\"/price_oracle\")").unwrap();
+        assert!(python.find("@app.post(\"/sqrt\")").unwrap() < route);
+        assert!(route < python.find(PYTHON_MAIN_GUARD).unwrap());
+
+        // Without a main guard, the route is appended
+        let python = with_route("app = FastAPI()\n", "PriceOracle", "price_oracle");
+        assert!(python.starts_with("app = FastAPI()\n"));
+        assert!(python
+            .trim_end()
+            .ends_with("return {\"result\": data[\"value\"]}"));
+    }
+
+    #[test]
+    fn test_with_server() {
+        let servers = indoc! {r#"
+            {
+              "sqrt": {
+                "server_url": "http://127.0.0.1:3000",
+                "polling": true,
+                "polling_config": {
+                  "max_attempts": 3,
+                  "polling_interval": 1,
+                  "request_timeout": 5,
+                  "overall_timeout": 30
+                }
+              },
+              "average": {
+                "server_url": "http://127.0.0.1:3001"
+              }
+            }"#};
+
+        // The existing entries keep the order of their keys
+        let written = with_server(Some(servers), "price_oracle", "http://127.0.0.1:3002")
+            .unwrap()
+            .unwrap();
+        let expected = servers.strip_suffix("\n}").unwrap().to_string()
+            + indoc! {r#"
+                ,
+                  "price_oracle": {
+                    "server_url": "http://127.0.0.1:3002",
+                    "polling": false
+                  }
+                }"#};
+        assert_eq!(written, expected);
+
+        // An existing entry is kept
+        assert!(with_server(Some(servers), "sqrt", "http://127.0.0.1:3002")
+            .unwrap()
+            .is_none());
+
+        let written = with_server(None, "sqrt", "http://127.0.0.1:3000")
+            .unwrap()
+            .unwrap();
+        let written: Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(
+            written,
+            json!({ "sqrt": { "server_url": "http://127.0.0.1:3000", "polling": false } })
+        );
+    }
+}
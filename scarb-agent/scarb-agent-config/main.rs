@@ -0,0 +1,71 @@
+use anyhow::Result;
+use clap::Parser;
+use scarb_agent_lib::config::AgentConfig;
+use scarb_metadata::MetadataCommand;
+use scarb_ui::args::PackagesFilter;
+use serde_json::{json, Value};
+
+/// Print the agent configuration of a package, with its defaults applied.
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    packages_filter: PackagesFilter,
+
+    /// Oracle profile the servers are resolved for.
+    #[arg(long, value_name = "PROFILE")]
+    oracle_profile: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let result = match config() {
+        Ok((data, problems)) if problems.is_empty() => json!({
+            "status": "success",
+            "data": data
+        }),
+        // Still printing the configuration, to see where the missing files are looked for
+        Ok((data, problems)) => json!({
+            "status": "error",
+            "message": problems.join("\n"),
+            "data": data
+        }),
+        Err(err) => json!({
+            "status": "error",
+            "message": format!("{err:#}")
+        }),
+    };
+
+    println!("{}", serde_json::to_string(&result)?);
+
+    std::process::exit(if result["status"] == "error" { 1 } else { 0 });
+}
+
+fn config() -> Result<(Value, Vec<String>)> {
+    let args: Args = Args::parse();
+    let metadata = MetadataCommand::new().inherit_stderr().exec()?;
+    let package = args.packages_filter.match_one(&metadata)?;
+    let config = AgentConfig::from_package(&package)?;
+
+    let unknown_keys = config.unknown_keys();
+    let mut problems: Vec<String> = unknown_keys
+        .iter()
+        .map(|key| format!("unknown key `{key}` in [tool.agent]"))
+        .collect();
+    problems.extend(config.check_files());
+    let servers = match config.load_servers_config(None, args.oracle_profile) {
+        Ok(servers) => Some(servers),
+        Err(err) => {
+            problems.push(format!("{err:#}"));
+            None
+        }
+    };
+
+    let data = json!({
+        "package": package.name,
+        "manifest_path": package.manifest_path,
+        "config": config.resolved(),
+        "unknown_keys": unknown_keys,
+        "servers": servers,
+    });
+    Ok((data, problems))
+}
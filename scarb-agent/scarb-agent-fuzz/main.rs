@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use cairo_io_serde::cairo_input::process_value_args;
+use cairo_io_serde::generate::{random_input, shrink_input, GenerateConfig};
+use cairo_io_serde::schema::{parse_schema_file, Schema};
+use cairo_lang_hints_test_runner::as_short_string;
+use cairo_lang_sierra::program::VersionedProgram;
+use cairo_oracle_hint_processor::cairo_run::{PreparedProgram, RunLimits};
+use cairo_oracle_hint_processor::compiled::CASM_CACHE_DIR;
+use cairo_oracle_hint_processor::mock::OracleMock;
+use cairo_oracle_hint_processor::{run_1, Error, RunOptions};
+use cairo_proto_serde::configuration::Configuration;
+use cairo_proto_serde::generate::random_value;
+use cairo_vm::types::layout_name::LayoutName;
+use clap::Parser;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use scarb_agent_lib::config::AgentConfig;
+use scarb_agent_lib::layout::{str_into_layout, validate_layout};
+use scarb_metadata::{Metadata, MetadataCommand, ScarbCommand};
+use scarb_ui::args::PackagesFilter;
+use serde_json::{json, Value};
+
+/// Run the agent entry point with random inputs and oracle responses, reporting unexpected panics.
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    packages_filter: PackagesFilter,
+
+    #[arg(long, default_value_t = false)]
+    no_build: bool,
+
+    #[arg(long)]
+    oracle_lock: Option<PathBuf>,
+
+    #[clap(long = "layout", default_value = "all_cairo", value_parser = validate_layout)]
+    layout: String,
+
+    /// Path of the function to fuzz, e.g. `::quote`.
+    #[arg(long, default_value = "::main")]
+    function: String,
+
+    /// Number of random inputs to run.
+    #[arg(long, default_value_t = 100)]
+    runs: usize,
+
+    /// Seed of the random inputs, to reproduce a previous session. Random if not set.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Maximum length of the generated arrays and spans.
+    #[arg(long, default_value_t = 8)]
+    max_array_len: usize,
+
+    /// Maximum length in bytes of the generated byte arrays.
+    #[arg(long, default_value_t = 64)]
+    max_byte_array_len: usize,
+
+    /// Panic message the entry point is expected to raise on invalid inputs, not reported. May be repeated.
+    #[arg(long)]
+    expected_panic: Vec<String>,
+
+    /// Maximum number of runs spent minimizing each failing input.
+    #[arg(long, default_value_t = 500)]
+    max_shrinks: usize,
+
+    /// Maximum number of steps each run may execute.
+    #[arg(long)]
+    max_steps: Option<usize>,
+
+    /// Maximum duration of each run in seconds.
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Write the failing inputs to this file as test cases, to replay them with `scarb agent-test --cases`.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+/// A failing input, minimized, with the oracle responses it ran with.
+struct Failure {
+    input: Value,
+    mocks: Vec<OracleMock>,
+}
+
+/// Runs the entry point of a package on given inputs.
+struct Fuzzer {
+    configuration: Configuration,
+    program: PreparedProgram,
+    schema: Schema,
+    function: String,
+    layout: LayoutName,
+    limits: RunLimits,
+    expected_panics: Vec<String>,
+}
+
+impl Fuzzer {
+    /// Runs an input, returning why it failed if it did.
+    fn check(&self, input: &Value, mocks: &[OracleMock]) -> Result<Option<String>> {
+        let args = process_value_args(input, &self.schema)
+            .map_err(|e| anyhow::anyhow!("generated an invalid input {input}: {e}"))?;
+        let result = run_1(
+            &self.configuration,
+            &self.program,
+            &self.function,
+            &args,
+            &self.schema,
+            &RunOptions {
+                layout: self.layout,
+                limits: self.limits,
+                mocks: Some(mocks),
+                ..Default::default()
+            },
+        );
+        Ok(match result {
+            Ok(_) => None,
+            Err(Error::RunPanic { panic_data, .. }) => {
+                let messages = panic_data
+                    .iter()
+                    .map(|felt| as_short_string(felt).unwrap_or_else(|| felt.to_string()))
+                    .collect::<Vec<_>>();
+                let expected = messages
+                    .iter()
+                    .any(|message| self.expected_panics.contains(message));
+                (!expected).then(|| format!("panicked with [{}]", messages.join(", ")))
+            }
+            Err(e) => Some(e.to_string()),
+        })
+    }
+
+    /// Shrinks an input as long as it keeps failing with the same message.
+    fn minimize(
+        &self,
+        mut input: Value,
+        mocks: &[OracleMock],
+        message: &str,
+        max_shrinks: usize,
+    ) -> Result<Value> {
+        let mut shrinks = 0;
+        'shrink: while shrinks < max_shrinks {
+            for variant in shrink_input(&input, &self.schema) {
+                if shrinks == max_shrinks {
+                    break 'shrink;
+                }
+                shrinks += 1;
+                if self.check(&variant, mocks)?.as_deref() == Some(message) {
+                    input = variant;
+                    continue 'shrink;
+                }
+            }
+            break;
+        }
+        Ok(input)
+    }
+}
+
+/// Returns a mock of every oracle method, answering with a random response of its output type.
+fn random_mocks<R: Rng>(
+    configuration: &Configuration,
+    max_len: usize,
+    rng: &mut R,
+) -> Vec<OracleMock> {
+    let mut methods = configuration
+        .services
+        .values()
+        .flat_map(|service| service.methods.iter())
+        .collect::<Vec<_>>();
+    // Sorted for the same seed to generate the same responses
+    methods.sort_by(|(a, _), (b, _)| a.cmp(b));
+    methods
+        .into_iter()
+        .map(|(selector, method)| OracleMock {
+            selector: selector.clone(),
+            request: None,
+            response: random_value(configuration, &method.output, max_len, rng),
+        })
+        .collect()
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args: Args = Args::parse();
+    let metadata = MetadataCommand::new().inherit_stderr().exec()?;
+    let package = args.packages_filter.match_one(&metadata)?;
+
+    if !args.no_build {
+        ScarbCommand::new()
+            .arg("build")
+            .env(
+                "SCARB_PACKAGES_FILTER",
+                PackagesFilter::generate_for::<Metadata>(std::iter::once(&package)).to_env(),
+            )
+            .run()?;
+    }
+    let profile = std::env::var("SCARB_PROFILE").unwrap_or("dev".into());
+    let default_target_dir = metadata.runtime_manifest.join("target");
+    let sierra_path = metadata
+        .target_dir
+        .clone()
+        .unwrap_or(default_target_dir)
+        .join(profile)
+        .join(format!("{}.sierra.json", package.name));
+    let sierra_program = serde_json::from_str::<VersionedProgram>(
+        &fs::read_to_string(&sierra_path)
+            .with_context(|| format!("failed to read file: {sierra_path}"))?,
+    )?
+    .into_v1()
+    .context("failed to load Sierra program")?
+    .program;
+
+    let config = AgentConfig::load(&package)?;
+    let lock_output = config.oracle_lock(args.oracle_lock.clone());
+    let configuration: Configuration =
+        serde_json::from_reader(BufReader::new(File::open(lock_output)?))?;
+
+    let schema = parse_schema_file(&config.cairo_schema())
+        .map_err(|e| anyhow::anyhow!("failed to parse cairo schema: {e}"))?
+        .for_function(&args.function);
+
+    let fuzzer = Fuzzer {
+        configuration,
+        program: PreparedProgram::new(
+            sierra_program,
+            Some(sierra_path.with_file_name(CASM_CACHE_DIR).as_std_path()),
+        )?,
+        schema,
+        function: args.function.clone(),
+        layout: str_into_layout(&args.layout),
+        limits: RunLimits {
+            max_steps: args.max_steps,
+            timeout: args.timeout.map(Duration::from_secs),
+            max_memory_cells: None,
+        },
+        expected_panics: args.expected_panic.clone(),
+    };
+    let generate_config = GenerateConfig {
+        max_array_len: args.max_array_len,
+        max_byte_array_len: args.max_byte_array_len,
+    };
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    println!(
+        "fuzzing {}{} with seed {seed} ...",
+        package.name, args.function
+    );
+
+    let mut failures = Vec::new();
+    let mut failed_runs = 0;
+    let mut seen = HashSet::new();
+    for _ in 0..args.runs {
+        let input = random_input(&fuzzer.schema, &generate_config, &mut rng)
+            .map_err(|e| anyhow::anyhow!("failed to generate an input: {e}"))?;
+        let mocks = random_mocks(&fuzzer.configuration, args.max_array_len, &mut rng);
+        let Some(message) = fuzzer.check(&input, &mocks)? else {
+            continue;
+        };
+        failed_runs += 1;
+        // Inputs failing the same way are reported once
+        if !seen.insert(message.clone()) {
+            continue;
+        }
+        let input = fuzzer.minimize(input, &mocks, &message, args.max_shrinks)?;
+        println!("failure {}: {message}", failures.len() + 1);
+        println!("  input: {input}");
+        for mock in &mocks {
+            println!("  oracle {}: {}", mock.selector, mock.response);
+        }
+        failures.push(Failure { input, mocks });
+    }
+
+    if let Some(output) = &args.output {
+        let cases = json!({
+            "function": args.function,
+            "cases": failures
+                .iter()
+                .enumerate()
+                .map(|(i, failure)| json!({
+                    "name": format!("fuzz_{seed}_{}", i + 1),
+                    "input": failure.input,
+                    "mocks": failure.mocks,
+                }))
+                .collect::<Vec<_>>(),
+        });
+        fs::write(output, serde_yaml::to_string(&cases)?)
+            .with_context(|| format!("failed to write failing inputs: {}", output.display()))?;
+    }
+
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} runs failed in {} distinct ways, rerun with --seed {seed} to reproduce",
+            failed_runs,
+            args.runs,
+            failures.len()
+        );
+    }
+    println!("fuzz result: ok. {} runs", args.runs);
+    Ok(())
+}
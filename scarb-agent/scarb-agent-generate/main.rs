@@ -1,11 +1,15 @@
+use anyhow::{bail, ensure, Context, Result};
 use cairo_proto_build::Config;
 use clap::Parser;
-use scarb_agent_lib::utils::absolute_path;
+use scarb_agent_lib::config::AgentConfig;
 use scarb_metadata::MetadataCommand;
 use scarb_ui::args::PackagesFilter;
-use std::{io::Result, path::PathBuf};
+use std::path::PathBuf;
 
-/// Execute the main function of a package.
+/// Directory of the proto files shared by the agents of a workspace, at its root.
+const SHARED_PROTO_DIR: &str = "proto";
+
+/// Generate the Cairo code and Oracle.lock of the oracles of the matched packages.
 #[derive(Parser, Clone, Debug)]
 #[command(author, version)]
 struct Args {
@@ -24,38 +28,69 @@ struct Args {
 
     #[structopt(long)]
     oracle_lock: Option<PathBuf>,
+
+    /// Directory of proto files shared by the agents, proto at the workspace root if it exists.
+    #[arg(long)]
+    shared_proto: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
     let args: Args = Args::parse();
-    let metadata = MetadataCommand::new().inherit_stderr().exec().unwrap();
-    let package = args.packages_filter.match_one(&metadata).unwrap();
-
-    let definitions = absolute_path(&package, args.definitions, "definitions", None)
-        .expect("oracle.proto definitions path must be provided either as an argument (--definitions proto/oracle.proto) or in the Scarb.toml file in [tool.agent] section.");
-
-    let includes = definitions.parent().unwrap();
-
-    let cairo_output: PathBuf = absolute_path(&package, args.cairo_output, "cairo_output", Some(PathBuf::from("src")))
-        .expect("cairo output path must be provided either as an argument (--cairo-output src) or in the Scarb.toml file in the [tool.agent] section.");
-
-    let oracle_module = args
-        .oracle_module
-        .or_else(|| {
-            package
-                .tool_metadata("agent")
-                .and_then(|tool_config| tool_config["oracle_module"].as_str().map(String::from))
-        })
-        .unwrap_or("lib.cairo".to_string());
-
-    let lock_output = absolute_path(&package, args.oracle_lock, "oracle_lock", Some(PathBuf::from("Oracle.lock")))
-        .expect("lock path must be provided either as an argument (--oracle-lock src) or in the Scarb.toml file in the [tool.agent] section.");
-
-    Config::new()
-        .out_dir(cairo_output)
-        .oracle_module(&oracle_module)
-        .oracle_lock(lock_output)
-        .compile_protos(&[&definitions], &[includes])?;
+    let metadata = MetadataCommand::new().inherit_stderr().exec()?;
+    let matched = args.packages_filter.match_many(&metadata)?;
+    let shared_proto = args
+        .shared_proto
+        .map(|dir| std::env::current_dir().map(|cwd| cwd.join(dir)))
+        .transpose()?;
+    let workspace_proto = metadata
+        .workspace
+        .root
+        .join(SHARED_PROTO_DIR)
+        .into_std_path_buf();
+
+    let single = matched.len() == 1;
+    // Paths of a single package, every matched package would write to the same files
+    for (flag, set) in [
+        ("--definitions", args.definitions.is_some()),
+        ("--cairo-output", args.cairo_output.is_some()),
+        ("--oracle-lock", args.oracle_lock.is_some()),
+    ] {
+        ensure!(
+            single || !set,
+            "{flag} applies to a single package, select one with --package"
+        );
+    }
+    let mut generated = 0;
+    for package in matched {
+        let config = AgentConfig::load(&package)?;
+        let Some(definitions) = config.definitions(args.definitions.clone()) else {
+            // Members of a workspace that are not agents have nothing to generate
+            if single {
+                bail!("oracle.proto definitions path must be provided either as an argument (--definitions proto/oracle.proto) or in the Scarb.toml file in [tool.agent] section.");
+            }
+            continue;
+        };
+        let definitions_dir = definitions.parent().context("invalid definitions path")?;
+        let shared_dir = config
+            .shared_proto(shared_proto.clone())
+            .or_else(|| Some(workspace_proto.clone()).filter(|dir| dir.is_dir()))
+            .filter(|dir| dir != definitions_dir);
+        let includes = std::iter::once(definitions_dir.to_path_buf())
+            .chain(shared_dir)
+            .collect::<Vec<_>>();
+
+        Config::new()
+            .out_dir(config.cairo_output(args.cairo_output.clone()))
+            .oracle_module(&config.oracle_module(args.oracle_module.clone()))
+            .oracle_lock(config.oracle_lock(args.oracle_lock.clone()))
+            .compile_protos(&[&definitions], &includes)
+            .with_context(|| format!("failed to generate the oracles of {}", package.name))?;
+        println!("Generated the oracles of {}", package.name);
+        generated += 1;
+    }
+    if generated == 0 {
+        bail!("no package defines oracles in the [tool.agent] section of its Scarb.toml");
+    }
 
     println!("Done");
     Ok(())
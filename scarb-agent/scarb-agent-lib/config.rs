@@ -0,0 +1,577 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use cairo_proto_serde::configuration::{Configuration, ServerConfig};
+use scarb_metadata::PackageMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::hooks::{Hook, HookConfig, HookStage};
+
+/// Environment variable selecting the oracle profile when `--oracle-profile` is not given.
+pub const ORACLE_PROFILE_ENV: &str = "AGENT_ORACLE_PROFILE";
+
+/// The `[tool.agent]` section of Scarb.toml, paths being relative to the package root.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolConfig {
+    /// Proto file defining the oracles.
+    pub definitions: Option<PathBuf>,
+    /// Directory of proto files shared by the agents of a workspace, importable by `definitions`.
+    pub shared_proto: Option<PathBuf>,
+    /// Directory the Cairo code of the oracles is generated in.
+    pub cairo_output: Option<PathBuf>,
+    /// Module of `cairo_output` declaring the generated oracles.
+    pub oracle_module: Option<String>,
+    pub oracle_lock: Option<PathBuf>,
+    pub servers_config: Option<PathBuf>,
+    pub cairo_schema: Option<PathBuf>,
+    pub oracle_mocks: Option<PathBuf>,
+    pub test_cases: Option<PathBuf>,
+    pub snapshots: Option<PathBuf>,
+    pub preprocess: Option<HookConfig>,
+    pub postprocess: Option<HookConfig>,
+    /// Keys not understood by the agent.
+    #[serde(flatten, skip_serializing)]
+    pub unknown: BTreeMap<String, Value>,
+}
+
+/// The agent configuration of a package, resolving the settings of its `[tool.agent]` section.
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    /// Directory of the manifest of the package.
+    pub root: PathBuf,
+    pub tool: ToolConfig,
+}
+
+/// A servers config file, mapping the oracle services to their servers.
+///
+/// The `profiles` key is reserved, no oracle can be named `profiles`.
+#[derive(Debug, Default, Deserialize)]
+struct ServersFile {
+    /// Servers replacing the default ones when their profile is selected, only read from
+    /// the default file.
+    #[serde(default)]
+    profiles: BTreeMap<String, HashMap<String, ServerConfig>>,
+    #[serde(flatten)]
+    servers: HashMap<String, ServerConfig>,
+}
+
+impl ServersFile {
+    fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read servers config file: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse servers config file: {}", path.display()))
+    }
+}
+
+/// The file holding the servers of a profile, `servers.<profile>.json` for `servers.json`.
+fn profile_path(path: &Path, profile: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{stem}.{profile}.{}", extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{stem}.{profile}")),
+    }
+}
+
+/// The oracle profile given as an argument, or else with [`ORACLE_PROFILE_ENV`].
+fn oracle_profile(profile: Option<String>) -> Option<String> {
+    profile
+        .or_else(|| std::env::var(ORACLE_PROFILE_ENV).ok())
+        .filter(|profile| !profile.is_empty())
+}
+
+/// Reads the servers of `path`, overridden by those of the profile if any.
+fn read_servers_config(
+    path: &Path,
+    profile: Option<String>,
+) -> Result<HashMap<String, ServerConfig>> {
+    let Some(profile) = profile else {
+        return Ok(ServersFile::read(path)?.servers);
+    };
+
+    let profile_path = profile_path(path, &profile);
+    if profile_path.exists() {
+        // The default file is optional when every server is in the profile file
+        let mut servers = if path.exists() {
+            ServersFile::read(path)?.servers
+        } else {
+            HashMap::new()
+        };
+        let profile_file = ServersFile::read(&profile_path)?;
+        ensure!(
+            profile_file.profiles.is_empty(),
+            "`profiles` of {} is only read from {}",
+            profile_path.display(),
+            path.display()
+        );
+        servers.extend(profile_file.servers);
+        return Ok(servers);
+    }
+    let mut file = ServersFile::read(path)?;
+    let overrides = file.profiles.remove(&profile).with_context(|| {
+        format!(
+            "unknown oracle profile `{profile}`: {} does not exist and {} has no `profiles.{profile}` entry",
+            profile_path.display(),
+            path.display()
+        )
+    })?;
+    file.servers.extend(overrides);
+    Ok(file.servers)
+}
+
+/// A configuration with every default applied and every path made absolute.
+#[derive(Debug, Serialize)]
+pub struct ResolvedConfig {
+    pub definitions: Option<PathBuf>,
+    pub shared_proto: Option<PathBuf>,
+    pub cairo_output: PathBuf,
+    pub oracle_module: String,
+    pub oracle_lock: PathBuf,
+    pub servers_config: PathBuf,
+    pub cairo_schema: PathBuf,
+    pub oracle_mocks: Option<PathBuf>,
+    pub test_cases: Option<PathBuf>,
+    pub snapshots: PathBuf,
+    pub preprocess: HookConfig,
+    pub postprocess: HookConfig,
+}
+
+impl AgentConfig {
+    /// Deserializes the `[tool.agent]` section of a package.
+    pub fn from_package(package: &PackageMetadata) -> Result<Self> {
+        let root = package
+            .manifest_path
+            .parent()
+            .context("invalid manifest path")?
+            .as_std_path()
+            .to_path_buf();
+        let tool = match package.tool_metadata("agent") {
+            Some(section) => serde_json::from_value(section.clone())
+                .with_context(|| format!("invalid [tool.agent] section in {}", package.name))?,
+            None => ToolConfig::default(),
+        };
+        Ok(Self { root, tool })
+    }
+
+    /// Deserializes the `[tool.agent]` section of a package, warning about its unknown keys.
+    pub fn load(package: &PackageMetadata) -> Result<Self> {
+        let config = Self::from_package(package)?;
+        for key in config.unknown_keys() {
+            eprintln!(
+                "warning: unknown key `{key}` in [tool.agent] of {}",
+                package.name
+            );
+        }
+        Ok(config)
+    }
+
+    /// Keys of the section not understood by the agent, those of the hook sections being
+    /// prefixed with their stage, e.g. `preprocess.uri`.
+    pub fn unknown_keys(&self) -> Vec<String> {
+        let hooks = [
+            (HookStage::Preprocess, &self.tool.preprocess),
+            (HookStage::Postprocess, &self.tool.postprocess),
+        ];
+        let hook_keys = hooks.into_iter().flat_map(|(stage, config)| {
+            config
+                .iter()
+                .flat_map(|config| config.unknown.keys())
+                .map(move |key| format!("{stage}.{key}"))
+        });
+        self.tool.unknown.keys().cloned().chain(hook_keys).collect()
+    }
+
+    /// Makes a path relative to the package root absolute.
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root.join(path)
+    }
+
+    fn resolve(&self, arg: Option<PathBuf>, key: &Option<PathBuf>, default: &str) -> PathBuf {
+        self.path(
+            arg.or_else(|| key.clone())
+                .unwrap_or_else(|| default.into()),
+        )
+    }
+
+    /// A path with no default, or its default when that file exists.
+    fn resolve_optional(
+        &self,
+        arg: Option<PathBuf>,
+        key: &Option<PathBuf>,
+        default: &str,
+    ) -> Option<PathBuf> {
+        match arg.or_else(|| key.clone()) {
+            Some(path) => Some(self.path(path)),
+            None => Some(self.path(default)).filter(|path| path.exists()),
+        }
+    }
+
+    pub fn definitions(&self, arg: Option<PathBuf>) -> Option<PathBuf> {
+        arg.or_else(|| self.tool.definitions.clone())
+            .map(|path| self.path(path))
+    }
+
+    pub fn shared_proto(&self, arg: Option<PathBuf>) -> Option<PathBuf> {
+        arg.or_else(|| self.tool.shared_proto.clone())
+            .map(|path| self.path(path))
+    }
+
+    pub fn cairo_output(&self, arg: Option<PathBuf>) -> PathBuf {
+        self.resolve(arg, &self.tool.cairo_output, "src")
+    }
+
+    pub fn oracle_module(&self, arg: Option<String>) -> String {
+        arg.or_else(|| self.tool.oracle_module.clone())
+            .unwrap_or_else(|| "lib.cairo".to_string())
+    }
+
+    pub fn oracle_lock(&self, arg: Option<PathBuf>) -> PathBuf {
+        self.resolve(arg, &self.tool.oracle_lock, "Oracle.lock")
+    }
+
+    pub fn servers_config(&self, arg: Option<PathBuf>) -> PathBuf {
+        self.resolve(arg, &self.tool.servers_config, "servers.json")
+    }
+
+    /// Loads the servers of the oracles, overridden by those of the selected profile.
+    ///
+    /// The profile is given as an argument or with [`ORACLE_PROFILE_ENV`], its servers being
+    /// read from `servers.<profile>.json` if it exists, or from the `profiles` map of `servers.json`.
+    pub fn load_servers_config(
+        &self,
+        arg: Option<PathBuf>,
+        profile: Option<String>,
+    ) -> Result<HashMap<String, ServerConfig>> {
+        let path = self.servers_config(arg);
+        read_servers_config(&path, oracle_profile(profile))
+    }
+
+    /// Loads the oracle lock, with the servers of the oracles.
+    pub fn load_configuration(
+        &self,
+        oracle_lock: Option<PathBuf>,
+        servers_config: Option<PathBuf>,
+        profile: Option<String>,
+    ) -> Result<Configuration> {
+        let lock_path = self.oracle_lock(oracle_lock);
+        let lock_file = File::open(&lock_path)
+            .with_context(|| format!("failed to open oracle lock: {}", lock_path.display()))?;
+        let mut configuration: Configuration =
+            serde_json::from_reader(BufReader::new(lock_file))
+                .with_context(|| format!("failed to parse oracle lock: {}", lock_path.display()))?;
+        configuration.servers_config = self.load_servers_config(servers_config, profile)?;
+        Ok(configuration)
+    }
+
+    pub fn cairo_schema(&self) -> PathBuf {
+        self.resolve(None, &self.tool.cairo_schema, "cairo_schema.yaml")
+    }
+
+    pub fn oracle_mocks(&self, arg: Option<PathBuf>) -> Option<PathBuf> {
+        self.resolve_optional(arg, &self.tool.oracle_mocks, "oracle_mocks.json")
+    }
+
+    pub fn test_cases(&self, arg: Option<PathBuf>) -> Option<PathBuf> {
+        self.resolve_optional(arg, &self.tool.test_cases, "test_cases.yaml")
+    }
+
+    pub fn snapshots(&self) -> PathBuf {
+        self.resolve(None, &self.tool.snapshots, "snapshots")
+    }
+
+    fn hook_config(&self, stage: HookStage) -> HookConfig {
+        match stage {
+            HookStage::Preprocess => self.tool.preprocess.clone(),
+            HookStage::Postprocess => self.tool.postprocess.clone(),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Creates the hook of a stage.
+    pub fn hook(&self, stage: HookStage) -> Result<Hook> {
+        Hook::new(stage, self.hook_config(stage), &self.root)
+    }
+
+    /// The configuration used when no setting is overridden on the command line.
+    pub fn resolved(&self) -> ResolvedConfig {
+        ResolvedConfig {
+            definitions: self.definitions(None),
+            shared_proto: self.shared_proto(None),
+            cairo_output: self.cairo_output(None),
+            oracle_module: self.oracle_module(None),
+            oracle_lock: self.oracle_lock(None),
+            servers_config: self.servers_config(None),
+            cairo_schema: self.cairo_schema(),
+            oracle_mocks: self.oracle_mocks(None),
+            test_cases: self.test_cases(None),
+            snapshots: self.snapshots(),
+            preprocess: self
+                .hook_config(HookStage::Preprocess)
+                .resolved(HookStage::Preprocess),
+            postprocess: self
+                .hook_config(HookStage::Postprocess)
+                .resolved(HookStage::Postprocess),
+        }
+    }
+
+    /// Describes the files referenced by the configuration that do not exist.
+    ///
+    /// The servers config is checked by loading it, as the file of a profile may replace it.
+    pub fn check_files(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |key: &str, path: &Path, hint: &str| {
+            if !path.exists() {
+                problems.push(format!("{key}: {} does not exist{hint}", path.display()));
+            }
+        };
+
+        let resolved = self.resolved();
+        if let Some(definitions) = &resolved.definitions {
+            check("definitions", definitions, "");
+            check(
+                "oracle_lock",
+                &resolved.oracle_lock,
+                ", run `scarb agent-generate` to create it",
+            );
+        }
+        check("cairo_schema", &resolved.cairo_schema, "");
+        if let Some(shared_proto) = &resolved.shared_proto {
+            check("shared_proto", shared_proto, "");
+        }
+        // Unlike their defaults, explicitly set files are expected to exist
+        if let Some(oracle_mocks) = &self.tool.oracle_mocks {
+            check("oracle_mocks", &self.path(oracle_mocks), "");
+        }
+        if let Some(test_cases) = &self.tool.test_cases {
+            check("test_cases", &self.path(test_cases), "");
+        }
+        for stage in [HookStage::Preprocess, HookStage::Postprocess] {
+            if let Some(plugin) = &self.hook_config(stage).plugin {
+                check(&format!("{stage}.plugin"), &self.path(plugin), "");
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Writes a servers config file in the directory.
+    fn write_servers(dir: &Path, file: &str, servers: Value) {
+        fs::write(dir.join(file), servers.to_string()).unwrap();
+    }
+
+    /// Returns the server URL of each oracle, sorted by oracle.
+    fn urls(servers: HashMap<String, ServerConfig>) -> Vec<(String, String)> {
+        let mut urls = servers
+            .into_iter()
+            .map(|(name, server)| (name, server.server_url))
+            .collect::<Vec<_>>();
+        urls.sort();
+        urls
+    }
+
+    fn pair(name: &str, url: &str) -> (String, String) {
+        (name.to_string(), url.to_string())
+    }
+
+    fn config(root: &Path, tool: Value) -> AgentConfig {
+        AgentConfig {
+            root: root.to_path_buf(),
+            tool: serde_json::from_value(tool).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let config = config(
+            Path::new("/agent"),
+            json!({
+                "definitions": "proto/oracle.proto",
+                "definition": "proto/oracle.proto",
+                "preprocess": { "url": "http://localhost:3000/preprocess", "uri": "typo" },
+                "postprocess": { "command": ["./post.sh"], "timout": 5 },
+            }),
+        );
+        assert_eq!(
+            config.unknown_keys(),
+            vec!["definition", "preprocess.uri", "postprocess.timout"]
+        );
+        assert_eq!(
+            config.tool.preprocess.unwrap().url.as_deref(),
+            Some("http://localhost:3000/preprocess")
+        );
+    }
+
+    #[test]
+    fn test_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let config = config(
+            root,
+            json!({
+                "cairo_output": "src/oracles",
+                "oracle_mocks": "fixtures/mocks.json",
+            }),
+        );
+
+        // Arguments take precedence over the section, itself over the defaults
+        assert_eq!(config.cairo_output(None), root.join("src/oracles"));
+        assert_eq!(config.cairo_output(Some("out".into())), root.join("out"));
+        assert_eq!(config.oracle_lock(None), root.join("Oracle.lock"));
+        assert_eq!(config.servers_config(None), root.join("servers.json"));
+        assert_eq!(config.oracle_module(None), "lib.cairo");
+        assert_eq!(config.definitions(None), None);
+
+        // Optional files are used when set, their defaults only when they exist
+        assert_eq!(
+            config.oracle_mocks(None),
+            Some(root.join("fixtures/mocks.json"))
+        );
+        assert_eq!(config.test_cases(None), None);
+        fs::write(root.join("test_cases.yaml"), "cases: []").unwrap();
+        assert_eq!(config.test_cases(None), Some(root.join("test_cases.yaml")));
+    }
+
+    #[test]
+    fn test_check_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let config = config(
+            root,
+            json!({
+                "definitions": "proto/oracle.proto",
+                "oracle_mocks": "mocks.json",
+                "postprocess": { "plugin": "libpost.so" },
+            }),
+        );
+        fs::create_dir(root.join("proto")).unwrap();
+        fs::write(root.join("proto/oracle.proto"), "").unwrap();
+
+        let path = |file: &str| root.join(file).display().to_string();
+        assert_eq!(
+            config.check_files(),
+            vec![
+                format!(
+                    "oracle_lock: {} does not exist, run `scarb agent-generate` to create it",
+                    path("Oracle.lock")
+                ),
+                format!("cairo_schema: {} does not exist", path("cairo_schema.yaml")),
+                format!("oracle_mocks: {} does not exist", path("mocks.json")),
+                format!("postprocess.plugin: {} does not exist", path("libpost.so")),
+            ]
+        );
+
+        for file in [
+            "Oracle.lock",
+            "cairo_schema.yaml",
+            "mocks.json",
+            "libpost.so",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        assert!(config.check_files().is_empty());
+    }
+
+    #[test]
+    fn test_servers_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        write_servers(
+            dir.path(),
+            "servers.json",
+            json!({
+                "price": { "server_url": "http://localhost:3000" },
+                "volume": { "server_url": "http://localhost:3001" },
+                "profiles": {
+                    "staging": { "price": { "server_url": "https://staging" } },
+                    "prod": { "price": { "server_url": "https://prod-map" } },
+                },
+            }),
+        );
+        write_servers(
+            dir.path(),
+            "servers.prod.json",
+            json!({ "price": { "server_url": "https://prod-file" } }),
+        );
+        let path = dir.path().join("servers.json");
+
+        assert_eq!(
+            urls(read_servers_config(&path, None).unwrap()),
+            vec![
+                pair("price", "http://localhost:3000"),
+                pair("volume", "http://localhost:3001")
+            ]
+        );
+        assert_eq!(
+            urls(read_servers_config(&path, Some("staging".to_string())).unwrap()),
+            vec![
+                pair("price", "https://staging"),
+                pair("volume", "http://localhost:3001")
+            ]
+        );
+        // The profile file takes precedence over the `profiles` entry
+        assert_eq!(
+            urls(read_servers_config(&path, Some("prod".to_string())).unwrap()),
+            vec![
+                pair("price", "https://prod-file"),
+                pair("volume", "http://localhost:3001")
+            ]
+        );
+
+        let error = read_servers_config(&path, Some("dev".to_string())).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("unknown oracle profile `dev`: "));
+    }
+
+    #[test]
+    fn test_servers_profile_file_only() {
+        let dir = tempfile::tempdir().unwrap();
+        write_servers(
+            dir.path(),
+            "servers.local.json",
+            json!({ "price": { "server_url": "http://localhost:3000" } }),
+        );
+        let path = dir.path().join("servers.json");
+
+        // The default file may be left out when the profile file has every server
+        assert_eq!(
+            urls(read_servers_config(&path, Some("local".to_string())).unwrap()),
+            vec![pair("price", "http://localhost:3000")]
+        );
+        assert!(read_servers_config(&path, None).is_err());
+
+        write_servers(
+            dir.path(),
+            "servers.local.json",
+            json!({ "profiles": { "other": {} } }),
+        );
+        let error = read_servers_config(&path, Some("local".to_string())).unwrap_err();
+        assert!(error.to_string().contains("is only read from"));
+    }
+
+    #[test]
+    fn test_oracle_profile_env() {
+        // The only test reading the variable, not to race with the others
+        std::env::set_var(ORACLE_PROFILE_ENV, "staging");
+        assert_eq!(oracle_profile(None).as_deref(), Some("staging"));
+        assert_eq!(
+            oracle_profile(Some("prod".to_string())).as_deref(),
+            Some("prod")
+        );
+        std::env::set_var(ORACLE_PROFILE_ENV, "");
+        assert_eq!(oracle_profile(None), None);
+        std::env::remove_var(ORACLE_PROFILE_ENV);
+        assert_eq!(oracle_profile(None), None);
+    }
+}
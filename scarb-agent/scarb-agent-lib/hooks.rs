@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use cairo_oracle_hint_processor::report::ExecutionReport;
+use cairo_oracle_hint_processor::rpc_hint_processor::RUN_ID_HEADER;
+use cairo_vm::Felt252;
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Time a hook is given to answer, unless set with `timeout` in its section.
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Version of the plugin interface, returned by the `agent_abi_version` function of plugins.
+///
+/// Bumped whenever the signature of the functions of plugins changes: version 2 added the
+/// run ID argument of the hook functions.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+/// Environment variable holding the run ID for command hooks.
+pub const RUN_ID_ENV: &str = "AGENT_RUN_ID";
+
+/// Returns a new random run ID.
+pub fn new_run_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Request sent to the postprocess hook once the function has run.
+#[derive(Debug, Serialize)]
+pub struct PostprocessRequest<'a> {
+    pub run_id: &'a str,
+    /// The run ID again, under the name read by servers written before it was sent.
+    pub request_id: &'a str,
+    /// Output serialized as a JSON string, `Null` if the run panicked.
+    pub result: String,
+    /// Arguments of the function, after preprocessing.
+    pub inputs: &'a Value,
+    /// Output decoded through the output schema.
+    pub output: Option<Value>,
+    /// Panic data, if the run panicked.
+    pub panic_data: Option<Vec<String>>,
+    /// Execution resources of the run.
+    pub resources: Option<&'a ExecutionReport>,
+}
+
+impl<'a> PostprocessRequest<'a> {
+    /// Request of a run returning the given serialized output.
+    pub fn output(
+        run_id: &'a str,
+        inputs: &'a Value,
+        output: Option<String>,
+        resources: &'a ExecutionReport,
+    ) -> Self {
+        Self {
+            run_id,
+            request_id: run_id,
+            output: output.as_deref().and_then(|o| serde_json::from_str(o).ok()),
+            result: output.unwrap_or_else(|| "Null".to_string()),
+            inputs,
+            panic_data: None,
+            resources: Some(resources),
+        }
+    }
+
+    /// Request of a run that panicked.
+    pub fn panic(
+        run_id: &'a str,
+        inputs: &'a Value,
+        panic_data: &[Felt252],
+        resources: Option<&'a ExecutionReport>,
+    ) -> Self {
+        Self {
+            run_id,
+            request_id: run_id,
+            result: "Null".to_string(),
+            inputs,
+            output: None,
+            panic_data: Some(panic_data.iter().map(Felt252::to_string).collect()),
+            resources,
+        }
+    }
+}
+
+/// When a hook runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// Before the run, turning the request into the arguments of the function.
+    Preprocess,
+    /// After the run, processing its output.
+    Postprocess,
+}
+
+impl HookStage {
+    /// Key of the section of the hook in `[tool.agent]`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            HookStage::Preprocess => "preprocess",
+            HookStage::Postprocess => "postprocess",
+        }
+    }
+
+    /// Symbol of the hook function of a plugin.
+    fn plugin_symbol(&self) -> &'static [u8] {
+        match self {
+            HookStage::Preprocess => b"agent_preprocess",
+            HookStage::Postprocess => b"agent_postprocess",
+        }
+    }
+
+    /// URL of the hook when it has no section, as used before hooks were configurable.
+    fn default_url(&self) -> String {
+        let (var, path) = match self {
+            HookStage::Preprocess => ("PREPROCESS_URL", "preprocess"),
+            HookStage::Postprocess => ("POSTPROCESS_URL", "postprocess"),
+        };
+        std::env::var(var).unwrap_or_else(|_| format!("http://localhost:3000/{path}"))
+    }
+}
+
+impl fmt::Display for HookStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+/// A `[tool.agent.preprocess]` or `[tool.agent.postprocess]` section of Scarb.toml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// URL the request is POSTed to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Program and arguments reading the request on stdin and writing the response on stdout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    /// Dynamic library exporting the hook function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<PathBuf>,
+    /// Seconds the hook is given to answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Keys not understood by the agent.
+    #[serde(flatten, skip_serializing)]
+    pub unknown: BTreeMap<String, Value>,
+}
+
+impl HookConfig {
+    /// Returns the configuration with the defaults of the stage filled in.
+    pub fn resolved(&self, stage: HookStage) -> Self {
+        let mut config = self.clone();
+        if config.command.is_none() && config.plugin.is_none() {
+            config.url.get_or_insert_with(|| stage.default_url());
+        }
+        config.timeout.get_or_insert(DEFAULT_HOOK_TIMEOUT.as_secs());
+        config
+    }
+}
+
+/// How a hook is called.
+enum HookKind {
+    Http(String),
+    Command(Vec<String>),
+    Plugin(Arc<Library>),
+}
+
+/// A preprocess or postprocess hook, exchanging a JSON request and response with the agent.
+pub struct Hook {
+    stage: HookStage,
+    kind: HookKind,
+    timeout: Duration,
+    /// Directory commands are run from.
+    dir: PathBuf,
+}
+
+type PluginHook = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char;
+type PluginFree = unsafe extern "C" fn(*mut c_char);
+type PluginAbiVersion = unsafe extern "C" fn() -> u32;
+
+impl Hook {
+    /// Creates a hook, relative commands and plugins being resolved from `dir`.
+    pub fn new(stage: HookStage, config: HookConfig, dir: &Path) -> Result<Self> {
+        let kind = match (config.url, config.command, config.plugin) {
+            (url, None, None) => HookKind::Http(url.unwrap_or_else(|| stage.default_url())),
+            (None, Some(command), None) => {
+                if command.is_empty() {
+                    bail!("the command of [tool.agent.{stage}] is empty");
+                }
+                HookKind::Command(command)
+            }
+            (None, None, Some(plugin)) => {
+                let path = dir.join(plugin);
+                // Loading a library runs its initializers, trusted like the commands of the manifest
+                let library = unsafe { Library::new(&path) }
+                    .with_context(|| format!("failed to load {stage} plugin {}", path.display()))?;
+                check_abi_version(&library, stage, &path)?;
+                HookKind::Plugin(Arc::new(library))
+            }
+            _ => bail!("[tool.agent.{stage}] must set only one of url, command and plugin"),
+        };
+        Ok(Self {
+            stage,
+            kind,
+            timeout: config
+                .timeout
+                .map_or(DEFAULT_HOOK_TIMEOUT, Duration::from_secs),
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Sends a request of a run to the hook, returning its response.
+    pub fn call(&self, request: &impl Serialize, run_id: &str) -> Result<Value> {
+        let request = serde_json::to_vec(request)?;
+        let output = match &self.kind {
+            HookKind::Http(url) => self.call_http(url, request, run_id)?,
+            HookKind::Command(command) => self.call_command(command, request, run_id)?,
+            HookKind::Plugin(library) => self.call_plugin(library, request, run_id)?,
+        };
+        serde_json::from_slice(&output).map_err(|e| {
+            anyhow::anyhow!(with_output(
+                format!("{} hook returned invalid JSON: {e}", self.stage),
+                &output
+            ))
+        })
+    }
+
+    fn call_http(&self, url: &str, request: Vec<u8>, run_id: &str) -> Result<Vec<u8>> {
+        let response = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()?
+            .post(url)
+            .header(RUN_ID_HEADER, run_id)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(request)
+            .send()
+            .with_context(|| format!("failed to call {} hook {url}", self.stage))?;
+        let status = response.status();
+        let body = response.bytes()?.to_vec();
+        if !status.is_success() {
+            bail!(with_output(
+                format!("{} hook {url} answered {status}", self.stage),
+                &body
+            ));
+        }
+        Ok(body)
+    }
+
+    fn call_command(&self, command: &[String], request: Vec<u8>, run_id: &str) -> Result<Vec<u8>> {
+        let (program, args) = command.split_first().expect("commands are not empty");
+        let mut child = Command::new(program)
+            .args(args)
+            .current_dir(&self.dir)
+            .env(RUN_ID_ENV, run_id)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to start {} hook `{program}`", self.stage))?;
+
+        // Written and read in the background, so a hook filling a pipe does not block the other
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || stdin.write_all(&request));
+        let stdout = read_to_end(child.stdout.take().expect("stdout is piped"));
+        let stderr = read_to_end(child.stderr.take().expect("stderr is piped"));
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                break None;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        // The hook may exit without reading its input
+        let _ = writer.join();
+        let stdout = stdout.join().unwrap_or_default();
+        let mut output = stderr.join().unwrap_or_default();
+        match status {
+            None => {
+                output.extend(&stdout);
+                bail!(with_output(
+                    format!(
+                        "{} hook `{program}` timed out after {:?}",
+                        self.stage, self.timeout
+                    ),
+                    &output
+                ))
+            }
+            Some(status) if !status.success() => {
+                output.extend(&stdout);
+                bail!(with_output(
+                    format!("{} hook `{program}` failed with {status}", self.stage),
+                    &output
+                ))
+            }
+            Some(_) => Ok(stdout),
+        }
+    }
+
+    fn call_plugin(
+        &self,
+        library: &Arc<Library>,
+        request: Vec<u8>,
+        run_id: &str,
+    ) -> Result<Vec<u8>> {
+        let hook = *unsafe { library.get::<PluginHook>(self.stage.plugin_symbol()) }
+            .with_context(|| format!("the {} plugin exports no hook function", self.stage))?;
+        let free = *unsafe { library.get::<PluginFree>(b"agent_free") }
+            .with_context(|| format!("the {} plugin exports no agent_free", self.stage))?;
+        let request = CString::new(request)?;
+        let run_id = CString::new(run_id)?;
+
+        // Called on its own thread to be able to time out, keeping the library loaded until it returns
+        let (sender, receiver) = mpsc::channel();
+        let library = library.clone();
+        thread::spawn(move || {
+            let response = unsafe {
+                let response = hook(request.as_ptr(), run_id.as_ptr());
+                if response.is_null() {
+                    None
+                } else {
+                    let bytes = CStr::from_ptr(response).to_bytes().to_vec();
+                    free(response);
+                    Some(bytes)
+                }
+            };
+            drop(library);
+            let _ = sender.send(response);
+        });
+        match receiver.recv_timeout(self.timeout) {
+            Ok(Some(response)) => Ok(response),
+            Ok(None) => bail!("{} plugin returned no response", self.stage),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                bail!("{} plugin timed out after {:?}", self.stage, self.timeout)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => bail!("{} plugin panicked", self.stage),
+        }
+    }
+}
+
+/// Checks that a plugin was built for the interface of this version, not to call its
+/// functions with the wrong arguments.
+fn check_abi_version(library: &Library, stage: HookStage, path: &Path) -> Result<()> {
+    let abi_version = *unsafe { library.get::<PluginAbiVersion>(b"agent_abi_version") }
+        .with_context(|| {
+            format!(
+                "the {stage} plugin {} exports no agent_abi_version, rebuild it for version {PLUGIN_ABI_VERSION} of the plugin interface",
+                path.display()
+            )
+        })?;
+    let version = unsafe { abi_version() };
+    if version != PLUGIN_ABI_VERSION {
+        bail!(
+            "the {stage} plugin {} was built for version {version} of the plugin interface, rebuild it for version {PLUGIN_ABI_VERSION}",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn read_to_end(mut reader: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = reader.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
+/// Appends what a hook wrote to an error message about it.
+fn with_output(message: String, output: &[u8]) -> String {
+    let output = String::from_utf8_lossy(output);
+    if output.trim().is_empty() {
+        message
+    } else {
+        format!("{message}, output:\n{}", output.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A hook running a shell script.
+    fn shell_hook(script: &str, timeout: Option<u64>) -> Hook {
+        let config = HookConfig {
+            command: Some(vec!["sh".into(), "-c".into(), script.into()]),
+            timeout,
+            ..Default::default()
+        };
+        Hook::new(HookStage::Preprocess, config, Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn test_command_hook() {
+        let request = json!({ "n": 42 });
+        let hook = shell_hook("cat", None);
+        assert_eq!(hook.call(&request, "run").unwrap(), request);
+
+        let hook = shell_hook("printf '{\"run_id\": \"%s\"}' \"$AGENT_RUN_ID\"", None);
+        assert_eq!(
+            hook.call(&request, "0123").unwrap(),
+            json!({ "run_id": "0123" })
+        );
+    }
+
+    #[test]
+    fn test_command_hook_failure() {
+        let hook = shell_hook("echo 'invalid request' >&2; exit 3", None);
+        let error = hook.call(&json!({}), "run").unwrap_err().to_string();
+        assert!(error.starts_with("preprocess hook `sh` failed with exit status: 3"));
+        assert!(error.ends_with("output:\ninvalid request"));
+
+        let hook = shell_hook("echo 'not json'", None);
+        let error = hook.call(&json!({}), "run").unwrap_err().to_string();
+        assert!(error.starts_with("preprocess hook returned invalid JSON"));
+    }
+
+    #[test]
+    fn test_command_hook_timeout() {
+        // Replaced by `sleep`, for the pipes to be closed once it is killed
+        let hook = shell_hook("exec sleep 10", Some(1));
+        let started_at = Instant::now();
+        let error = hook.call(&json!({}), "run").unwrap_err().to_string();
+        assert_eq!(error, "preprocess hook `sh` timed out after 1s");
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_hook_kind() {
+        let config = HookConfig {
+            url: Some("http://localhost:3000/preprocess".into()),
+            command: Some(vec!["cat".into()]),
+            ..Default::default()
+        };
+        let error = Hook::new(HookStage::Preprocess, config, Path::new("."))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "[tool.agent.preprocess] must set only one of url, command and plugin"
+        );
+
+        let config = HookConfig {
+            command: Some(vec![]),
+            ..Default::default()
+        };
+        let error = Hook::new(HookStage::Postprocess, config, Path::new("."))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the command of [tool.agent.postprocess] is empty"
+        );
+
+        let config = HookConfig {
+            url: Some("http://localhost:3000/preprocess".into()),
+            ..Default::default()
+        };
+        assert!(Hook::new(HookStage::Preprocess, config, Path::new(".")).is_ok());
+    }
+}
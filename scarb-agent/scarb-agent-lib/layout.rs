@@ -0,0 +1,62 @@
+use cairo_vm::types::layout_name::LayoutName;
+
+/// The layouts accepted by `--layout`, by name.
+const LAYOUTS: [(&str, LayoutName); 11] = [
+    ("plain", LayoutName::plain),
+    ("small", LayoutName::small),
+    ("dex", LayoutName::dex),
+    ("recursive", LayoutName::recursive),
+    ("starknet", LayoutName::starknet),
+    ("starknet_with_keccak", LayoutName::starknet_with_keccak),
+    ("recursive_large_output", LayoutName::recursive_large_output),
+    (
+        "recursive_with_poseidon",
+        LayoutName::recursive_with_poseidon,
+    ),
+    ("all_solidity", LayoutName::all_solidity),
+    ("all_cairo", LayoutName::all_cairo),
+    ("dynamic", LayoutName::dynamic),
+];
+
+fn find_layout(value: &str) -> Option<LayoutName> {
+    LAYOUTS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, layout)| *layout)
+}
+
+/// Checks the value of `--layout`, to be used as its value parser.
+pub fn validate_layout(value: &str) -> Result<String, String> {
+    match find_layout(value) {
+        Some(_) => Ok(value.to_string()),
+        None => Err(format!(
+            "{value} is not a valid layout, expected one of: {}",
+            LAYOUTS.map(|(name, _)| name).join(", ")
+        )),
+    }
+}
+
+/// Returns the layout of a name checked by `validate_layout`, `all_cairo` for any other.
+pub fn str_into_layout(value: &str) -> LayoutName {
+    find_layout(value).unwrap_or(LayoutName::all_cairo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layouts() {
+        for (name, layout) in LAYOUTS {
+            assert_eq!(validate_layout(name).as_deref(), Ok(name));
+            assert_eq!(str_into_layout(name), layout);
+        }
+        assert_eq!(
+            str_into_layout("recursive_with_poseidon"),
+            LayoutName::recursive_with_poseidon
+        );
+        assert!(validate_layout("recursive_with_keccak")
+            .unwrap_err()
+            .starts_with("recursive_with_keccak is not a valid layout"));
+    }
+}
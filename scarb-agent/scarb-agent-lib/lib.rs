@@ -1 +1,4 @@
-pub mod utils;
+pub mod config;
+pub mod hooks;
+pub mod layout;
+pub mod prover;
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use cairo_io_serde::cairo_output::process_output;
+use cairo_io_serde::schema::Schema;
+use cairo_oracle_hint_processor::bundle::{
+    public_output, return_values, Bundle, Manifest, AIR_PRIVATE_INPUT_FILE, AIR_PUBLIC_INPUT_FILE,
+    SCHEMA_FILE,
+};
+use serde_json::{json, Value};
+
+/// Binary of the Stone CPU prover.
+pub const PROVER_BIN: &str = "cpu_air_prover";
+/// Binary of the Stone CPU verifier.
+pub const VERIFIER_BIN: &str = "cpu_air_verifier";
+
+const LAST_LAYER_DEGREE_BOUND: u32 = 64;
+
+/// Returns the given binary, or looks the default one up in `PATH`.
+pub fn find_binary(arg: Option<PathBuf>, default: &str) -> Result<PathBuf> {
+    match arg {
+        Some(path) => Ok(path),
+        None => which::which(default).with_context(|| {
+            format!("{default} not found in PATH, install the Stone prover or pass its path")
+        }),
+    }
+}
+
+/// Opens a bundle to prove, checking that it was run in proof mode with the return values
+/// copied to the output builtin and that its AIR public input matches the manifest.
+pub fn open_bundle(dir: &Path) -> Result<(Bundle, Manifest, Value)> {
+    let (bundle, manifest) = Bundle::open(dir)?;
+    if !manifest.proof_mode {
+        bail!(
+            "the bundle was not run in proof mode, rerun it with `scarb agent-run --proof-mode --bundle {}`",
+            dir.display()
+        );
+    }
+    if !manifest.copy_to_output {
+        bail!("the return values of the bundle were not copied to the output builtin");
+    }
+    for file in [AIR_PUBLIC_INPUT_FILE, AIR_PRIVATE_INPUT_FILE] {
+        if bundle.artifact(&manifest, file).is_none() {
+            bail!("the bundle has no {file}");
+        }
+    }
+
+    let public_input = read_json(&bundle.path(AIR_PUBLIC_INPUT_FILE))?;
+    check_public_input(&manifest, &public_input)?;
+    Ok((bundle, manifest, public_input))
+}
+
+/// Checks that a public input was produced with the layout and output builtin of the manifest.
+pub fn check_public_input(manifest: &Manifest, public_input: &Value) -> Result<()> {
+    let layout = public_input["layout"].as_str().unwrap_or_default();
+    if layout != manifest.layout {
+        bail!(
+            "layout mismatch, the bundle was run with {} but the public input is for {layout}",
+            manifest.layout
+        );
+    }
+    if public_input["memory_segments"]["output"].is_null() {
+        bail!("the public input has no output builtin segment");
+    }
+    Ok(())
+}
+
+/// Decodes the return values held in the public output, through the output schema of the bundle.
+pub fn decode_public_output(
+    bundle: &Bundle,
+    manifest: &Manifest,
+    public_input: &Value,
+) -> Result<Value> {
+    let schema: Schema = serde_json::from_value(read_json(&bundle.path(SCHEMA_FILE))?)
+        .context("invalid schema in the bundle")?;
+    let output = return_values(manifest, &public_output(public_input)?)?;
+    let decoded = process_output(output, &schema).map_err(|e| anyhow::anyhow!(e))?;
+    serde_json::from_str(&decoded).context("the decoded public output is not valid JSON")
+}
+
+/// Returns the Stone prover parameters for a trace of `n_steps` steps.
+pub fn prover_parameters(n_steps: u64) -> Result<Value> {
+    Ok(json!({
+        "field": "PrimeField0",
+        "stark": {
+            "fri": {
+                "fri_step_list": fri_step_list(n_steps)?,
+                "last_layer_degree_bound": LAST_LAYER_DEGREE_BOUND,
+                "n_queries": 18,
+                "proof_of_work_bits": 24
+            },
+            "log_n_cosets": 4
+        },
+        "use_extension_field": false
+    }))
+}
+
+/// Returns the default Stone prover configuration.
+pub fn prover_config() -> Value {
+    json!({
+        "cached_lde_config": {
+            "store_full_lde": false,
+            "use_fft_for_eval": false
+        },
+        "constraint_polynomial_task_size": 256,
+        "n_out_of_memory_merkle_layers": 1,
+        "table_prover_n_tasks_per_segment": 32
+    })
+}
+
+/// Returns FRI steps such that `log2(last_layer_degree_bound) + sum(steps) = log2(n_steps) + 4`.
+fn fri_step_list(n_steps: u64) -> Result<Vec<u32>> {
+    if !n_steps.is_power_of_two() {
+        bail!("the number of steps of a proof mode run must be a power of two, got {n_steps}");
+    }
+    let Some(total) = (n_steps.ilog2() + 4).checked_sub(LAST_LAYER_DEGREE_BOUND.ilog2()) else {
+        bail!("the run has too few steps to be proven, got {n_steps}");
+    };
+    let mut steps = vec![0];
+    steps.extend(std::iter::repeat(4).take((total / 4) as usize));
+    if total % 4 != 0 {
+        steps.push(total % 4);
+    }
+    Ok(steps)
+}
+
+/// Runs a command, failing with its output if it does not succeed.
+pub fn run_command(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .with_context(|| format!("failed to run {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} failed with {}:\n{}{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Reads a JSON file.
+pub fn read_json(path: &Path) -> Result<Value> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read file: {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse file: {}", path.display()))
+}
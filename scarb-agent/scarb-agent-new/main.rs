@@ -1,3 +1,5 @@
+use std::io::IsTerminal;
+
 use anyhow::Result;
 use camino::Utf8PathBuf;
 use clap::Parser;
@@ -6,6 +8,7 @@ use dialoguer::{theme::ColorfulTheme, Confirm};
 use new::{new_package, InitOptions, VersionControl};
 use scarb::core::{Config, PackageName};
 use scarb::ops;
+use templates::ProjectTemplate;
 
 mod fsx;
 mod new;
@@ -21,6 +24,30 @@ struct Args {
     path: Utf8PathBuf,
     #[clap(long = "name", value_parser)]
     name: Option<PackageName>,
+
+    /// Add a preprocess endpoint, prompted for if not set.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    preprocess: Option<bool>,
+
+    /// Add a postprocess endpoint, prompted for if not set.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    postprocess: Option<bool>,
+
+    /// Call smart contracts through the Agent-API, prompted for if not set.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    agent_api: Option<bool>,
+
+    /// Add an oracle, prompted for if not set.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    oracle: Option<bool>,
+
+    /// Do not prompt, leaving out what is not enabled by a flag.
+    #[clap(long, short = 'y', default_value_t = false)]
+    yes: bool,
+
+    /// Directory of Handlebars templates, or name of one in ~/.scarb-agent/templates, rendered over the project.
+    #[clap(long, value_name = "NAME|PATH")]
+    template: Option<String>,
 }
 
 pub(crate) struct ProjectConfig {
@@ -28,12 +55,23 @@ pub(crate) struct ProjectConfig {
     postprocess: bool,
     agent_api: bool,
     oracle: bool,
+    template: Option<ProjectTemplate>,
 }
 
 fn run(args: Args, config: &Config) -> Result<()> {
-    print_welcome_message();
+    // Loaded first, not to prompt for a project that cannot be created
+    let template = args
+        .template
+        .as_deref()
+        .map(ProjectTemplate::load)
+        .transpose()?;
+    // Without a terminal to prompt on, the flags are all there is
+    let interactive = !args.yes && std::io::stdin().is_terminal();
+    if interactive {
+        print_welcome_message();
+    }
 
-    let project_config = get_project_config()?;
+    let project_config = get_project_config(&args, interactive, template)?;
     let result = new_package(
         InitOptions {
             name: args.name,
@@ -57,28 +95,49 @@ fn run(args: Args, config: &Config) -> Result<()> {
     Ok(())
 }
 
-fn get_project_config() -> Result<ProjectConfig> {
+fn get_project_config(
+    args: &Args,
+    interactive: bool,
+    template: Option<ProjectTemplate>,
+) -> Result<ProjectConfig> {
     let theme = ColorfulTheme::default();
-    println!("\n{}", "Project Configuration:".yellow().bold());
-
-    let preprocess = Confirm::with_theme(&theme)
-        .with_prompt("Do you plan preprocessing in your project?")
-        .interact()?;
-    let postprocess = Confirm::with_theme(&theme)
-        .with_prompt("Do you plan postprocessing in your project?")
-        .interact()?;
-    let agent_api = Confirm::with_theme(&theme)
-        .with_prompt("Are you planning to call a smart contract through the Agent-API?")
-        .interact()?;
-    let oracle = Confirm::with_theme(&theme)
-        .with_prompt("Are you planning to create and interact with an Oracle?")
-        .interact()?;
+    let flags = [
+        args.preprocess,
+        args.postprocess,
+        args.agent_api,
+        args.oracle,
+    ];
+    if interactive && flags.iter().any(Option::is_none) {
+        println!("\n{}", "Project Configuration:".yellow().bold());
+    }
+    let confirm = |flag: Option<bool>, prompt: &str| -> Result<bool> {
+        match flag {
+            Some(value) => Ok(value),
+            None if interactive => {
+                Ok(Confirm::with_theme(&theme).with_prompt(prompt).interact()?)
+            }
+            None => Ok(false),
+        }
+    };
 
     Ok(ProjectConfig {
-        preprocess,
-        postprocess,
-        agent_api,
-        oracle,
+        preprocess: confirm(
+            args.preprocess,
+            "Do you plan preprocessing in your project?",
+        )?,
+        postprocess: confirm(
+            args.postprocess,
+            "Do you plan postprocessing in your project?",
+        )?,
+        agent_api: confirm(
+            args.agent_api,
+            "Are you planning to call a smart contract through the Agent-API?",
+        )?,
+        oracle: confirm(
+            args.oracle,
+            "Are you planning to create and interact with an Oracle?",
+        )?,
+        template,
     })
 }
 
@@ -126,16 +126,14 @@ fn mk(
 
     // Generate README.md
     let registry = get_template_engine();
-    let readme_content = registry.render(
-        "readme",
-        &json!({
-            "name": name.to_string(),
-            "preprocess": project_config.preprocess,
-            "postprocess": project_config.postprocess,
-            "agent_api": project_config.agent_api,
-            "oracle": project_config.oracle,
-        }),
-    )?;
+    let template_data = json!({
+        "name": name.to_string(),
+        "preprocess": project_config.preprocess,
+        "postprocess": project_config.postprocess,
+        "agent_api": project_config.agent_api,
+        "oracle": project_config.oracle,
+    });
+    let readme_content = registry.render("readme", &template_data)?;
 
     let readme_path = canonical_path.join("README.md");
     fsx::write(&readme_path, readme_content)?;
@@ -192,6 +190,11 @@ fn mk(
     mk_python(&canonical_path, project_config)?;
     mk_cairo(&canonical_path, &name, &config, project_config)?;
 
+    // Render the custom template last, its files replacing the generated ones
+    if let Some(template) = &project_config.template {
+        template.render(&canonical_path, &template_data)?;
+    }
+
     Ok(())
 }
 
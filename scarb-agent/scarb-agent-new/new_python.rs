@@ -96,8 +96,8 @@ async def preprocess(request: Request):
 @app.post("/postprocess")
 async def postprocess(request: Request):
     """
-    Receives JSON data as the output of a Cairo main function, processes it,
-    and returns the modified result.
+    Receives the run of a Cairo main function: its run_id, inputs, decoded output,
+    panic_data if it panicked and execution resources, and returns the modified result.
     """
     data = await request.json()
     # Insert custom postprocessing logic here
@@ -1,4 +1,18 @@
+use std::env;
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
 use handlebars::Handlebars;
+use serde_json::Value;
+
+use crate::fsx;
+
+/// Environment variable holding the directory of the named project templates.
+const TEMPLATES_DIR_ENV: &str = "SCARB_AGENT_TEMPLATES";
+
+/// Extension of the files of a template rendered with Handlebars, the others being copied.
+const TEMPLATE_EXTENSION: &str = "hbs";
 
 pub(crate) fn get_template_engine() -> handlebars::Handlebars<'static> {
     let mut registry = Handlebars::new();
@@ -37,3 +51,110 @@ pub(crate) fn get_template_engine() -> handlebars::Handlebars<'static> {
         .unwrap();
     registry
 }
+
+/// A directory of files rendered over a new project, replacing the generated files of the same path.
+pub(crate) struct ProjectTemplate {
+    root: Utf8PathBuf,
+    registry: Handlebars<'static>,
+    /// Path of each file in the template, and whether it is rendered.
+    files: Vec<(Utf8PathBuf, bool)>,
+}
+
+impl ProjectTemplate {
+    /// Loads a template from its directory, or by name from the templates directory.
+    pub(crate) fn load(template: &str) -> Result<Self> {
+        let path = Utf8Path::new(template);
+        let root = if path.is_dir() {
+            path.to_path_buf()
+        } else if path.components().count() == 1 {
+            let Some(templates_dir) = templates_dir() else {
+                bail!("template `{template}` not found, set {TEMPLATES_DIR_ENV} to the directory of the named templates");
+            };
+            let root = templates_dir.join(template);
+            if !root.is_dir() {
+                bail!("template `{template}` not found in {templates_dir}");
+            }
+            root
+        } else {
+            bail!("template directory `{template}` does not exist");
+        };
+
+        let mut files = Vec::new();
+        collect_files(&root, Utf8Path::new(""), &mut files)?;
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        for (path, rendered) in &files {
+            if *rendered {
+                registry
+                    .register_template_file(path.as_str(), root.join(path))
+                    .with_context(|| format!("invalid template `{}`", root.join(path)))?;
+            }
+        }
+        Ok(Self {
+            root,
+            registry,
+            files,
+        })
+    }
+
+    /// Writes the files of the template to the project at `path`.
+    pub(crate) fn render(&self, path: &Utf8Path, data: &Value) -> Result<()> {
+        for (file, rendered) in &self.files {
+            let (target, content) = if *rendered {
+                let content = self
+                    .registry
+                    .render(file.as_str(), data)
+                    .with_context(|| format!("failed to render `{}`", self.root.join(file)))?;
+                (path.join(file.with_extension("")), content.into_bytes())
+            } else {
+                let source = self.root.join(file);
+                let content =
+                    fs::read(&source).with_context(|| format!("failed to read `{source}`"))?;
+                (path.join(file), content)
+            };
+            fsx::create_dir_all(target.parent().unwrap())?;
+            fsx::write(target, content)?;
+        }
+        Ok(())
+    }
+}
+
+/// Directory of the named templates, `~/.scarb-agent/templates` by default.
+fn templates_dir() -> Option<Utf8PathBuf> {
+    env::var(TEMPLATES_DIR_ENV)
+        .ok()
+        .map(Utf8PathBuf::from)
+        .or_else(|| {
+            env::var("HOME").ok().map(|home| {
+                Utf8PathBuf::from(home)
+                    .join(".scarb-agent")
+                    .join("templates")
+            })
+        })
+}
+
+fn collect_files(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    files: &mut Vec<(Utf8PathBuf, bool)>,
+) -> Result<()> {
+    let mut entries = fs::read_dir(root.join(dir))
+        .with_context(|| format!("failed to read template directory `{}`", root.join(dir)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = dir.join(entry.file_name().into_string().map_err(|name| {
+            anyhow::anyhow!("file name {name:?} of the template is not UTF-8 encoded")
+        })?);
+        if path.file_name() == Some(".git") {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let rendered = path.extension() == Some(TEMPLATE_EXTENSION);
+            files.push((path, rendered));
+        }
+    }
+    Ok(())
+}
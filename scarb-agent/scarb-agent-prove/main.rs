@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use cairo_oracle_hint_processor::bundle::{
+    AIR_PRIVATE_INPUT_FILE, AIR_PUBLIC_INPUT_FILE, MEMORY_FILE, PROOF_FILE, TRACE_FILE,
+};
+use clap::Parser;
+use scarb_agent_lib::prover::{
+    decode_public_output, find_binary, open_bundle, prover_config, prover_parameters, read_json,
+    run_command, PROVER_BIN,
+};
+use serde_json::{json, Value};
+
+/// Prove a run bundle with a local Stone CPU prover.
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Bundle written by `scarb agent-run --proof-mode --bundle <dir>`.
+    bundle: PathBuf,
+
+    /// Prover binary, looked up in PATH if not set.
+    #[arg(long)]
+    prover: Option<PathBuf>,
+
+    /// Prover parameters file, derived from the number of steps of the run if not set.
+    #[arg(long)]
+    parameter_file: Option<PathBuf>,
+
+    /// Prover configuration file, the default configuration being used if not set.
+    #[arg(long)]
+    prover_config_file: Option<PathBuf>,
+
+    /// Write the proof to this file instead of proof.json in the bundle.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let result = match prove() {
+        Ok(data) => json!({
+            "status": "success",
+            "data": data
+        }),
+        Err(err) => json!({
+            "status": "error",
+            "message": format!("{err:#}")
+        }),
+    };
+
+    println!("{}", serde_json::to_string(&result)?);
+
+    std::process::exit(if result["status"] == "error" { 1 } else { 0 });
+}
+
+fn prove() -> Result<Value> {
+    let args: Args = Args::parse();
+    let prover = find_binary(args.prover, PROVER_BIN)?;
+    let (bundle, manifest, public_input) = open_bundle(&args.bundle)?;
+    let work_dir = tempfile::tempdir()?;
+
+    // The private input points to the trace and memory files by the path they were written
+    // at, which no longer holds once the bundle is moved
+    let mut private_input = read_json(&bundle.path(AIR_PRIVATE_INPUT_FILE))?;
+    private_input["trace_path"] = json!(fs::canonicalize(bundle.path(TRACE_FILE))?);
+    private_input["memory_path"] = json!(fs::canonicalize(bundle.path(MEMORY_FILE))?);
+    let private_input_file = work_dir.path().join(AIR_PRIVATE_INPUT_FILE);
+    fs::write(&private_input_file, serde_json::to_string(&private_input)?)?;
+
+    let parameter_file = match args.parameter_file {
+        Some(path) => path,
+        None => {
+            let n_steps = public_input["n_steps"]
+                .as_u64()
+                .context("the public input has no number of steps")?;
+            let path = work_dir.path().join("cpu_air_params.json");
+            fs::write(
+                &path,
+                serde_json::to_string_pretty(&prover_parameters(n_steps)?)?,
+            )?;
+            path
+        }
+    };
+    let prover_config_file = match args.prover_config_file {
+        Some(path) => path,
+        None => {
+            let path = work_dir.path().join("cpu_air_prover_config.json");
+            fs::write(&path, serde_json::to_string_pretty(&prover_config())?)?;
+            path
+        }
+    };
+
+    let proof_file = args.output.unwrap_or_else(|| bundle.path(PROOF_FILE));
+    run_command(
+        Command::new(&prover)
+            .arg("--out_file")
+            .arg(&proof_file)
+            .arg("--public_input_file")
+            .arg(bundle.path(AIR_PUBLIC_INPUT_FILE))
+            .arg("--private_input_file")
+            .arg(&private_input_file)
+            .arg("--prover_config_file")
+            .arg(&prover_config_file)
+            .arg("--parameter_file")
+            .arg(&parameter_file),
+    )?;
+
+    Ok(json!({
+        "proof": proof_file,
+        "layout": manifest.layout,
+        "output": decode_public_output(&bundle, &manifest, &public_input)?,
+    }))
+}
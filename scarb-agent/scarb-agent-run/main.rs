@@ -1,27 +1,36 @@
 use std::{
-    collections::HashMap,
-    env,
-    fs::{self, File},
-    io::BufReader,
+    env, fs,
+    io::{self, Read},
     path::PathBuf,
+    time::{Duration, SystemTime},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use cairo_io_serde::{
-    cairo_input::process_json_args,
+    cairo_input::{self, ArgsFormat},
     schema::{parse_schema_file, Schema},
     FuncArgs,
 };
 use cairo_lang_sierra::program::VersionedProgram;
-use cairo_oracle_hint_processor::{run_1, Error};
-use cairo_proto_serde::configuration::{Configuration, ServerConfig};
-use cairo_vm::types::layout_name::LayoutName;
+use cairo_oracle_hint_processor::{
+    bundle::{
+        Bundle, Manifest, AIR_PRIVATE_INPUT_FILE, AIR_PUBLIC_INPUT_FILE, CAIRO_PIE_FILE,
+        MEMORY_FILE, TRACE_FILE,
+    },
+    cairo_run::{list_functions, Instrumentation, PreparedProgram, RunLimits},
+    compiled::CASM_CACHE_DIR,
+    run_1,
+    telemetry::RunTrace,
+    Error, RunOptions,
+};
 use camino::Utf8PathBuf;
 use clap::Parser;
-use scarb_agent_lib::utils::absolute_path;
-use scarb_metadata::{MetadataCommand, ScarbCommand};
+use scarb_agent_lib::config::AgentConfig;
+use scarb_agent_lib::hooks::{new_run_id, Hook, HookStage, PostprocessRequest};
+use scarb_agent_lib::layout::{str_into_layout, validate_layout};
+use scarb_metadata::{Metadata, MetadataCommand, ScarbCommand};
 use scarb_ui::args::PackagesFilter;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 #[derive(Parser, Clone, Debug)]
@@ -63,6 +72,10 @@ struct Args {
     #[clap(long)]
     servers_config_file: Option<PathBuf>,
 
+    /// Oracle profile, read from servers.<profile>.json or the `profiles` of servers.json.
+    #[arg(long, value_name = "PROFILE")]
+    oracle_profile: Option<String>,
+
     #[clap(long)]
     oracle_lock: Option<PathBuf>,
 
@@ -72,46 +85,76 @@ struct Args {
     #[clap(long)]
     memory_file: Option<PathBuf>,
 
+    /// Program arguments, or `-` to read them from stdin.
     #[clap(long = "args", default_value = "")]
     args: Option<String>,
 
+    /// Read the program arguments from a file.
+    #[clap(long, conflicts_with = "args")]
+    args_file: Option<PathBuf>,
+
+    /// Format of the program arguments (json, yaml or csv).
+    /// Detected from the file extension or the content if not set.
+    #[clap(long)]
+    args_format: Option<ArgsFormat>,
+
     #[clap(long, default_value_t = false)]
     preprocess: bool,
 
     #[clap(long, default_value_t = false)]
     postprocess: bool,
-}
 
-fn validate_layout(value: &str) -> Result<String, String> {
-    match value {
-        "plain"
-        | "small"
-        | "dex"
-        | "starknet"
-        | "starknet_with_keccak"
-        | "recursive_large_output"
-        | "all_cairo"
-        | "all_solidity"
-        | "dynamic" => Ok(value.to_string()),
-        _ => Err(format!("{value} is not a valid layout")),
-    }
-}
+    /// ID of the run, sent to the hooks and oracle servers. Generated if not set.
+    #[clap(long)]
+    run_id: Option<String>,
 
-fn str_into_layout(value: &str) -> LayoutName {
-    match value {
-        "plain" => LayoutName::plain,
-        "small" => LayoutName::small,
-        "dex" => LayoutName::dex,
-        "recursive" => LayoutName::recursive,
-        "starknet" => LayoutName::starknet,
-        "starknet_with_keccak" => LayoutName::starknet_with_keccak,
-        "recursive_large_output" => LayoutName::recursive_large_output,
-        "recursive_with_poseidon" => LayoutName::recursive_with_poseidon,
-        "all_solidity" => LayoutName::all_solidity,
-        "all_cairo" => LayoutName::all_cairo,
-        "dynamic" => LayoutName::dynamic,
-        _ => LayoutName::all_cairo,
-    }
+    /// Path of the function to run, e.g. `::quote`.
+    #[clap(long, default_value = "::main")]
+    function: String,
+
+    /// List the functions of the package and their signatures instead of running.
+    #[clap(long, default_value_t = false)]
+    list_functions: bool,
+
+    /// Maximum number of steps the run may execute.
+    #[clap(long)]
+    max_steps: Option<usize>,
+
+    /// Maximum duration of the run in seconds, including time spent waiting on oracles.
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// Maximum number of memory cells the run may use.
+    #[clap(long)]
+    max_memory_cells: Option<usize>,
+
+    /// Print the execution resources on stderr, and write them as JSON to the given file if any.
+    #[clap(long, num_args = 0..=1)]
+    report: Option<Option<PathBuf>>,
+
+    /// Profile the steps of each Cairo function, writing them as folded stacks (for flamegraph tools) to the given file.
+    #[clap(long)]
+    profile: Option<PathBuf>,
+
+    /// Number of functions shown in the profile summary printed on stderr.
+    #[clap(long, default_value_t = 10, requires = "profile")]
+    profile_top: usize,
+
+    /// Append the spans of the run and of its oracle calls to the given file, as OTLP JSON lines.
+    #[clap(long)]
+    spans_file: Option<PathBuf>,
+
+    /// Step through the run from an interactive debugger prompt, showing every oracle call.
+    #[clap(long, default_value_t = false)]
+    debug: bool,
+
+    /// Write every proving artifact of the run, its inputs and oracle transcript to this directory,
+    /// along with a manifest of their checksums.
+    #[clap(
+        long,
+        conflicts_with_all = ["trace_file", "memory_file", "cairo_pie_output", "air_public_input", "air_private_input"]
+    )]
+    bundle: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -119,13 +162,8 @@ struct PreprocessResponse {
     args: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct CairoRunResponse {
-    result: String,
-    request_id: String,
-}
-
 fn main() -> Result<()> {
+    env_logger::init();
     let result = match run() {
         Ok(return_values) => {
             let parsed_data: Value = serde_json::from_str(&return_values)?;
@@ -137,7 +175,7 @@ fn main() -> Result<()> {
         Err(err) => {
             json!({
                 "status": "error",
-                "message": err.to_string()
+                "message": format!("{err:#}")
             })
         }
     };
@@ -151,16 +189,26 @@ fn run() -> Result<String> {
     let args: Args = Args::parse();
     let metadata = MetadataCommand::new().inherit_stderr().exec()?;
     let package = args.packages_filter.match_one(&metadata)?;
+    let config = AgentConfig::load(&package)?;
 
+    // Only the selected package is built, the others of the workspace may not be agents
     if !args.no_build {
-        ScarbCommand::new().arg("build").run()?;
+        ScarbCommand::new()
+            .arg("build")
+            .env(
+                "SCARB_PACKAGES_FILTER",
+                PackagesFilter::generate_for::<Metadata>(std::iter::once(&package)).to_env(),
+            )
+            .run()?;
     }
     let filename = format!("{}.sierra.json", package.name);
-    let scarb_target_dir = env::var("SCARB_TARGET_DIR").context("SCARB_TARGET_DIR not set")?;
-    let scarb_profile = env::var("SCARB_PROFILE").context("SCARB_PROFILE not set")?;
-    let path = Utf8PathBuf::from(scarb_target_dir)
-        .join(scarb_profile)
-        .join(filename);
+    let scarb_target_dir = env::var("SCARB_TARGET_DIR")
+        .map(Utf8PathBuf::from)
+        .ok()
+        .or_else(|| metadata.target_dir.clone())
+        .unwrap_or_else(|| metadata.runtime_manifest.join("target"));
+    let scarb_profile = env::var("SCARB_PROFILE").unwrap_or("dev".into());
+    let path = scarb_target_dir.join(scarb_profile).join(filename);
 
     if !path.try_exists()? {
         anyhow::bail!(
@@ -169,111 +217,238 @@ fn run() -> Result<String> {
         );
     }
 
-    let lock_output = absolute_path(&package, args.clone().oracle_lock, "oracle_lock", Some(PathBuf::from("Oracle.lock")))
-        .context("Lock path must be provided either as an argument (--oracle-lock src) or in the Scarb.toml file in the [tool.agent] section.")?;
-    let lock_file = File::open(lock_output)?;
-    let reader = BufReader::new(lock_file);
-    let mut service_configuration: Configuration = serde_json::from_reader(reader)?;
-
-    // Get the servers config path using absolute_path
-    let servers_config_path = absolute_path(&package, None, "servers_config", Some(PathBuf::from("servers.json")))
-        .expect("servers config path must be provided either in the Scarb.toml file in the [tool.agent] section or default to servers.json in the project root.");
-
-    // Read and parse the servers config file
-    let config_content = fs::read_to_string(&servers_config_path).map_err(|e| Error::IO(e))?;
-    let servers_config: HashMap<String, ServerConfig> = serde_json::from_str(&config_content)
-        .map_err(|e| {
-            Error::ServersConfigFileError(format!("Failed to parse servers config: {}", e))
-        })?;
-
-    // Add the servers_config to the Configuration
-    service_configuration.servers_config = servers_config;
+    let service_configuration = config.load_configuration(
+        args.oracle_lock.clone(),
+        args.servers_config_file.clone(),
+        args.oracle_profile.clone(),
+    )?;
 
     let sierra_program = serde_json::from_str::<VersionedProgram>(&fs::read_to_string(&path)?)?
         .into_v1()
         .context("Failed to load Sierra program")?
         .program;
 
-    let schema_file = get_cairo_schema(&package)?;
-    let schema = parse_schema_file(&schema_file)
-        .map_err(|e| anyhow::anyhow!("Failed to parse input schema: {}", e))?;
+    if args.list_functions {
+        let functions = list_functions(&sierra_program)?
+            .into_iter()
+            .filter(|f| f.name.starts_with(&format!("{}::", package.name)))
+            .collect::<Vec<_>>();
+        return Ok(serde_json::to_string(&functions)?);
+    }
 
-    let func_args = get_func_args(&args, &schema)?;
+    let schema = parse_schema_file(&config.cairo_schema())
+        .map_err(|e| anyhow::anyhow!("Failed to parse input schema: {}", e))?
+        .for_function(&args.function);
 
-    let (result, _) = run_1(
+    if args.debug && args.args.as_deref() == Some("-") {
+        bail!("--debug reads its commands from stdin, pass the arguments with --args-file instead");
+    }
+    let preprocess = args
+        .preprocess
+        .then(|| config.hook(HookStage::Preprocess))
+        .transpose()?;
+    let postprocess = args
+        .postprocess
+        .then(|| config.hook(HookStage::Postprocess))
+        .transpose()?;
+    let run_id = args.run_id.clone().unwrap_or_else(new_run_id);
+    let (func_args, inputs) = get_func_args(&args, &schema, preprocess.as_ref(), &run_id)?;
+
+    // A bundle holds the artifacts needed by the prover, the PIE being proven in place of
+    // the AIR inputs when not running in proof mode
+    let mut bundle = args.bundle.as_deref().map(Bundle::create).transpose()?;
+    let (trace_file, memory_file, cairo_pie_output, air_public_input, air_private_input) =
+        match &bundle {
+            Some(bundle) => (
+                Some(bundle.path(TRACE_FILE)),
+                Some(bundle.path(MEMORY_FILE)),
+                (!args.proof_mode).then(|| bundle.path(CAIRO_PIE_FILE)),
+                args.proof_mode.then(|| bundle.path(AIR_PUBLIC_INPUT_FILE)),
+                args.proof_mode.then(|| bundle.path(AIR_PRIVATE_INPUT_FILE)),
+            ),
+            None => (
+                args.trace_file.clone(),
+                args.memory_file.clone(),
+                args.cairo_pie_output.clone(),
+                args.air_public_input.clone(),
+                args.air_private_input.clone(),
+            ),
+        };
+
+    // Bundles are meant to be proven, which needs the stop pointers of the builtins
+    let finalize_builtins = bundle.is_some() || args.finalize_builtins.unwrap_or_default();
+
+    let program = PreparedProgram::new(
+        sierra_program,
+        Some(path.with_file_name(CASM_CACHE_DIR).as_std_path()),
+    )?;
+    let started_at = SystemTime::now();
+    let run_result = run_1(
         &service_configuration,
-        &str_into_layout(&args.layout),
-        &args.trace_file,
-        &args.memory_file,
-        &args.cairo_pie_output,
-        &args.air_public_input,
-        &args.air_private_input,
+        &program,
+        &args.function,
         &func_args,
         &schema,
-        &sierra_program,
-        "::main",
-        args.proof_mode,
-        args.finalize_builtins,
-        args.append_return_values,
-    )?;
-
-    process_result(Ok(result), args.postprocess)
-}
+        &RunOptions {
+            layout: str_into_layout(&args.layout),
+            trace_file,
+            memory_file,
+            cairo_pie_output: cairo_pie_output.clone(),
+            air_public_input,
+            air_private_input,
+            proof_mode: args.proof_mode,
+            finalize_builtins,
+            append_return_values: args.append_return_values.unwrap_or_default(),
+            limits: RunLimits {
+                max_steps: args.max_steps,
+                timeout: args.timeout.map(Duration::from_secs),
+                max_memory_cells: args.max_memory_cells,
+            },
+            instrumentation: Instrumentation {
+                profile: args.profile.is_some(),
+                coverage: false,
+            },
+            debug: args.debug,
+            mocks: None,
+            run_id: Some(&run_id),
+        },
+    );
+    if let Some(spans_file) = &args.spans_file {
+        let outcome = run_result.as_ref().map(|(_, _, report)| report);
+        RunTrace::new(&run_id, &args.function, started_at, outcome)
+            .export(spans_file, &package.name)
+            .with_context(|| format!("Failed to write spans: {}", spans_file.display()))?;
+    }
+    let (result, _, report) = match run_result {
+        Ok(run) => run,
+        Err(Error::RunPanic { panic_data, report }) => match &postprocess {
+            // The postprocess hook decides what to answer for panicked runs too
+            Some(hook) => {
+                let request =
+                    PostprocessRequest::panic(&run_id, &inputs, &panic_data, report.as_deref());
+                return Ok(hook.call(&request, &run_id)?.to_string());
+            }
+            None => return Err(Error::RunPanic { panic_data, report }.into()),
+        },
+        Err(err) => return Err(err.into()),
+    };
 
-fn get_func_args(args: &Args, schema: &Schema) -> Result<FuncArgs> {
-    if args.preprocess {
-        preprocess_args(args, schema)
-    } else {
-        process_args(args, schema)
+    if let Some(bundle) = &mut bundle {
+        let manifest = Manifest::new(
+            &args.function,
+            &args.layout,
+            args.proof_mode,
+            finalize_builtins,
+            args.append_return_values.unwrap_or_default(),
+            &program.sierra_program,
+        );
+        bundle.finish(manifest, &inputs, &schema, &report.oracle_transcript)?;
+        eprintln!("Run bundle written to {}", bundle.dir.display());
     }
-}
 
-fn preprocess_args(args: &Args, schema: &Schema) -> Result<FuncArgs> {
-    let preprocess_url = env::var("PREPROCESS_URL")
-        .unwrap_or_else(|_| "http://localhost:3000/preprocess".to_string());
+    if let (Some(profile_file), Some(profile)) = (&args.profile, &report.profile) {
+        fs::write(profile_file, profile.folded())
+            .with_context(|| format!("Failed to write profile: {}", profile_file.display()))?;
+        eprintln!("{}", profile.table(args.profile_top));
+    }
 
-    let body: Value = serde_json::from_str(&args.args.as_ref().context("Expect --args")?)?;
+    if let Some(report_file) = &args.report {
+        eprintln!("{report}");
+        if let Some(report_file) = report_file {
+            fs::write(report_file, serde_json::to_string_pretty(&report)?)
+                .with_context(|| format!("Failed to write report: {}", report_file.display()))?;
+        }
+    }
 
-    let preprocess_result = call_server::<PreprocessResponse>(&preprocess_url, Some(body))?.args;
-    process_json_args(&preprocess_result, schema).map_err(|e| anyhow::anyhow!(e))
+    match &postprocess {
+        Some(hook) => {
+            let request = PostprocessRequest::output(&run_id, &inputs, result, &report);
+            Ok(hook.call(&request, &run_id)?.to_string())
+        }
+        None => process_result(Ok(result)),
+    }
 }
 
-fn process_args(args: &Args, schema: &Schema) -> Result<FuncArgs> {
-    match &args.args {
-        Some(json_args) if !json_args.trim().is_empty() => {
-            process_json_args(json_args, schema).map_err(|e| anyhow::anyhow!(e))
-        }
-        _ => Ok(FuncArgs::default()),
+/// Returns the program arguments along with the JSON inputs they were parsed from.
+fn get_func_args(
+    args: &Args,
+    schema: &Schema,
+    preprocess: Option<&Hook>,
+    run_id: &str,
+) -> Result<(FuncArgs, Value)> {
+    match preprocess {
+        Some(hook) => preprocess_args(args, schema, hook, run_id),
+        None => process_args(args, schema),
     }
 }
 
-fn get_cairo_schema(package: &scarb_metadata::PackageMetadata) -> Result<PathBuf> {
-    absolute_path(package, None, "cairo_schema", Some(PathBuf::from("cairo_schema.yaml")))
-        .context("Cairo schema path must be provided either in the Scarb.toml file in the [tool.agent] section or default to cairo_schema.yaml in the project root.")
+fn preprocess_args(
+    args: &Args,
+    schema: &Schema,
+    hook: &Hook,
+    run_id: &str,
+) -> Result<(FuncArgs, Value)> {
+    let (content, format) = read_args(args)?.context("Expect --args or --args-file")?;
+    let body = cairo_input::parse_args_value(&content, format, schema)
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    let preprocess_result = serde_json::from_value::<PreprocessResponse>(hook.call(&body, run_id)?)
+        .context("Preprocess returned an invalid response")?
+        .args;
+    let inputs: Value = serde_json::from_str(&preprocess_result)
+        .context("Preprocess returned invalid JSON arguments")?;
+    let func_args =
+        cairo_input::process_value_args(&inputs, schema).map_err(|e| anyhow::anyhow!(e))?;
+    Ok((func_args, inputs))
 }
 
-fn process_result(result: Result<Option<String>, Error>, postprocess: bool) -> Result<String> {
-    match result {
-        Ok(return_values) => {
-            let cairo_output = return_values.unwrap_or_else(|| "Null".to_string());
+fn process_args(args: &Args, schema: &Schema) -> Result<(FuncArgs, Value)> {
+    match read_args(args)? {
+        Some((content, format)) => {
+            let inputs = cairo_input::parse_args_value(&content, format, schema)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let func_args =
+                cairo_input::process_value_args(&inputs, schema).map_err(|e| anyhow::anyhow!(e))?;
+            Ok((func_args, inputs))
+        }
+        None => Ok((FuncArgs::default(), Value::Null)),
+    }
+}
 
-            if postprocess {
-                let postprocess_url = env::var("POSTPROCESS_URL")
-                    .unwrap_or_else(|_| "http://localhost:3000/postprocess".to_string());
+/// Reads the raw program arguments from `--args`, stdin or `--args-file` along with their format.
+fn read_args(args: &Args) -> Result<Option<(String, ArgsFormat)>> {
+    let (content, path_format) = match (&args.args_file, &args.args) {
+        (Some(path), _) => (
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read args file: {}", path.display()))?,
+            ArgsFormat::from_path(path),
+        ),
+        (None, Some(inline)) if inline.trim() == "-" => {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read args from stdin")?;
+            (content, None)
+        }
+        (None, Some(inline)) => (inline.clone(), None),
+        (None, None) => return Ok(None),
+    };
 
-                let body = CairoRunResponse {
-                    result: cairo_output,
-                    request_id: "None".to_string(),
-                };
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
 
-                call_server::<Value>(&postprocess_url, Some(body))
-                    .map(|v| v.to_string())
-                    .map_err(|e| e.into())
-            } else {
-                Ok(cairo_output)
-            }
-        }
-        Err(Error::RunPanic(panic_data)) => {
+    let format = args
+        .args_format
+        .or(path_format)
+        .unwrap_or_else(|| ArgsFormat::detect(&content));
+    Ok(Some((content, format)))
+}
+
+fn process_result(result: Result<Option<String>, Error>) -> Result<String> {
+    match result {
+        Ok(return_values) => Ok(return_values.unwrap_or_else(|| "Null".to_string())),
+        Err(Error::RunPanic { panic_data, .. }) => {
             let panic_data_string = if panic_data.is_empty() {
                 "Null".to_string()
             } else {
@@ -292,18 +467,3 @@ fn process_result(result: Result<Option<String>, Error>, postprocess: bool) -> R
         Err(err) => Err(err.into()),
     }
 }
-
-fn call_server<T: DeserializeOwned>(
-    url: &str,
-    body: Option<impl Serialize>,
-) -> Result<T, reqwest::Error> {
-    let client = reqwest::blocking::Client::new();
-    let mut request = client.post(url);
-
-    if let Some(body) = body {
-        request = request.json(&body);
-    }
-
-    let response = request.send()?;
-    response.error_for_status()?.json()
-}
@@ -0,0 +1,401 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use cairo_io_serde::cairo_input::process_value_args;
+use cairo_io_serde::schema::{parse_schema_file, Schema};
+use cairo_io_serde::FuncArgs;
+use cairo_lang_hints_test_runner::as_short_string;
+use cairo_lang_sierra::program::VersionedProgram;
+use cairo_oracle_hint_processor::cairo_run::{
+    cairo_run_prepared, list_functions, Cairo1RunConfig, PreparedProgram, RunLimits,
+};
+use cairo_oracle_hint_processor::compiled::CASM_CACHE_DIR;
+use cairo_oracle_hint_processor::rpc_hint_processor::RUN_ID_HEADER;
+use cairo_oracle_hint_processor::telemetry::RunTrace;
+use cairo_oracle_hint_processor::Error;
+use cairo_proto_serde::configuration::Configuration;
+use cairo_vm::types::layout_name::LayoutName;
+use clap::Parser;
+use scarb_agent_lib::config::AgentConfig;
+use scarb_agent_lib::hooks::{new_run_id, Hook, HookStage, PostprocessRequest};
+use scarb_agent_lib::layout::{str_into_layout, validate_layout};
+use scarb_metadata::{Metadata, MetadataCommand, ScarbCommand};
+use scarb_ui::args::PackagesFilter;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Largest body of a `POST /run` request, larger ones being refused without reading them whole.
+const MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Serve the agent over HTTP, loading and compiling the package once for every run.
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    packages_filter: PackagesFilter,
+
+    #[arg(long, default_value_t = false)]
+    no_build: bool,
+
+    #[clap(long = "layout", default_value = "all_cairo", value_parser = validate_layout)]
+    layout: String,
+
+    #[arg(long)]
+    oracle_lock: Option<PathBuf>,
+
+    #[arg(long)]
+    servers_config_file: Option<PathBuf>,
+
+    /// Oracle profile, read from servers.<profile>.json or the `profiles` of servers.json.
+    #[arg(long, value_name = "PROFILE")]
+    oracle_profile: Option<String>,
+
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on.
+    #[arg(long, default_value_t = 8000)]
+    port: u16,
+
+    /// Number of runs served concurrently, the number of CPUs if not set.
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Send the arguments of every run to the preprocess hook first.
+    #[arg(long, default_value_t = false)]
+    preprocess: bool,
+
+    /// Send the output of every run to the postprocess hook.
+    #[arg(long, default_value_t = false)]
+    postprocess: bool,
+
+    /// Maximum number of steps each run may execute.
+    #[arg(long)]
+    max_steps: Option<usize>,
+
+    /// Maximum duration of each run in seconds, including time spent waiting on oracles.
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Maximum number of memory cells each run may use.
+    #[arg(long)]
+    max_memory_cells: Option<usize>,
+
+    /// Append the spans of every run and of its oracle calls to the given file, as OTLP JSON lines.
+    #[arg(long)]
+    spans_file: Option<PathBuf>,
+}
+
+/// Body of a `POST /run` request.
+#[derive(Deserialize, Debug)]
+struct RunRequest {
+    /// Arguments of the function, as accepted by `scarb agent-run --args`.
+    #[serde(default)]
+    args: Value,
+    #[serde(default = "default_function")]
+    function: String,
+}
+
+fn default_function() -> String {
+    "::main".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PreprocessResponse {
+    args: String,
+}
+
+/// The package loaded once, shared by every worker.
+struct Agent {
+    package: String,
+    program: PreparedProgram,
+    functions: Vec<String>,
+    configuration: Configuration,
+    schema: Schema,
+    layout: LayoutName,
+    limits: RunLimits,
+    preprocess: Option<Hook>,
+    postprocess: Option<Hook>,
+    spans_file: Option<PathBuf>,
+    workers: usize,
+}
+
+impl Agent {
+    fn health(&self) -> Value {
+        json!({
+            "status": "success",
+            "data": {
+                "package": self.package,
+                "functions": self.functions,
+                "workers": self.workers,
+            }
+        })
+    }
+
+    /// Runs a function on the arguments of a request, returning its decoded output.
+    fn run(&self, request: RunRequest, run_id: &str) -> Result<Value> {
+        let schema = self.schema.for_function(&request.function);
+        let args = match &self.preprocess {
+            Some(hook) => {
+                let preprocessed =
+                    serde_json::from_value::<PreprocessResponse>(hook.call(&request.args, run_id)?)
+                        .context("Preprocess returned an invalid response")?
+                        .args;
+                serde_json::from_str(&preprocessed)
+                    .context("Preprocess returned invalid JSON arguments")?
+            }
+            None => request.args,
+        };
+        let func_args = if args.is_null() {
+            FuncArgs::default()
+        } else {
+            process_value_args(&args, &schema).map_err(|e| anyhow::anyhow!(e))?
+        };
+
+        let started_at = SystemTime::now();
+        let result = cairo_run_prepared(
+            &self.program,
+            Cairo1RunConfig {
+                args: &func_args.0,
+                serialize_output: true,
+                layout: self.layout,
+                limits: self.limits,
+                run_id: Some(run_id),
+                ..Default::default()
+            },
+            &self.configuration,
+            &request.function,
+            &schema,
+        );
+        if let Some(spans_file) = &self.spans_file {
+            let outcome = result.as_ref().map(|(_, _, _, report)| report);
+            // A run is not failed for its spans
+            if let Err(err) = RunTrace::new(run_id, &request.function, started_at, outcome)
+                .export(spans_file, &self.package)
+            {
+                eprintln!("failed to write the spans of run {run_id}: {err}");
+            }
+        }
+        let (output, report) = match result {
+            Ok((_, _, output, report)) => (output, report),
+            Err(Error::RunPanic { panic_data, report }) => {
+                // The postprocess hook decides what to answer for panicked runs too
+                if let Some(hook) = &self.postprocess {
+                    let request =
+                        PostprocessRequest::panic(run_id, &args, &panic_data, report.as_deref());
+                    return hook.call(&request, run_id);
+                }
+                let panic_data = panic_data
+                    .iter()
+                    .map(|felt| match as_short_string(felt) {
+                        Some(message) => format!("{felt} ('{message}')"),
+                        None => felt.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                anyhow::bail!("Run panicked with: [{}]", panic_data.join(", "));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        match &self.postprocess {
+            Some(hook) => hook.call(
+                &PostprocessRequest::output(run_id, &args, output, &report),
+                run_id,
+            ),
+            None => serde_json::from_str(output.as_deref().unwrap_or("null"))
+                .context("the output of the run is not valid JSON"),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args: Args = Args::parse();
+    let metadata = MetadataCommand::new().inherit_stderr().exec()?;
+    let package = args.packages_filter.match_one(&metadata)?;
+
+    if !args.no_build {
+        ScarbCommand::new()
+            .arg("build")
+            .env(
+                "SCARB_PACKAGES_FILTER",
+                PackagesFilter::generate_for::<Metadata>(std::iter::once(&package)).to_env(),
+            )
+            .run()?;
+    }
+    let profile = env::var("SCARB_PROFILE").unwrap_or("dev".into());
+    let default_target_dir = metadata.runtime_manifest.join("target");
+    let sierra_path = metadata
+        .target_dir
+        .clone()
+        .unwrap_or(default_target_dir)
+        .join(profile)
+        .join(format!("{}.sierra.json", package.name));
+    let sierra_program = serde_json::from_str::<VersionedProgram>(
+        &fs::read_to_string(&sierra_path)
+            .with_context(|| format!("failed to read file: {sierra_path}"))?,
+    )?
+    .into_v1()
+    .context("failed to load Sierra program")?
+    .program;
+
+    let config = AgentConfig::load(&package)?;
+    let configuration = config.load_configuration(
+        args.oracle_lock.clone(),
+        args.servers_config_file.clone(),
+        args.oracle_profile.clone(),
+    )?;
+
+    let schema = parse_schema_file(&config.cairo_schema())
+        .map_err(|e| anyhow::anyhow!("failed to parse cairo schema: {e}"))?;
+
+    let functions = list_functions(&sierra_program)?
+        .into_iter()
+        .map(|f| f.name)
+        .filter(|name| name.starts_with(&format!("{}::", package.name)))
+        .collect();
+    let workers = args
+        .workers
+        .unwrap_or_else(|| thread::available_parallelism().map_or(4, |n| n.get()))
+        .max(1);
+    let agent = Arc::new(Agent {
+        package: package.name.clone(),
+        program: PreparedProgram::new(
+            sierra_program,
+            Some(sierra_path.with_file_name(CASM_CACHE_DIR).as_std_path()),
+        )?,
+        functions,
+        configuration,
+        schema,
+        layout: str_into_layout(&args.layout),
+        limits: RunLimits {
+            max_steps: args.max_steps,
+            timeout: args.timeout.map(Duration::from_secs),
+            max_memory_cells: args.max_memory_cells,
+        },
+        preprocess: args
+            .preprocess
+            .then(|| config.hook(HookStage::Preprocess))
+            .transpose()?,
+        postprocess: args
+            .postprocess
+            .then(|| config.hook(HookStage::Postprocess))
+            .transpose()?,
+        spans_file: args.spans_file.clone(),
+        workers,
+    });
+
+    let server =
+        Arc::new(Server::http((args.host.as_str(), args.port)).map_err(|e| {
+            anyhow::anyhow!("failed to listen on {}:{}: {e}", args.host, args.port)
+        })?);
+    eprintln!(
+        "Serving {} on http://{}:{} with {workers} workers",
+        package.name, args.host, args.port
+    );
+
+    let handles = (0..workers)
+        .map(|i| {
+            let server = server.clone();
+            let agent = agent.clone();
+            thread::Builder::new()
+                .name(format!("worker-{i}"))
+                .spawn(move || {
+                    for request in server.incoming_requests() {
+                        handle(&agent, request);
+                    }
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| anyhow::anyhow!("a worker panicked"))?;
+    }
+    Ok(())
+}
+
+fn handle(agent: &Agent, mut request: Request) {
+    let started = Instant::now();
+    // Runs keep the ID given by the client, to be correlated with its own requests
+    let run_id = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(RUN_ID_HEADER))
+        .map(|header| header.value.to_string())
+        .unwrap_or_else(new_run_id);
+    let method = request.method().clone();
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let (status, body) = match (&method, path.as_str()) {
+        (Method::Get, "/health") => (200, agent.health()),
+        (Method::Post, "/run") => {
+            let mut content = String::new();
+            // One byte more than allowed is read, to tell a body of the maximum size from a larger one
+            let read = request
+                .as_reader()
+                .take(MAX_BODY_SIZE + 1)
+                .read_to_string(&mut content)
+                .context("failed to read the request body");
+            match read {
+                Ok(size) if size as u64 > MAX_BODY_SIZE => (
+                    413,
+                    error(&anyhow::anyhow!(
+                        "the request body is larger than {MAX_BODY_SIZE} bytes"
+                    )),
+                ),
+                Ok(_) => match serde_json::from_str::<RunRequest>(&content)
+                    .context("invalid run request")
+                {
+                    Ok(run_request) => match agent.run(run_request, &run_id) {
+                        Ok(data) => (200, json!({ "status": "success", "data": data })),
+                        Err(err) => (500, error(&err)),
+                    },
+                    Err(err) => (400, error(&err)),
+                },
+                Err(err) => (400, error(&err)),
+            }
+        }
+        _ => (
+            404,
+            json!({ "status": "error", "message": format!("no route for {method} {path}") }),
+        ),
+    };
+    eprintln!(
+        "{method} {path} {status} in {:?} (run {run_id})",
+        started.elapsed()
+    );
+
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .expect("valid header"),
+        )
+        .with_header(
+            Header::from_bytes(RUN_ID_HEADER.as_bytes(), run_id.as_bytes()).expect("valid header"),
+        );
+    if let Err(err) = request.respond(response) {
+        eprintln!("failed to respond to {method} {path}: {err}");
+    }
+}
+
+fn error(err: &anyhow::Error) -> Value {
+    json!({
+        "status": "error",
+        "message": format!("{err:#}")
+    })
+}
@@ -1,17 +1,23 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
 use std::path::PathBuf;
-use std::{env, fs};
+use std::process::Command;
+use std::time::Duration;
+use std::{env, fs, io};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
+use cairo_io_serde::schema::parse_schema_file;
+use cairo_lang_hints_test_runner::cases::{CasesRunner, TestCases};
+use cairo_lang_hints_test_runner::report::{to_json, to_junit, TestSuite};
+use cairo_lang_hints_test_runner::snapshot::Snapshots;
 use cairo_lang_hints_test_runner::{CompiledTestRunner, TestRunConfig};
+use cairo_lang_sierra::program::VersionedProgram;
 use cairo_lang_test_plugin::TestCompilation;
-use cairo_proto_serde::configuration::{Configuration, ServerConfig};
-use cairo_vm::types::layout_name::LayoutName;
-use clap::Parser;
-use scarb_agent_lib::utils::absolute_path;
-use scarb_metadata::{Metadata, MetadataCommand, PackageMetadata, ScarbCommand, TargetMetadata};
+use cairo_oracle_hint_processor::cairo_run::RunLimits;
+use cairo_oracle_hint_processor::compiled::CASM_CACHE_DIR;
+use cairo_oracle_hint_processor::mock::OracleMocks;
+use clap::{Parser, ValueEnum};
+use scarb_agent_lib::config::AgentConfig;
+use scarb_agent_lib::layout::{str_into_layout, validate_layout};
+use scarb_metadata::{Metadata, MetadataCommand, PackageMetadata, TargetMetadata};
 use scarb_ui::args::PackagesFilter;
 
 /// Execute all unit tests of a local package.
@@ -37,57 +43,89 @@ struct Args {
     #[arg(long)]
     servers_config_file: Option<PathBuf>,
 
+    /// Oracle profile, read from servers.<profile>.json or the `profiles` of servers.json.
+    #[arg(long, value_name = "PROFILE")]
+    oracle_profile: Option<String>,
+
     #[arg(long)]
     oracle_lock: Option<PathBuf>,
 
     #[clap(long = "layout", default_value = "all_cairo", value_parser=validate_layout)]
     layout: String,
-}
 
-fn validate_layout(value: &str) -> Result<String, String> {
-    match value {
-        "plain"
-        | "small"
-        | "dex"
-        | "starknet"
-        | "starknet_with_keccak"
-        | "recursive_large_output"
-        | "all_cairo"
-        | "all_solidity"
-        | "dynamic" => Ok(value.to_string()),
-        _ => Err(format!("{value} is not a valid layout")),
-    }
+    /// Maximum number of steps each test may execute.
+    #[arg(long)]
+    max_steps: Option<usize>,
+
+    /// Maximum duration of each test in seconds, including time spent waiting on oracles.
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Maximum number of memory cells each test may use.
+    #[arg(long)]
+    max_memory_cells: Option<usize>,
+
+    /// Profile the steps of each Cairo function per test, writing folded stacks and a summary table to this directory.
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Collect line coverage of the package sources, writing an LCOV file per test target to this directory.
+    #[arg(long)]
+    coverage: Option<PathBuf>,
+
+    /// Serve the oracle calls of the tests from this mocks file instead of the oracle servers.
+    /// Defaults to oracle_mocks.json in the package root if it exists.
+    #[arg(long)]
+    oracle_mocks: Option<PathBuf>,
+
+    /// Run the input-driven test cases of the package entry point listed in this JSON or YAML file.
+    /// Defaults to test_cases.yaml in the package root if it exists.
+    #[arg(long)]
+    cases: Option<PathBuf>,
+
+    /// Compare the decoded output and oracle calls of each test case to its stored snapshot.
+    /// Snapshots are stored in the `snapshots` directory of the package unless configured otherwise.
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Replace the snapshots that differ instead of failing the test cases.
+    #[arg(long)]
+    update_snapshots: bool,
+
+    /// Format of the test results.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Write the test results to this file instead of stdout, with `--format json` or `--format junit`.
+    #[arg(long)]
+    output: Option<PathBuf>,
 }
 
-fn str_into_layout(value: &str) -> LayoutName {
-    match value {
-        "plain" => LayoutName::plain,
-        "small" => LayoutName::small,
-        "dex" => LayoutName::dex,
-        "recursive" => LayoutName::recursive,
-        "starknet" => LayoutName::starknet,
-        "starknet_with_keccak" => LayoutName::starknet_with_keccak,
-        "recursive_large_output" => LayoutName::recursive_large_output,
-        "recursive_with_poseidon" => LayoutName::recursive_with_poseidon,
-        "all_solidity" => LayoutName::all_solidity,
-        "all_cairo" => LayoutName::all_cairo,
-        "dynamic" => LayoutName::dynamic,
-        _ => LayoutName::all_cairo,
-    }
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    /// Human readable progress and results.
+    Text,
+    /// A JSON array of the test targets and their results.
+    Json,
+    /// JUnit XML, as read by CI dashboards.
+    Junit,
 }
 
 fn main() -> Result<()> {
+    env_logger::init();
     let args: Args = Args::parse();
 
     let metadata = MetadataCommand::new().inherit_stderr().exec()?;
 
+    // In the other formats, stdout only holds the test results
+    let quiet = args.format != ReportFormat::Text;
+
     let matched = args.packages_filter.match_many(&metadata)?;
-    let filter = PackagesFilter::generate_for::<Metadata>(matched.iter());
-    ScarbCommand::new()
-        .arg("build")
-        .arg("--test")
-        .env("SCARB_PACKAGES_FILTER", filter.to_env())
-        .run()?;
+    scarb_build(
+        &["--test"],
+        PackagesFilter::generate_for::<Metadata>(matched.iter()),
+        quiet,
+    )?;
 
     let profile = env::var("SCARB_PROFILE").unwrap_or("dev".into());
     let default_target_dir = metadata.runtime_manifest.join("target");
@@ -97,36 +135,51 @@ fn main() -> Result<()> {
         .unwrap_or(default_target_dir)
         .join(profile);
 
+    let mut suites = Vec::new();
     for package in matched {
-        println!("testing {} ...", package.name);
-
-        let lock_output = absolute_path(&package, args.oracle_lock.clone(), "oracle_lock", Some(PathBuf::from("Oracle.lock")))
-            .expect("lock path must be provided either as an argument (--oracle-lock src) or in the Scarb.toml file in the [tool.agent] section.");
-        let lock_file = File::open(lock_output)?;
-        let reader = BufReader::new(lock_file);
-        let mut service_config: Configuration = serde_json::from_reader(reader)?;
-
-        // Get the servers config path
-        let servers_config_path = absolute_path(&package, None, "servers_config", Some(PathBuf::from("servers.json")))
-            .expect("servers config path must be provided either in the Scarb.toml file in the [tool.agent] section or default to servers.json in the project root.");
-
-        // Read and parse the servers config file
-        let config_content = fs::read_to_string(&servers_config_path).with_context(|| {
-            format!(
-                "failed to read servers config file: {}",
-                servers_config_path.display()
-            )
-        })?;
-        let servers_config: HashMap<String, ServerConfig> = serde_json::from_str(&config_content)
-            .with_context(|| {
-            format!(
-                "failed to parse servers config file: {}",
-                servers_config_path.display()
-            )
-        })?;
+        if !quiet {
+            println!("testing {} ...", package.name);
+        }
+
+        let config = AgentConfig::load(&package)?;
+        let service_config = config.load_configuration(
+            args.oracle_lock.clone(),
+            args.servers_config_file.clone(),
+            args.oracle_profile.clone(),
+        )?;
+
+        let mocks = match config.oracle_mocks(args.oracle_mocks.clone()) {
+            Some(mocks_path) => {
+                let content = fs::read_to_string(&mocks_path).with_context(|| {
+                    format!("failed to read oracle mocks file: {}", mocks_path.display())
+                })?;
+                let mocks: OracleMocks = serde_json::from_str(&content).with_context(|| {
+                    format!(
+                        "failed to parse oracle mocks file: {}",
+                        mocks_path.display()
+                    )
+                })?;
+                Some(mocks)
+            }
+            None => None,
+        };
 
-        // Add the server_config to the Configuration
-        service_config.servers_config = servers_config;
+        let test_config = |coverage: Option<PathBuf>| TestRunConfig {
+            filter: args.filter.clone(),
+            include_ignored: args.include_ignored,
+            ignored: args.ignored,
+            limits: RunLimits {
+                max_steps: args.max_steps,
+                timeout: args.timeout.map(Duration::from_secs),
+                max_memory_cells: args.max_memory_cells,
+            },
+            profile_dir: args.profile.clone(),
+            coverage,
+            source_root: Some(package.root.clone().into_std_path_buf()),
+            mocks: mocks.clone(),
+            quiet,
+            casm_cache_dir: Some(target_dir.join(CASM_CACHE_DIR).into_std_path_buf()),
+        };
 
         for target in find_testable_targets(&package) {
             let file_path = target_dir.join(format!("{}.test.json", target.name.clone()));
@@ -136,19 +189,97 @@ fn main() -> Result<()> {
             )
             .with_context(|| format!("failed to deserialize compiled tests file: {file_path}"))?;
 
-            let config = TestRunConfig {
-                filter: args.filter.clone(),
-                include_ignored: args.include_ignored,
-                ignored: args.ignored,
-            };
+            let config = test_config(
+                args.coverage
+                    .as_ref()
+                    .map(|dir| dir.join(format!("{}.lcov", target.name))),
+            );
             let runner = CompiledTestRunner::new(test_compilation, config);
-            runner.run(&service_config, &str_into_layout(&args.layout))?;
-            println!();
+            let summary = runner.run(&service_config, &str_into_layout(&args.layout))?;
+            if !quiet {
+                println!();
+            }
+            suites.push(TestSuite {
+                name: target.name.clone(),
+                summary,
+            });
+        }
+
+        if let Some(cases_path) = config.test_cases(args.cases.clone()) {
+            let cases = TestCases::from_file(&cases_path)?;
+
+            // The cases run the package itself, not its test build
+            scarb_build(
+                &[],
+                PackagesFilter::generate_for::<Metadata>(std::iter::once(&package)),
+                quiet,
+            )?;
+            let sierra_path = target_dir.join(format!("{}.sierra.json", package.name));
+            let sierra_program = serde_json::from_str::<VersionedProgram>(
+                &fs::read_to_string(&sierra_path)
+                    .with_context(|| format!("failed to read file: {sierra_path}"))?,
+            )?
+            .into_v1()
+            .context("failed to load Sierra program")?
+            .program;
+
+            let schema = parse_schema_file(&config.cairo_schema())
+                .map_err(|e| anyhow::anyhow!("failed to parse cairo schema: {e}"))?
+                .for_function(&cases.function);
+
+            if !quiet {
+                println!("running cases of {} ...", cases_path.display());
+            }
+            let mut runner = CasesRunner::new(cases, sierra_program, schema, test_config(None));
+            if args.snapshot || args.update_snapshots {
+                runner = runner
+                    .with_snapshots(Snapshots::new(config.snapshots(), args.update_snapshots));
+            }
+            let summary = runner.run(&service_config, &str_into_layout(&args.layout))?;
+            if !quiet {
+                println!();
+            }
+            suites.push(TestSuite {
+                name: format!("{}_cases", package.name),
+                summary,
+            });
         }
     }
 
+    let report = match args.format {
+        ReportFormat::Text => None,
+        ReportFormat::Json => Some(to_json(&suites)?),
+        ReportFormat::Junit => Some(to_junit(&suites)),
+    };
+    match (report, &args.output) {
+        (Some(report), Some(output)) => fs::write(output, report)
+            .with_context(|| format!("failed to write test results: {}", output.display()))?,
+        (Some(report), None) => println!("{report}"),
+        (None, _) => {}
+    }
+
+    let failed: usize = suites.iter().map(|suite| suite.summary.failed.len()).sum();
+    if failed > 0 {
+        bail!("{failed} tests failed");
+    }
     Ok(())
 }
+/// Runs `scarb build` on the packages, its output going to stderr when `quiet`.
+fn scarb_build(args: &[&str], filter: PackagesFilter, quiet: bool) -> Result<()> {
+    let scarb = env::var("SCARB").unwrap_or_else(|_| "scarb".to_string());
+    let mut command = Command::new(scarb);
+    command
+        .arg("build")
+        .args(args)
+        .env("SCARB_PACKAGES_FILTER", filter.to_env());
+    if quiet {
+        command.stdout(io::stderr());
+    }
+    let status = command.status().context("failed to run `scarb build`")?;
+    ensure!(status.success(), "`scarb build` failed");
+    Ok(())
+}
+
 fn find_testable_targets(package: &PackageMetadata) -> Vec<&TargetMetadata> {
     package
         .targets
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use cairo_oracle_hint_processor::bundle::{public_output, PROOF_FILE};
+use clap::Parser;
+use scarb_agent_lib::prover::{
+    check_public_input, decode_public_output, find_binary, open_bundle, read_json, run_command,
+    VERIFIER_BIN,
+};
+use serde_json::{json, Value};
+
+/// Verify the proof of a run bundle with a local Stone CPU verifier.
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Bundle the proof was generated from with `scarb agent-prove`.
+    bundle: PathBuf,
+
+    /// Proof to verify, proof.json in the bundle if not set.
+    #[arg(long)]
+    proof: Option<PathBuf>,
+
+    /// Verifier binary, looked up in PATH if not set.
+    #[arg(long)]
+    verifier: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let result = match verify() {
+        Ok(data) => json!({
+            "status": "success",
+            "data": data
+        }),
+        Err(err) => json!({
+            "status": "error",
+            "message": format!("{err:#}")
+        }),
+    };
+
+    println!("{}", serde_json::to_string(&result)?);
+
+    std::process::exit(if result["status"] == "error" { 1 } else { 0 });
+}
+
+fn verify() -> Result<Value> {
+    let args: Args = Args::parse();
+    let verifier = find_binary(args.verifier, VERIFIER_BIN)?;
+    let (bundle, manifest, bundle_public_input) = open_bundle(&args.bundle)?;
+
+    let proof_file = args.proof.unwrap_or_else(|| bundle.path(PROOF_FILE));
+    let proof = read_json(&proof_file)?;
+    let public_input = &proof["public_input"];
+    check_public_input(&manifest, public_input)?;
+    if public_output(public_input)? != public_output(&bundle_public_input)? {
+        bail!("the public output of the proof differs from the one of the bundle");
+    }
+
+    run_command(Command::new(&verifier).arg("--in_file").arg(&proof_file))?;
+
+    Ok(json!({
+        "verified": true,
+        "proof": proof_file,
+        "layout": manifest.layout,
+        "output": decode_public_output(&bundle, &manifest, public_input)?,
+    }))
+}